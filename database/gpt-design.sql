@@ -13,7 +13,7 @@ CREATE TABLE users
 insert into users
     (username, password, full_name, role, created_at)
 values
-    ('distributor', '$2b$12$VsrfBeuszFplm3HX4QgMWOg/KMsIhZgPLCjej2W3DI.YHz9Gq9Zjq', 'Thant Zin', 'Disributor', now()),
+    ('distributor', '$2b$12$VsrfBeuszFplm3HX4QgMWOg/KMsIhZgPLCjej2W3DI.YHz9Gq9Zjq', 'Thant Zin', 'Distributor', now()),
     ('admin', '$2b$12$VsrfBeuszFplm3HX4QgMWOg/KMsIhZgPLCjej2W3DI.YHz9Gq9Zjq', 'Thant Zin Lin', 'Admin', now
 ());
 
@@ -353,3 +353,524 @@ CREATE TABLE product_suppliers
     supplier_id INT REFERENCES suppliers(supplier_id),
     PRIMARY KEY (product_id, supplier_id)
 );
+
+
+-- Login sessions. Each refresh token belongs to one session; `generation` is
+-- bumped on every refresh so that older access tokens stop working.
+CREATE TABLE user_sessions
+(
+    session_id SERIAL PRIMARY KEY,
+    user_id INT REFERENCES users(user_id),
+    refresh_token_hash VARCHAR(64) NOT NULL,
+    generation INT NOT NULL DEFAULT 1,
+    user_agent TEXT DEFAULT '',
+    expires_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    revoked_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+-- Roles and what each of them may do. `users.role` holds a role_name; handlers
+-- check (resource, action) pairs through role_permissions instead of
+-- comparing role names.
+CREATE TABLE roles
+(
+    role_id SERIAL PRIMARY KEY,
+    role_name VARCHAR(100) NOT NULL UNIQUE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMP DEFAULT NULL
+);
+
+CREATE TABLE permissions
+(
+    permission_id SERIAL PRIMARY KEY,
+    resource VARCHAR(100) NOT NULL,
+    action VARCHAR(100) NOT NULL,
+    description TEXT,
+    UNIQUE (resource, action)
+);
+
+CREATE TABLE role_permissions
+(
+    role_id INT REFERENCES roles(role_id),
+    permission_id INT REFERENCES permissions(permission_id),
+    PRIMARY KEY (role_id, permission_id)
+);
+
+INSERT INTO roles
+    (role_name)
+VALUES
+    ('Admin'),
+    ('Manager'),
+    ('Distributor');
+
+INSERT INTO permissions
+    (resource, action)
+SELECT r.resource, a.action
+FROM (VALUES ('users'), ('roles'), ('brands'), ('categories'), ('products'), ('prices'), ('discounts'), ('shops'), ('townships'), ('wards')) r(resource)
+    CROSS JOIN (VALUES ('read'), ('create'), ('update'), ('delete')) a(action);
+
+INSERT INTO permissions
+    (resource, action, description)
+VALUES
+    ('orders', 'read', NULL),
+    ('orders', 'create', NULL),
+    ('orders', 'read_all', 'See orders placed by every user'),
+    ('shops', 'read_all', 'See shops outside of the assigned wards');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'Admin'
+    OR (r.role_name = 'Manager' AND p.resource NOT IN ('users', 'roles'))
+    OR (r.role_name = 'Distributor' AND (
+        (p.resource IN ('brands', 'categories', 'products', 'prices', 'shops', 'townships', 'wards') AND p.action = 'read')
+        OR (p.resource = 'orders' AND p.action IN ('read', 'create'))
+    ));
+
+-- Earlier seeds misspelled the distributor role.
+UPDATE users SET role = 'Distributor' WHERE role = 'Disributor';
+
+-- Failed login counters, keyed by `user:<username>` or `ip:<address>`.
+CREATE TABLE login_throttles
+(
+    throttle_key VARCHAR(255) PRIMARY KEY,
+    failed_attempts INT NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    locked_until TIMESTAMP DEFAULT NULL
+);
+
+-- Set when an admin resets a password; the user has to pick a new one before
+-- they can use the API again.
+ALTER TABLE users ADD COLUMN must_change_password BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Keys for machine integrations (ERP, BI). Only a SHA-256 hash of the key is
+-- stored; `key_prefix` is the public part used to look it up.
+CREATE TABLE api_keys
+(
+    api_key_id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    key_prefix VARCHAR(16) NOT NULL UNIQUE,
+    key_hash VARCHAR(64) NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    rate_limit INT NOT NULL DEFAULT 60,
+    -- requests per minute
+    created_by INT REFERENCES users(user_id),
+    last_used_at TIMESTAMP DEFAULT NULL,
+    revoked_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every request made with an API key; also used for rate limiting.
+CREATE TABLE api_key_requests
+(
+    request_id BIGSERIAL PRIMARY KEY,
+    api_key_id INT REFERENCES api_keys(api_key_id),
+    method VARCHAR(10) NOT NULL,
+    path TEXT NOT NULL,
+    allowed BOOLEAN NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX api_key_requests_key_created_idx ON api_key_requests (api_key_id, created_at);
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('api_keys', 'read'),
+    ('api_keys', 'create'),
+    ('api_keys', 'delete');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'Admin' AND p.resource = 'api_keys';
+
+-- TOTP secret of a user. The secret is pending until `enabled_at` is set by
+-- confirming a code; `last_used_step` stops a code from being used twice.
+CREATE TABLE user_mfa
+(
+    user_id INT PRIMARY KEY REFERENCES users(user_id),
+    totp_secret VARCHAR(64) NOT NULL,
+    enabled_at TIMESTAMP DEFAULT NULL,
+    last_used_step BIGINT DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One-time recovery codes, stored as sha256 hashes.
+CREATE TABLE user_recovery_codes
+(
+    code_id SERIAL PRIMARY KEY,
+    user_id INT REFERENCES users(user_id),
+    code_hash VARCHAR(64) NOT NULL,
+    used_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX user_recovery_codes_user_idx ON user_recovery_codes (user_id);
+
+-- Logins waiting for their second factor.
+CREATE TABLE mfa_challenges
+(
+    challenge_id SERIAL PRIMARY KEY,
+    user_id INT REFERENCES users(user_id),
+    token_hash VARCHAR(64) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- FCM registration tokens of the apps users are logged in on.
+CREATE TABLE user_devices
+(
+    device_id SERIAL PRIMARY KEY,
+    user_id INT REFERENCES users(user_id),
+    token TEXT NOT NULL UNIQUE,
+    platform VARCHAR(20) NOT NULL DEFAULT '',
+    -- e.g., 'android', 'ios'
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    last_seen_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Set once distributors have been pushed a started discount.
+ALTER TABLE discounts ADD COLUMN notified_at TIMESTAMP DEFAULT NULL;
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('orders', 'update'),
+    ('notifications', 'create');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name IN ('Admin', 'Manager')
+    AND ((p.resource = 'orders' AND p.action = 'update') OR p.resource = 'notifications');
+
+-- Ward coverage over time. `effective_to` is the last day covered (NULL for
+-- open ended). Rows with `delegated_from_user_id` are temporary cover for
+-- that user, e.g. while they are on leave.
+CREATE TABLE ward_assignments
+(
+    assignment_id SERIAL PRIMARY KEY,
+    ward_id INT REFERENCES wards(ward_id),
+    user_id INT REFERENCES users(user_id),
+    effective_from DATE NOT NULL DEFAULT CURRENT_DATE,
+    effective_to DATE DEFAULT NULL,
+    delegated_from_user_id INT REFERENCES users(user_id) NULL,
+    created_by INT REFERENCES users(user_id) NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX ward_assignments_ward_idx ON ward_assignments (ward_id, effective_from);
+CREATE INDEX ward_assignments_user_idx ON ward_assignments (user_id, effective_from);
+
+INSERT INTO ward_assignments
+    (user_id, ward_id, effective_from)
+SELECT user_id, ward_id, created_at::date
+FROM user_wards
+WHERE deleted_at IS NULL;
+
+-- `user_wards` used to be rewritten on every user update, losing history. It
+-- is now the set of assignments in effect today.
+DROP TABLE user_wards;
+CREATE VIEW user_wards AS
+SELECT DISTINCT user_id, ward_id
+FROM ward_assignments
+WHERE effective_from <= CURRENT_DATE AND (effective_to IS NULL OR effective_to >= CURRENT_DATE);
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('territories', 'read'),
+    ('territories', 'update');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name IN ('Admin', 'Manager') AND p.resource = 'territories';
+
+-- Reporting line: managers see and approve the orders and shops of everyone
+-- below them.
+ALTER TABLE users ADD COLUMN manager_id INT REFERENCES users(user_id) NULL;
+CREATE INDEX users_manager_idx ON users (manager_id);
+
+ALTER TABLE orders ADD COLUMN approved_by INT REFERENCES users(user_id) NULL;
+ALTER TABLE orders ADD COLUMN approved_at TIMESTAMP DEFAULT NULL;
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('orders', 'approve');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name IN ('Admin', 'Manager') AND p.resource = 'orders' AND p.action = 'approve';
+
+-- Managers are limited to their team from now on.
+DELETE FROM role_permissions rp
+USING roles r, permissions p
+WHERE rp.role_id = r.role_id AND rp.permission_id = p.permission_id
+    AND r.role_name = 'Manager' AND p.action = 'read_all';
+
+-- One row per create, update or delete, written in the same transaction as the
+-- change. `before` and `after` are JSON snapshots of the row (NULL on create
+-- and hard delete respectively); secrets are left out.
+CREATE TABLE audit_events
+(
+    event_id BIGSERIAL PRIMARY KEY,
+    actor_id INT NOT NULL REFERENCES users(user_id),
+    api_key_id INT REFERENCES api_keys(api_key_id) NULL,
+    request_id VARCHAR(64) NOT NULL,
+    action VARCHAR(20) NOT NULL,
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id INT NOT NULL,
+    before JSONB DEFAULT NULL,
+    after JSONB DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX audit_events_entity_idx ON audit_events (entity_type, entity_id);
+CREATE INDEX audit_events_actor_created_idx ON audit_events (actor_id, created_at);
+CREATE INDEX audit_events_request_idx ON audit_events (request_id);
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('audit', 'read');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'Admin' AND p.resource = 'audit';
+
+-- Trash: soft-deleted records can be listed and restored, and purged for good
+-- once they are older than TRASH_RETENTION_DAYS.
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('trash', 'read'),
+    ('trash', 'restore'),
+    ('trash', 'purge');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE r.role_name = 'Admin' AND p.resource = 'trash';
+
+-- Regions (states/divisions) group townships; a township may have none yet.
+CREATE TABLE regions
+(
+    region_id SERIAL PRIMARY KEY,
+    region_name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMP DEFAULT null
+);
+
+ALTER TABLE townships ADD COLUMN region_id INT REFERENCES regions(region_id) NULL;
+CREATE INDEX townships_region_idx ON townships (region_id);
+CREATE INDEX wards_township_idx ON wards (township_id);
+
+INSERT INTO permissions
+    (resource, action)
+SELECT 'regions', a.action
+FROM (VALUES ('read'), ('create'), ('update'), ('delete')) a(action);
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE p.resource = 'regions'
+    AND (r.role_name IN ('Admin', 'Manager') OR (r.role_name = 'Distributor' AND p.action = 'read'));
+
+-- Ward boundaries as GeoJSON Polygon/MultiPolygon geometries, used to check
+-- that a shop's coordinates lie in its ward.
+ALTER TABLE wards ADD COLUMN boundary JSONB DEFAULT NULL;
+
+-- Narrows the nearby-shop search to a bounding box before distances are taken.
+CREATE INDEX shops_location_idx ON shops (latitude, longitude) WHERE deleted_at IS NULL;
+
+-- Days without shop visits, left out of the daily route plan.
+CREATE TABLE holidays
+(
+    holiday_id SERIAL PRIMARY KEY,
+    holiday_date DATE NOT NULL UNIQUE,
+    holiday_name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('holidays', 'read'),
+    ('holidays', 'create'),
+    ('holidays', 'delete');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE p.resource = 'holidays'
+    AND (r.role_name IN ('Admin', 'Manager') OR (r.role_name = 'Distributor' AND p.action = 'read'));
+
+-- A distributor's stop at a shop, checked in and out with the device's
+-- position. `flagged` marks check-ins beyond VISIT_RADIUS_M of the shop, or at
+-- a shop without coordinates.
+CREATE TABLE shop_visits
+(
+    visit_id SERIAL PRIMARY KEY,
+    shop_id INT NOT NULL REFERENCES shops(shop_id),
+    user_id INT NOT NULL REFERENCES users(user_id),
+    check_in_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    check_in_latitude DECIMAL(9,6) NOT NULL,
+    check_in_longitude DECIMAL(9,6) NOT NULL,
+    check_in_distance_m DOUBLE PRECISION DEFAULT NULL,
+    flagged BOOLEAN NOT NULL DEFAULT FALSE,
+    check_out_at TIMESTAMP DEFAULT NULL,
+    check_out_latitude DECIMAL(9,6) DEFAULT NULL,
+    check_out_longitude DECIMAL(9,6) DEFAULT NULL,
+    order_id INT REFERENCES orders(order_id) NULL,
+    -- One of NO_ORDER_REASONS, when the visit ended without an order.
+    no_order_reason VARCHAR(50) DEFAULT NULL,
+    note TEXT DEFAULT NULL
+);
+CREATE INDEX shop_visits_user_check_in_idx ON shop_visits (user_id, check_in_at);
+CREATE INDEX shop_visits_shop_idx ON shop_visits (shop_id);
+-- A user has at most one visit open at a time.
+CREATE UNIQUE INDEX shop_visits_open_idx ON shop_visits (user_id) WHERE check_out_at IS NULL;
+
+INSERT INTO permissions
+    (resource, action, description)
+VALUES
+    ('visits', 'read', NULL),
+    ('visits', 'create', NULL),
+    ('visits', 'read_all', 'See shop visits of every user');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE p.resource = 'visits'
+    AND (r.role_name = 'Admin' OR (r.role_name IN ('Manager', 'Distributor') AND p.action IN ('read', 'create')));
+
+-- Credit: a shop owes the total of its orders that were not cancelled, less
+-- what it paid. `credit_limit` caps that balance; NULL means no limit.
+ALTER TABLE shops ADD COLUMN credit_limit DECIMAL(12,2) DEFAULT NULL;
+
+CREATE TABLE payments
+(
+    payment_id SERIAL PRIMARY KEY,
+    shop_id INT NOT NULL REFERENCES shops(shop_id),
+    -- Who collected it.
+    user_id INT NOT NULL REFERENCES users(user_id),
+    amount DECIMAL(12,2) NOT NULL CHECK (amount > 0),
+    paid_at DATE NOT NULL DEFAULT CURRENT_DATE,
+    reference VARCHAR(255) DEFAULT NULL,
+    note TEXT DEFAULT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMP DEFAULT NULL
+);
+CREATE INDEX payments_shop_idx ON payments (shop_id, paid_at);
+CREATE INDEX orders_shop_date_idx ON orders (shop_id, order_date);
+
+INSERT INTO permissions
+    (resource, action)
+VALUES
+    ('payments', 'read'),
+    ('payments', 'create'),
+    ('payments', 'delete'),
+    ('receivables', 'read'),
+    ('receivables', 'update');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE p.resource IN ('payments', 'receivables')
+    AND (r.role_name IN ('Admin', 'Manager')
+        OR (r.role_name = 'Distributor' AND p.action IN ('read', 'create')));
+
+-- Shop contacts and classification. `channel` is one of SHOP_CHANNELS and
+-- `size_class` one of SHOP_SIZE_CLASSES; `tags` are free labels.
+ALTER TABLE shops
+    ADD COLUMN owner_name VARCHAR(255) DEFAULT NULL,
+    ADD COLUMN phone_numbers TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN channel VARCHAR(20) DEFAULT NULL,
+    ADD COLUMN size_class VARCHAR(20) DEFAULT NULL,
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+CREATE INDEX shops_channel_idx ON shops (channel);
+CREATE INDEX shops_tags_idx ON shops USING GIN (tags);
+
+-- A price or discount with a channel only applies to shops of that channel;
+-- NULL applies to every shop.
+ALTER TABLE product_prices ADD COLUMN channel VARCHAR(20) DEFAULT NULL;
+ALTER TABLE discounts ADD COLUMN channel VARCHAR(20) DEFAULT NULL;
+
+-- Shops found in the field, waiting for an admin to approve or reject them.
+-- Only an approved proposal becomes a shop (`shop_id`), so pending ones stay
+-- out of shop lists and routes.
+CREATE TABLE shop_proposals
+(
+    proposal_id SERIAL PRIMARY KEY,
+    -- Who proposed it.
+    user_id INT NOT NULL REFERENCES users(user_id),
+    -- pending, approved or rejected.
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    shop_name VARCHAR(255) NOT NULL,
+    address TEXT NOT NULL,
+    latitude DECIMAL(9,6) NOT NULL,
+    longitude DECIMAL(9,6) NOT NULL,
+    image_url TEXT NOT NULL,
+    ward_id INT NOT NULL REFERENCES wards(ward_id),
+    weekday_ids INT[] NOT NULL DEFAULT '{}',
+    owner_name VARCHAR(255) DEFAULT NULL,
+    phone_numbers TEXT[] NOT NULL DEFAULT '{}',
+    channel VARCHAR(20) DEFAULT NULL,
+    size_class VARCHAR(20) DEFAULT NULL,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    note TEXT DEFAULT NULL,
+    reviewed_by INT REFERENCES users(user_id) NULL,
+    reviewed_at TIMESTAMP DEFAULT NULL,
+    review_note TEXT DEFAULT NULL,
+    shop_id INT REFERENCES shops(shop_id) NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX shop_proposals_status_idx ON shop_proposals (status, created_at);
+CREATE INDEX shop_proposals_user_idx ON shop_proposals (user_id);
+
+INSERT INTO permissions
+    (resource, action, description)
+VALUES
+    ('shop_proposals', 'read', NULL),
+    ('shop_proposals', 'create', NULL),
+    ('shop_proposals', 'review', 'Edit, approve and reject shop proposals'),
+    ('shop_proposals', 'read_all', 'See shop proposals of every user');
+
+INSERT INTO role_permissions
+    (role_id, permission_id)
+SELECT r.role_id, p.permission_id
+FROM roles r, permissions p
+WHERE p.resource = 'shop_proposals'
+    AND (r.role_name = 'Admin' OR (r.role_name IN ('Manager', 'Distributor') AND p.action IN ('read', 'create')));
+
+-- Assignments ended by deleting their user carry the user's deletion time,
+-- so restoring the user can reopen them.
+ALTER TABLE ward_assignments ADD COLUMN deleted_at TIMESTAMP DEFAULT NULL;
+
+-- Revoking a user's sessions also revokes their older tokens that carry no
+-- session id.
+ALTER TABLE users ADD COLUMN tokens_revoked_at TIMESTAMP DEFAULT NULL;
+
+-- Role names only have to be unique among live roles, so a deleted role's
+-- name can be used again.
+ALTER TABLE roles DROP CONSTRAINT roles_role_name_key;
+CREATE UNIQUE INDEX roles_role_name_key ON roles (role_name) WHERE deleted_at IS NULL;
+
+-- Usernames are unique among live users, regardless of case.
+CREATE UNIQUE INDEX users_username_key ON users (lower(username)) WHERE deleted_at IS NULL;
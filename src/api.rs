@@ -1,25 +1,74 @@
+mod api_key;
+mod audit;
 mod auth;
 mod brand;
 mod category;
+mod device;
+mod holiday;
 mod order;
+mod payment;
 mod shop;
+mod shop_proposal;
+mod shop_visit;
 mod township;
 mod user;
 mod ward;
+mod ward_assignment;
 mod discount;
+mod mfa;
+mod notification;
 mod product;
 mod product_price;
+mod receivable;
+mod region;
+mod role;
+mod route;
+mod trash;
 
 use actix_web::web;
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(auth::login);
+    cfg.service(auth::refresh);
+    cfg.service(auth::logout);
+    cfg.service(auth::change_password);
     cfg.service(auth::hash_password);
+    cfg.service(auth::verify_token);
+    cfg.service(auth::jwks);
+    cfg.service(mfa::verify);
+    cfg.service(mfa::enroll);
+    cfg.service(mfa::confirm);
+    cfg.service(mfa::disable);
     cfg.service(user::add_user);
+    cfg.service(user::import_users);
     cfg.service(user::get_users);
     cfg.service(user::get_user_by_id);
     cfg.service(user::update_user);
     cfg.service(user::delete_user);
+    cfg.service(user::revoke_user_sessions);
+    cfg.service(user::unlock_user);
+    cfg.service(user::reset_password);
+    cfg.service(user::reset_mfa);
+    cfg.service(user::set_manager);
+    cfg.service(user::get_team);
+    cfg.service(role::get_permissions);
+    cfg.service(role::get_roles);
+    cfg.service(role::add_role);
+    cfg.service(role::get_role_by_id);
+    cfg.service(role::update_role);
+    cfg.service(role::delete_role);
+    cfg.service(api_key::get_api_keys);
+    cfg.service(api_key::add_api_key);
+    cfg.service(api_key::revoke_api_key);
+    cfg.service(api_key::get_api_key_requests);
+    cfg.service(audit::get_audit_events);
+    cfg.service(trash::get_trash);
+    cfg.service(trash::purge);
+    cfg.service(trash::restore);
+    cfg.service(device::get_devices);
+    cfg.service(device::add_device);
+    cfg.service(device::delete_device);
+    cfg.service(notification::broadcast);
     cfg.service(category::add_category);
     cfg.service(category::get_categories);
     cfg.service(category::get_category_by_id);
@@ -31,22 +80,61 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(brand::update_brand);
     cfg.service(brand::delete_brand);
     cfg.service(shop::get_shops);
+    cfg.service(shop::get_nearby_shops);
+    cfg.service(shop::get_shop_features);
     cfg.service(shop::get_shop_by_id);
     cfg.service(shop::add_shop);
     cfg.service(shop::update_shop);
     cfg.service(shop::delete_shop);
+    cfg.service(shop::get_misplaced_shops);
+    cfg.service(shop_proposal::get_proposals);
+    cfg.service(shop_proposal::add_proposal);
+    cfg.service(shop_proposal::get_proposal_by_id);
+    cfg.service(shop_proposal::update_proposal);
+    cfg.service(shop_proposal::approve_proposal);
+    cfg.service(shop_proposal::reject_proposal);
+    cfg.service(shop_visit::get_visits);
+    cfg.service(shop_visit::check_in);
+    cfg.service(shop_visit::check_out);
+    cfg.service(region::add_region);
+    cfg.service(region::get_regions);
+    cfg.service(region::get_region_by_id);
+    cfg.service(region::update_region);
+    cfg.service(region::delete_region);
+    cfg.service(township::get_region_townships);
     cfg.service(township::add_township);
     cfg.service(township::get_townships);
     cfg.service(township::get_township_by_id);
     cfg.service(township::update_township);
     cfg.service(township::delete_township);
+    cfg.service(ward::get_township_wards);
     cfg.service(ward::add_ward);
     cfg.service(ward::get_wards);
+    cfg.service(ward::locate_ward);
     cfg.service(ward::get_ward_by_id);
     cfg.service(ward::update_ward);
     cfg.service(ward::delete_ward);
+    cfg.service(ward_assignment::get_ward_assignments);
+    cfg.service(ward_assignment::add_ward_assignment);
+    cfg.service(ward_assignment::end_ward_assignment);
+    cfg.service(ward_assignment::add_delegation);
+    cfg.service(ward_assignment::get_territory_report);
+    cfg.service(holiday::get_holidays);
+    cfg.service(holiday::add_holiday);
+    cfg.service(holiday::delete_holiday);
+    cfg.service(route::get_my_route);
     cfg.service(order::add_order);
     cfg.service(order::get_orders);
+    cfg.service(order::get_order_features);
+    cfg.service(order::update_order_status);
+    cfg.service(order::approve_order);
+    cfg.service(payment::get_payments);
+    cfg.service(payment::add_payment);
+    cfg.service(payment::delete_payment);
+    cfg.service(receivable::get_shop_credit);
+    cfg.service(receivable::set_credit_limit);
+    cfg.service(receivable::get_ledger);
+    cfg.service(receivable::get_aging);
     cfg.service(discount::add_discount);
     cfg.service(discount::get_discount_by_id);
     cfg.service(discount::get_discounts);
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::api_key::{self, ApiKeyRequest},
+    utils::{
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        auth::Authorized,
+        permission::{is_known_scope, ApiKeyCreate, ApiKeyDelete, ApiKeyRead},
+    },
+};
+
+#[get("/api/api-keys")]
+pub async fn get_api_keys(
+    _auth: Authorized<ApiKeyRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match api_key::get_api_keys(&client).await {
+        Ok(keys) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(keys),
+        }),
+        Err(err) => {
+            println!("Error retrieving api keys: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all api keys from database"),
+            })
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    pub api_key_id: i32,
+    // Only ever shown in this response.
+    pub api_key: String,
+}
+
+#[post("/api/api-keys")]
+pub async fn add_api_key(
+    auth: Authorized<ApiKeyCreate>,
+    req: HttpRequest,
+    body: web::Json<ApiKeyRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Name must not be empty!"),
+        });
+    }
+    if body.scopes.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("At least one scope is required!"),
+        });
+    }
+    if let Some(scope) = body.scopes.iter().find(|s| !is_known_scope(s)) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Unknown scope: {}", scope),
+        });
+    }
+    if body.rate_limit.is_some_and(|limit| limit <= 0) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Rate limit must be greater than 0!"),
+        });
+    }
+
+    let (prefix, key) = api_key::generate_api_key();
+    match api_key::add_api_key(
+        &body,
+        &prefix,
+        &api_key::hash_api_key(&key),
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(api_key_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("API key created successfully. Store it now, it will not be shown again."),
+            data: Some(CreatedApiKey {
+                api_key_id,
+                api_key: key,
+            }),
+        }),
+        Err(e) => {
+            eprintln!("API key adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding api key!"),
+            })
+        }
+    }
+}
+
+#[delete("/api/api-keys/{api_key_id}")]
+pub async fn revoke_api_key(
+    auth: Authorized<ApiKeyDelete>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let api_key_id = path.into_inner();
+    match api_key::get_api_key_by_id(api_key_id, &client).await {
+        Some(_) => match api_key::revoke_api_key(api_key_id, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                code: 204,
+                message: String::from("API key revoked successfully"),
+            }),
+            Err(e) => {
+                eprintln!("API key revoking error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error revoking api key!"),
+                })
+            }
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("API key not found!"),
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct GetApiKeyRequestsQuery {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[get("/api/api-keys/{api_key_id}/requests")]
+pub async fn get_api_key_requests(
+    _auth: Authorized<ApiKeyRead>,
+    path: web::Path<i32>,
+    query: web::Query<GetApiKeyRequestsQuery>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match api_key::get_api_key_requests(path.into_inner(), query.page, query.per_page, &client)
+        .await
+    {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving api key requests: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read api key requests from database"),
+            })
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::audit::{self, AuditListQuery},
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, PaginationResponse},
+        permission::AuditRead,
+    },
+};
+
+// Who changed what, newest first. `request_id` ties the events of one request
+// together and matches the `X-Request-Id` the client sent.
+#[get("/api/audit")]
+pub async fn get_audit_events(
+    _auth: Authorized<AuditRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<AuditListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match audit::get_audit_events(&query, &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving audit events: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read audit events from database"),
+            })
+        }
+    }
+}
@@ -1,10 +1,18 @@
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::models::user::get_user;
+use crate::models::{login_throttle, mfa, session};
+use crate::models::user::{
+    get_user_by_id, get_user_credentials, get_user_credentials_by_id, get_user_ward_ids,
+    set_password, User, UserCredentials,
+};
+use crate::utils::auth::{Authorized, PendingAuthUser, ServiceAuth};
 use crate::utils::common_struct::{BaseResponse, DataResponse};
-use crate::utils::jwt::{self, verify_token_and_get_sub};
-use actix_web::{post, web, HttpResponse, Responder};
+use crate::utils::jwt::{self, verify_token_and_get_claims};
+use crate::utils::keyring::keyring;
+use crate::utils::password::validate_password;
+use crate::utils::permission::UserCreate;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
 use bcrypt::{hash, verify, DEFAULT_COST};
 // use jsonwebtoken::{DecodingKey, decode, Validation, Algorithm};
 use serde::{Deserialize, Serialize};
@@ -22,50 +30,480 @@ pub struct LoginResponse {
     pub code: u16,
     pub message: String,
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: usize,
     pub name: String,
     pub role: String,
+    pub must_change_password: bool,
+    pub must_enroll_mfa: bool,
+}
+
+// Who an access token is for, including what the user still has to do
+// before the token lets them use the rest of the API.
+pub(crate) struct TokenSubject {
+    pub user_id: i32,
+    pub role: String,
+    pub username: String,
+    pub name: String,
+    pub must_change_password: bool,
+    pub must_enroll_mfa: bool,
+}
+
+impl TokenSubject {
+    async fn from_user(user: User, client: &Client) -> Result<Self, tokio_postgres::Error> {
+        TokenSubject {
+            user_id: user.userid,
+            role: user.role,
+            username: user.username,
+            name: user.fullname,
+            must_change_password: user.must_change_password,
+            must_enroll_mfa: false,
+        }
+        .with_mfa_state(client)
+        .await
+    }
+
+    pub(crate) async fn from_credentials(
+        user: UserCredentials,
+        client: &Client,
+    ) -> Result<Self, tokio_postgres::Error> {
+        TokenSubject {
+            user_id: user.userid,
+            role: user.role,
+            username: user.username,
+            name: user.fullname,
+            must_change_password: user.must_change_password,
+            must_enroll_mfa: false,
+        }
+        .with_mfa_state(client)
+        .await
+    }
+
+    async fn with_mfa_state(mut self, client: &Client) -> Result<Self, tokio_postgres::Error> {
+        self.must_enroll_mfa = mfa::is_mfa_required(&self.role)
+            && !mfa::is_mfa_enabled(self.user_id, client).await?;
+        Ok(self)
+    }
+}
+
+// Creates a new session for the user and returns the access token and the
+// refresh token that belongs to it.
+async fn start_session(
+    subject: &TokenSubject,
+    user_agent: &str,
+    client: &Client,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let secret = session::generate_refresh_secret();
+    let session_id = session::add_session(
+        subject.user_id,
+        &session::hash_refresh_secret(&secret),
+        user_agent,
+        jwt::refresh_token_ttl(),
+        client,
+    )
+    .await?;
+    let ward_ids = get_user_ward_ids(subject.user_id, client).await?;
+    let token = issue_access_token(subject, ward_ids, session_id, 1)?;
+    Ok((token, format!("{session_id}.{secret}")))
+}
+
+fn issue_access_token(
+    subject: &TokenSubject,
+    ward_ids: Vec<i32>,
+    session_id: i32,
+    generation: i32,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as usize;
+    let mut claims = jwt::Claims::new(
+        subject.user_id,
+        &subject.role,
+        &subject.username,
+        ward_ids,
+        now,
+        now + jwt::access_token_ttl(),
+    );
+    claims.sid = Some(session_id);
+    claims.gen = Some(generation);
+    claims.pwd_change = subject.must_change_password;
+    claims.mfa_setup = subject.must_enroll_mfa;
+    jwt::sign_token(&claims)
+}
+
+// Starts a session and answers with its tokens.
+pub(crate) async fn session_response(
+    subject: TokenSubject,
+    message: &str,
+    req: &HttpRequest,
+    client: &Client,
+) -> HttpResponse {
+    match start_session(&subject, &get_user_agent(req), client).await {
+        Ok((token, refresh_token)) => HttpResponse::Ok().json(LoginResponse {
+            code: 200,
+            message: String::from(message),
+            token,
+            refresh_token,
+            expires_in: jwt::access_token_ttl(),
+            name: subject.name,
+            role: subject.role,
+            must_change_password: subject.must_change_password,
+            must_enroll_mfa: subject.must_enroll_mfa,
+        }),
+        Err(err) => {
+            println!("Error creating session: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error creating session!"),
+            })
+        }
+    }
+}
+
+pub(crate) fn get_user_agent(req: &HttpRequest) -> String {
+    req.headers()
+        .get("User-Agent")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
+
+fn get_client_ip(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+// Checked against when the username does not exist, so that unknown and
+// known usernames take the same time to reject.
+const DUMMY_PASSWORD_HASH: &str = "$2b$12$VsrfBeuszFplm3HX4QgMWOg/KMsIhZgPLCjej2W3DI.YHz9Gq9Zjq";
+
+fn invalid_credentials() -> HttpResponse {
+    HttpResponse::Unauthorized().json(BaseResponse {
+        code: 401,
+        message: String::from("Invalid username or password!"),
+    })
 }
 
 #[post("/api/auth/login")]
 pub async fn login(
+    req: HttpRequest,
     data: web::Data<Arc<Mutex<Client>>>,
     credentials: web::Json<LoginRequest>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
+    let throttle_keys = [
+        login_throttle::username_key(&credentials.username),
+        login_throttle::ip_key(&get_client_ip(&req)),
+    ];
+
+    match login_throttle::get_lockout_remaining(&throttle_keys, &client).await {
+        Ok(Some(remaining)) => {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", remaining.to_string()))
+                .json(BaseResponse {
+                    code: 429,
+                    message: format!(
+                        "Too many failed login attempts. Try again in {} seconds.",
+                        remaining
+                    ),
+                });
+        }
+        Ok(None) => {}
+        Err(err) => {
+            println!("Error checking login throttle: {:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
     // Fetch user from the database based on the username
-    let user = get_user(&credentials.username, &client).await;
-
-    match user {
-        Some(user) => {
-            if verify(&credentials.password, &user.password).unwrap() {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_secs() as usize;
-                let token = jwt::sign_token(&jwt::Claims {
-                    sub: format!("{},{},{}", &user.userid, &user.role, &user.username),
-                    exp: now + (3600 * 24),
-                })
-                .unwrap();
-
-                HttpResponse::Ok().json(LoginResponse {
-                    code: 200,
-                    message: String::from("Token generated successfully."),
-                    token: token,
-                    name: user.fullname,
-                    role: user.role,
-                })
-            } else {
-                HttpResponse::Unauthorized().json(BaseResponse {
-                    code: 401,
-                    message: String::from("Invalid password!"),
-                })
+    let user = get_user_credentials(&credentials.username, &client).await;
+    let password_hash = user
+        .as_ref()
+        .map_or(DUMMY_PASSWORD_HASH, |u| u.password_hash.as_str());
+    let password_matches = verify(&credentials.password, password_hash).unwrap_or(false);
+
+    let user = match user {
+        Some(user) if password_matches => user,
+        _ => {
+            for key in &throttle_keys {
+                if let Err(err) = login_throttle::record_failure(key, &mut client).await {
+                    println!("Error recording failed login: {:?}", err);
+                }
             }
+            return invalid_credentials();
+        }
+    };
+
+    // With a second factor the throttle is only cleared once that is verified
+    // too, so that a known password does not buy unlimited code guesses.
+    match mfa::is_mfa_enabled(user.userid, &client).await {
+        Ok(true) => return mfa_challenge_response(user.userid, &client).await,
+        Ok(false) => {}
+        Err(err) => {
+            println!("Error reading mfa state: {:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
+    // Only the username is cleared. The IP's count keeps running, otherwise
+    // logging into one account of one's own between guesses would reset it
+    // and allow trying passwords against every other account from that IP.
+    if let Err(err) = login_throttle::clear(&throttle_keys[0], &client).await {
+        println!("Error clearing login throttle: {:?}", err);
+    }
+
+    match TokenSubject::from_credentials(user, &client).await {
+        Ok(subject) => {
+            session_response(subject, "Token generated successfully.", &req, &client).await
+        }
+        Err(err) => {
+            println!("Error reading mfa state: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MfaChallengeResponse {
+    pub code: u16,
+    pub message: String,
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+// How long the second step of a login may take, in seconds.
+const MFA_CHALLENGE_TTL: i64 = 300;
+
+// The password was right but the user has a second factor. Instead of tokens
+// the caller gets a one-time `mfa_token` to send along with the code.
+async fn mfa_challenge_response(user_id: i32, client: &Client) -> HttpResponse {
+    let secret = session::generate_refresh_secret();
+    match mfa::add_challenge(
+        user_id,
+        &session::hash_refresh_secret(&secret),
+        MFA_CHALLENGE_TTL,
+        client,
+    )
+    .await
+    {
+        Ok(challenge_id) => HttpResponse::Ok().json(MfaChallengeResponse {
+            code: 200,
+            message: String::from("Second factor required."),
+            mfa_required: true,
+            mfa_token: format!("{challenge_id}.{secret}"),
+            expires_in: MFA_CHALLENGE_TTL,
+        }),
+        Err(err) => {
+            println!("Error creating mfa challenge: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
         }
-        None => HttpResponse::Unauthorized().json(BaseResponse {
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[post("/api/auth/refresh")]
+pub async fn refresh(
+    data: web::Data<Arc<Mutex<Client>>>,
+    body: web::Json<RefreshRequest>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let invalid = || {
+        HttpResponse::Unauthorized().json(BaseResponse {
             code: 401,
-            message: String::from("Invalid username!"),
+            message: String::from("Invalid refresh token"),
+        })
+    };
+
+    let (session_id, secret) = match session::parse_refresh_token(&body.refresh_token) {
+        Some(parts) => parts,
+        None => return invalid(),
+    };
+    let s = match session::get_session(session_id, &client).await {
+        Some(s) => s,
+        None => return invalid(),
+    };
+    if s.revoked_at.is_some() {
+        return invalid();
+    }
+    if s.refresh_token_hash != session::hash_refresh_secret(secret) {
+        // An old refresh token was replayed, so it has probably been stolen.
+        // Kill the whole session to lock out both the thief and the owner.
+        if let Err(err) = session::revoke_session(s.session_id, &client).await {
+            println!("Error revoking session: {:?}", err);
+        }
+        return invalid();
+    }
+
+    let user = match get_user_by_id(s.user_id, &client).await {
+        Some(u) => u,
+        None => return invalid(),
+    };
+
+    let new_secret = session::generate_refresh_secret();
+    let generation = match session::rotate_session(
+        s.session_id,
+        &session::hash_refresh_secret(&new_secret),
+        &client,
+    )
+    .await
+    {
+        Ok(Some(generation)) => generation,
+        Ok(None) => return invalid(),
+        Err(err) => {
+            println!("Error rotating session: {:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error refreshing token!"),
+            });
+        }
+    };
+
+    let ward_ids = user.wards.iter().map(|w| w.ward_id).collect();
+    let subject = match TokenSubject::from_user(user, &client).await {
+        Ok(subject) => subject,
+        Err(err) => {
+            println!("Error reading mfa state: {:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error refreshing token!"),
+            });
+        }
+    };
+    match issue_access_token(&subject, ward_ids, s.session_id, generation) {
+        Ok(token) => HttpResponse::Ok().json(LoginResponse {
+            code: 200,
+            message: String::from("Token refreshed successfully."),
+            token,
+            refresh_token: format!("{}.{}", s.session_id, new_secret),
+            expires_in: jwt::access_token_ttl(),
+            name: subject.name,
+            role: subject.role,
+            must_change_password: subject.must_change_password,
+            must_enroll_mfa: subject.must_enroll_mfa,
         }),
+        Err(err) => {
+            println!("Error signing token: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error refreshing token!"),
+            })
+        }
+    }
+}
+
+#[post("/api/auth/logout")]
+pub async fn logout(auth: PendingAuthUser, data: web::Data<Arc<Mutex<Client>>>) -> HttpResponse {
+    let client = data.lock().await;
+    if let Some(session_id) = auth.session_id {
+        if let Err(err) = session::revoke_session(session_id, &client).await {
+            println!("Error revoking session: {:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error logging out!"),
+            });
+        }
+    }
+
+    HttpResponse::Ok().json(BaseResponse {
+        code: 200,
+        message: String::from("Logged out successfully."),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+// Replaces the caller's password. Every other session of the user is ended
+// and the caller gets a fresh one.
+#[post("/api/auth/change-password")]
+pub async fn change_password(
+    req: HttpRequest,
+    auth: PendingAuthUser,
+    data: web::Data<Arc<Mutex<Client>>>,
+    body: web::Json<ChangePasswordRequest>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let user = match get_user_credentials_by_id(auth.user_id, &client).await {
+        Some(u) => u,
+        None => {
+            return HttpResponse::NotFound().json(BaseResponse {
+                code: 404,
+                message: String::from("User not found!"),
+            })
+        }
+    };
+
+    if !verify(&body.old_password, &user.password_hash).unwrap_or(false) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Old password is incorrect!"),
+        });
+    }
+    if body.old_password == body.new_password {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("New password must be different from the old one!"),
+        });
+    }
+    if let Err(message) = validate_password(&body.new_password) {
+        return HttpResponse::BadRequest().json(BaseResponse { code: 400, message });
+    }
+
+    if let Err(err) = set_password(
+        user.userid,
+        &body.new_password,
+        false,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        println!("Error changing password: {:?}", err);
+        return HttpResponse::InternalServerError().json(BaseResponse {
+            code: 500,
+            message: String::from("Error changing password!"),
+        });
+    }
+    if let Err(err) = session::revoke_user_sessions(user.userid, &client).await {
+        println!("Error revoking sessions: {:?}", err);
+    }
+
+    let mut user = user;
+    user.must_change_password = false;
+    match TokenSubject::from_credentials(user, &client).await {
+        Ok(subject) => {
+            session_response(subject, "Password changed successfully.", &req, &client).await
+        }
+        Err(err) => {
+            println!("Error reading mfa state: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
+        }
     }
 }
 
@@ -80,7 +518,10 @@ pub struct HashedPasswordOutput {
 }
 
 #[post("/api/hash_password")]
-pub async fn hash_password(password_input: web::Json<PasswordInput>) -> HttpResponse {
+pub async fn hash_password(
+    _auth: Authorized<UserCreate>,
+    password_input: web::Json<PasswordInput>,
+) -> HttpResponse {
     match hash(&password_input.password, DEFAULT_COST) {
         Ok(hashed) => HttpResponse::Ok().json(HashedPasswordOutput {
             hashed_password: hashed,
@@ -96,13 +537,24 @@ pub struct VerifyTokenRequest {
 
 #[derive(Serialize)]
 pub struct VerifyTokenData {
+    pub user_id: i32,
+    pub role: String,
+    // Kept for gateways that only know about the per-user room.
     pub room: i32,
+    pub rooms: Vec<String>,
 }
 
+// Lets internal services check a user's access token. Callers authenticate
+// with `X-Service-Key`; the response tells them which rooms the user may join.
 #[post("/api/auth/verify-token")]
-pub async fn verify_token(body: web::Json<VerifyTokenRequest>) -> impl Responder {
-    let sub = match verify_token_and_get_sub(&body.token) {
-        Some(s) => s,
+pub async fn verify_token(
+    _service: ServiceAuth,
+    data: web::Data<Arc<Mutex<Client>>>,
+    body: web::Json<VerifyTokenRequest>,
+) -> impl Responder {
+    let client = data.lock().await;
+    let claims = match verify_token_and_get_claims(&body.token, &client).await {
+        Some(c) => c,
         None => {
             return HttpResponse::Unauthorized().json(BaseResponse {
                 code: 401,
@@ -111,19 +563,26 @@ pub async fn verify_token(body: web::Json<VerifyTokenRequest>) -> impl Responder
         }
     };
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let user_id: i32 = parsed_values[0].parse().unwrap();
-    return HttpResponse::Ok().json(DataResponse {
+    let mut rooms = vec![
+        format!("user:{}", claims.user_id),
+        format!("role:{}", claims.role),
+    ];
+    rooms.extend(claims.ward_ids.iter().map(|ward_id| format!("ward:{}", ward_id)));
+    HttpResponse::Ok().json(DataResponse {
         code: 200,
         message: String::from("Token is valid."),
-        data: Some(VerifyTokenData { room: user_id }),
-    });
+        data: Some(VerifyTokenData {
+            user_id: claims.user_id,
+            role: claims.role,
+            room: claims.user_id,
+            rooms,
+        }),
+    })
+}
+
+// Public keys that access tokens are signed with, for services that verify
+// tokens themselves.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(keyring().jwks())
 }
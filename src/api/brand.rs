@@ -9,7 +9,8 @@ use crate::{
     models::brand::{self, BrandRequest},
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{BrandCreate, BrandDelete, BrandRead, BrandUpdate},
     },
 };
 
@@ -22,59 +23,18 @@ pub struct GetBrandsQuery {
 
 #[get("/api/brands")]
 pub async fn get_brands(
-    req: HttpRequest,
+    auth: Authorized<BrandRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     query: web::Query<GetBrandsQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+    let newest_first = auth.can::<BrandCreate>(&client).await;
 
     match brand::get_brands(
         &query.search,
         query.page,
         query.per_page,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -101,61 +61,12 @@ pub async fn get_brands(
 
 #[post("/api/brands")]
 pub async fn add_brand(
+    auth: Authorized<BrandCreate>,
     req: HttpRequest,
     body: web::Json<BrandRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.brand_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
@@ -163,78 +74,29 @@ pub async fn add_brand(
         });
     }
 
-    match brand::add_brand(&body, &client).await {
+    match brand::add_brand(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Brand added successfully"),
         }),
         Err(e) => {
             eprintln!("Brand adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding brand!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/brands/{brand_id}")]
 pub async fn get_brand_by_id(
-    req: HttpRequest,
+    _auth: Authorized<BrandRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let brand_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match brand::get_brand_by_id(brand_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -250,61 +112,14 @@ pub async fn get_brand_by_id(
 
 #[put("/api/brands/{brand_id}")]
 pub async fn update_brand(
+    auth: Authorized<BrandUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<BrandRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let brand_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.brand_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -314,17 +129,17 @@ pub async fn update_brand(
     }
 
     match brand::get_brand_by_id(brand_id, &client).await {
-        Some(_) => match brand::update_brand(brand_id, &body, &client).await {
+        Some(_) => match brand::update_brand(brand_id, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Brand updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Brand updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating brand!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -336,73 +151,25 @@ pub async fn update_brand(
 
 #[delete("/api/brands/{brand_id}")]
 pub async fn delete_brand(
+    auth: Authorized<BrandDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let brand_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match brand::get_brand_by_id(brand_id, &client).await {
-        Some(_) => match brand::delete_brand(brand_id, &client).await {
+        Some(_) => match brand::delete_brand(brand_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Brand deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Brand deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting brand!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
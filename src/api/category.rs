@@ -9,7 +9,8 @@ use crate::{
     models::category::{self, CategoryRequest},
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{CategoryCreate, CategoryDelete, CategoryRead, CategoryUpdate},
     },
 };
 
@@ -22,59 +23,18 @@ pub struct GetCategoriesQuery {
 
 #[get("/api/categories")]
 pub async fn get_categories(
-    req: HttpRequest,
+    auth: Authorized<CategoryRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     query: web::Query<GetCategoriesQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+    let newest_first = auth.can::<CategoryCreate>(&client).await;
 
     match category::get_categories(
         &query.search,
         query.page,
         query.per_page,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -101,61 +61,12 @@ pub async fn get_categories(
 
 #[post("/api/categories")]
 pub async fn add_category(
+    auth: Authorized<CategoryCreate>,
     req: HttpRequest,
     body: web::Json<CategoryRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.category_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
@@ -163,78 +74,29 @@ pub async fn add_category(
         });
     }
 
-    match category::add_category(&body, &client).await {
+    match category::add_category(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Category added successfully"),
         }),
         Err(e) => {
             eprintln!("Category adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding category!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/categories/{category_id}")]
 pub async fn get_category_by_id(
-    req: HttpRequest,
+    _auth: Authorized<CategoryRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let category_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match category::get_category_by_id(category_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -250,61 +112,14 @@ pub async fn get_category_by_id(
 
 #[put("/api/categories/{category_id}")]
 pub async fn update_category(
+    auth: Authorized<CategoryUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<CategoryRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let category_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.category_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -314,17 +129,17 @@ pub async fn update_category(
     }
 
     match category::get_category_by_id(category_id, &client).await {
-        Some(_) => match category::update_category(category_id, &body, &client).await {
+        Some(_) => match category::update_category(category_id, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Category updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Category updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating category!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -336,73 +151,25 @@ pub async fn update_category(
 
 #[delete("/api/categories/{category_id}")]
 pub async fn delete_category(
+    auth: Authorized<CategoryDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let category_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match category::get_category_by_id(category_id, &client).await {
-        Some(_) => match category::delete_category(category_id, &client).await {
+        Some(_) => match category::delete_category(category_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Category deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Category deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting category!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::device::{self, DeviceRequest},
+    utils::{
+        auth::AuthUser,
+        common_struct::{BaseResponse, DataResponse},
+    },
+};
+
+#[get("/api/devices")]
+pub async fn get_devices(auth: AuthUser, data: web::Data<Arc<Mutex<Client>>>) -> impl Responder {
+    let client = data.lock().await;
+    match device::get_devices(auth.user_id, &client).await {
+        Ok(devices) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(devices),
+        }),
+        Err(err) => {
+            println!("Error retrieving devices: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read devices from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/devices")]
+pub async fn add_device(
+    auth: AuthUser,
+    body: web::Json<DeviceRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    if body.token.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Token must not be empty!"),
+        });
+    }
+
+    match device::add_device(&body, auth.user_id, &client).await {
+        Ok(device_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Device registered successfully"),
+            data: Some(device_id),
+        }),
+        Err(e) => {
+            eprintln!("Device adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error registering device!"),
+            })
+        }
+    }
+}
+
+#[delete("/api/devices/{device_id}")]
+pub async fn delete_device(
+    auth: AuthUser,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    match device::delete_device(path.into_inner(), auth.user_id, &client).await {
+        Ok(true) => HttpResponse::Ok().json(BaseResponse {
+            code: 204,
+            message: String::from("Device unregistered successfully"),
+        }),
+        Ok(false) => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Device not found!"),
+        }),
+        Err(e) => {
+            eprintln!("Device deleting error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error unregistering device!"),
+            })
+        }
+    }
+}
@@ -6,10 +6,14 @@ use tokio::sync::Mutex;
 use tokio_postgres::Client;
 
 use crate::{
-    models::discount::{self, DiscountRequest},
+    models::{
+        discount::{self, DiscountRequest},
+        shop::SHOP_CHANNELS,
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{DiscountCreate, DiscountDelete, DiscountRead, DiscountUpdate},
     },
 };
 
@@ -18,70 +22,25 @@ pub struct GetDiscountsQuery {
     pub search: Option<String>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
-    pub role: Option<String>,
+    // Only discounts that apply to shops of this channel.
+    pub channel: Option<String>,
 }
 
 #[get("/api/discounts")]
 pub async fn get_discounts(
-    req: HttpRequest,
+    auth: Authorized<DiscountRead>,
     query: web::Query<GetDiscountsQuery>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> impl Responder {
-    // Extract the token from the Authorization header
     let client = data.lock().await;
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
+    let newest_first = auth.can::<DiscountCreate>(&client).await;
 
     match discount::get_discounts(
+        query.channel.as_deref(),
         &query.search,
         query.page,
         query.per_page,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -108,77 +67,35 @@ pub async fn get_discounts(
 
 #[post("/api/discounts")]
 pub async fn add_discount(
+    auth: Authorized<DiscountCreate>,
     req: HttpRequest,
     body: web::Json<DiscountRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.discount_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Discount Name must not be empty!"),
         });
     }
-    match discount::add_discount(&body, &client).await {
+    if body.channel.as_deref().is_some_and(|c| !c.is_empty() && !SHOP_CHANNELS.contains(&c)) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Channel must be one of {}!", SHOP_CHANNELS.join(", ")),
+        });
+    }
+    match discount::add_discount(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Discount added successfully"),
         }),
         Err(e) => {
             eprintln!("Discount adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding discount!"),
-            });
+            })
         }
     }
 
@@ -186,61 +103,12 @@ pub async fn add_discount(
 
 #[get("/api/discounts/{discount_id}")]
 pub async fn get_discount_by_id(
-    req: HttpRequest,
+    _auth: Authorized<DiscountRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let discount_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match discount::get_discount_by_id(discount_id, &client).await {
         Some(u) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -256,81 +124,39 @@ pub async fn get_discount_by_id(
 
 #[put("/api/discounts/{discount_id}")]
 pub async fn update_discount(
+    auth: Authorized<DiscountUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<DiscountRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let discount_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     if body.discount_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Discount value must not be empty!"),
         });
     }
+    if body.channel.as_deref().is_some_and(|c| !c.is_empty() && !SHOP_CHANNELS.contains(&c)) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Channel must be one of {}!", SHOP_CHANNELS.join(", ")),
+        });
+    }
 
     match discount::get_discount_by_id(discount_id, &client).await {
-        Some(_) => match discount::update_discount(&body, discount_id, &client).await {
+        Some(_) => match discount::update_discount(&body, discount_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Discount updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Discount updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating discount!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -342,73 +168,25 @@ pub async fn update_discount(
 
 #[delete("/api/discounts/{discount_id}")]
 pub async fn delete_discount(
+    auth: Authorized<DiscountDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let discount_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match discount::get_discount_by_id(discount_id, &client).await {
-        Some(_) => match discount::delete_discount(discount_id, &client).await {
+        Some(_) => match discount::delete_discount(discount_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Discount deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Discount deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting discount!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::holiday::{self, HolidayListQuery, HolidayRequest},
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse},
+        permission::{HolidayCreate, HolidayDelete, HolidayRead},
+    },
+};
+
+#[get("/api/holidays")]
+pub async fn get_holidays(
+    _auth: Authorized<HolidayRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<HolidayListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match holiday::get_holidays(&query, &client).await {
+        Ok(holidays) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(holidays),
+        }),
+        Err(err) => {
+            println!("Error retrieving holidays: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all holidays from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/holidays")]
+pub async fn add_holiday(
+    auth: Authorized<HolidayCreate>,
+    req: HttpRequest,
+    body: web::Json<HolidayRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.holiday_name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Name must not be empty!"),
+        });
+    }
+    match holiday::get_holiday_name(body.holiday_date, &client).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("There is already a holiday on that date!"),
+            })
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
+    match holiday::add_holiday(&body, &auth.audit_context(&req), &mut client).await {
+        Ok(holiday_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Holiday added successfully"),
+            data: Some(holiday_id),
+        }),
+        Err(e) => {
+            eprintln!("Holiday adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding holiday!"),
+            })
+        }
+    }
+}
+
+#[delete("/api/holidays/{holiday_id}")]
+pub async fn delete_holiday(
+    auth: Authorized<HolidayDelete>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    match holiday::delete_holiday(path.into_inner(), &auth.audit_context(&req), &mut client).await {
+        Ok(true) => HttpResponse::Ok().json(BaseResponse {
+            code: 204,
+            message: String::from("Holiday deleted successfully"),
+        }),
+        Ok(false) => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Holiday not found!"),
+        }),
+        Err(e) => {
+            eprintln!("Holiday deleting error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error deleting holiday!"),
+            })
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    api::auth::{session_response, TokenSubject},
+    models::{login_throttle, mfa, session, user::get_user_credentials_by_id},
+    utils::{
+        auth::{AuthUser, PendingAuthUser},
+        common_struct::{BaseResponse, DataResponse},
+        totp,
+    },
+};
+
+// Number of recovery codes handed out when two-factor authentication is enabled.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error reading mfa state: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Something went wrong!"),
+    })
+}
+
+fn invalid_code() -> HttpResponse {
+    HttpResponse::Unauthorized().json(BaseResponse {
+        code: 401,
+        message: String::from("Invalid code!"),
+    })
+}
+
+// Checks a TOTP code against the user's secret and burns its time step.
+async fn check_code(
+    user_id: i32,
+    secret: &str,
+    code: &str,
+    client: &Client,
+) -> Result<bool, tokio_postgres::Error> {
+    match totp::verify_code(secret, code) {
+        Some(step) => mfa::use_step(user_id, step, client).await,
+        None => Ok(false),
+    }
+}
+
+fn locked_out(remaining: i64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", remaining.to_string()))
+        .json(BaseResponse {
+            code: 429,
+            message: format!(
+                "Too many failed login attempts. Try again in {} seconds.",
+                remaining
+            ),
+        })
+}
+
+// `check_code` for a signed in user confirming or turning off their second
+// factor. Wrong codes count towards the same lockout as failed logins, so a
+// stolen session cannot guess its way through.
+async fn check_user_code(
+    user_id: i32,
+    secret: &str,
+    code: &str,
+    client: &mut Client,
+) -> Option<HttpResponse> {
+    let Some(user) = get_user_credentials_by_id(user_id, client).await else {
+        return Some(invalid_code());
+    };
+    let throttle_keys = [login_throttle::username_key(&user.username)];
+    match login_throttle::get_lockout_remaining(&throttle_keys, client).await {
+        Ok(Some(remaining)) => return Some(locked_out(remaining)),
+        Ok(None) => {}
+        Err(err) => return Some(internal_error(err)),
+    }
+    match check_code(user_id, secret, code, client).await {
+        Ok(true) => {
+            if let Err(err) = login_throttle::clear(&throttle_keys[0], client).await {
+                println!("Error clearing login throttle: {:?}", err);
+            }
+            None
+        }
+        Ok(false) => {
+            if let Err(err) = login_throttle::record_failure(&throttle_keys[0], client).await {
+                println!("Error recording failed login: {:?}", err);
+            }
+            Some(invalid_code())
+        }
+        Err(err) => Some(internal_error(err)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+// Second step of a login: trades the `mfa_token` from `/api/auth/login` and a
+// code from the authenticator app (or an unused recovery code) for tokens.
+#[post("/api/auth/mfa/verify")]
+pub async fn verify(
+    req: HttpRequest,
+    body: web::Json<MfaVerifyRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let invalid_token = || {
+        HttpResponse::Unauthorized().json(BaseResponse {
+            code: 401,
+            message: String::from("Invalid or expired mfa token"),
+        })
+    };
+
+    let (challenge_id, secret) = match session::parse_refresh_token(&body.mfa_token) {
+        Some(parts) => parts,
+        None => return invalid_token(),
+    };
+    let challenge = match mfa::get_open_challenge(challenge_id, &client).await {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return invalid_token(),
+        Err(err) => return internal_error(err),
+    };
+    if challenge.token_hash != session::hash_refresh_secret(secret) {
+        return invalid_token();
+    }
+    let user = match get_user_credentials_by_id(challenge.user_id, &client).await {
+        Some(user) => user,
+        None => return invalid_token(),
+    };
+
+    let throttle_keys = [login_throttle::username_key(&user.username)];
+    match login_throttle::get_lockout_remaining(&throttle_keys, &client).await {
+        Ok(Some(remaining)) => return locked_out(remaining),
+        Ok(None) => {}
+        Err(err) => return internal_error(err),
+    }
+
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => match mfa::get_user_mfa(user.userid, &client).await {
+            Ok(Some(user_mfa)) if user_mfa.enabled => {
+                check_code(user.userid, &user_mfa.totp_secret, code, &client).await
+            }
+            Ok(_) => Ok(false),
+            Err(err) => Err(err),
+        },
+        (None, Some(recovery_code)) => {
+            mfa::use_recovery_code(user.userid, &mfa::hash_recovery_code(recovery_code), &client)
+                .await
+        }
+        (None, None) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Either code or recovery_code is required!"),
+            })
+        }
+    };
+    match verified {
+        Ok(true) => {}
+        Ok(false) => {
+            if let Err(err) = mfa::record_challenge_attempt(challenge.challenge_id, &client).await {
+                println!("Error recording mfa attempt: {:?}", err);
+            }
+            if let Err(err) = login_throttle::record_failure(&throttle_keys[0], &mut client).await {
+                println!("Error recording failed login: {:?}", err);
+            }
+            return invalid_code();
+        }
+        Err(err) => return internal_error(err),
+    }
+
+    match mfa::close_challenge(challenge.challenge_id, &client).await {
+        Ok(true) => {}
+        Ok(false) => return invalid_token(),
+        Err(err) => return internal_error(err),
+    }
+    if let Err(err) = login_throttle::clear(&throttle_keys[0], &client).await {
+        println!("Error clearing login throttle: {:?}", err);
+    }
+
+    match TokenSubject::from_credentials(user, &client).await {
+        Ok(subject) => {
+            session_response(subject, "Token generated successfully.", &req, &client).await
+        }
+        Err(err) => internal_error(err),
+    }
+}
+
+#[derive(Serialize)]
+pub struct MfaEnrollment {
+    pub secret: String,
+    // Render as a QR code for the authenticator app to scan.
+    pub otpauth_uri: String,
+}
+
+// Starts enrollment with a fresh secret. It only takes effect once a code for
+// it has been sent to `/api/auth/mfa/confirm`.
+#[post("/api/auth/mfa/enroll")]
+pub async fn enroll(
+    auth: PendingAuthUser,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let user = match get_user_credentials_by_id(auth.user_id, &client).await {
+        Some(user) => user,
+        None => {
+            return HttpResponse::NotFound().json(BaseResponse {
+                code: 404,
+                message: String::from("User not found!"),
+            })
+        }
+    };
+
+    let secret = totp::generate_secret();
+    match mfa::set_pending_secret(user.userid, &secret, &client).await {
+        Ok(true) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Scan the code and confirm it with a code from the app."),
+            data: Some(MfaEnrollment {
+                otpauth_uri: totp::provisioning_uri(&secret, &user.username),
+                secret,
+            }),
+        }),
+        Ok(false) => HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Two-factor authentication is already enabled!"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[post("/api/auth/mfa/confirm")]
+pub async fn confirm(
+    auth: PendingAuthUser,
+    req: HttpRequest,
+    body: web::Json<MfaCodeRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let user_mfa = match mfa::get_user_mfa(auth.user_id, &client).await {
+        Ok(Some(user_mfa)) if !user_mfa.enabled => user_mfa,
+        Ok(Some(_)) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Two-factor authentication is already enabled!"),
+            })
+        }
+        Ok(None) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Start enrollment first!"),
+            })
+        }
+        Err(err) => return internal_error(err),
+    };
+    if let Some(response) =
+        check_user_code(auth.user_id, &user_mfa.totp_secret, &body.code, &mut client).await
+    {
+        return response;
+    }
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| totp::generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| mfa::hash_recovery_code(code))
+        .collect();
+    match mfa::enable_mfa(auth.user_id, &hashes, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Two-factor authentication enabled. Store the recovery codes now, they will not be shown again."),
+            data: Some(RecoveryCodes { recovery_codes }),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[post("/api/auth/mfa/disable")]
+pub async fn disable(
+    auth: AuthUser,
+    req: HttpRequest,
+    body: web::Json<MfaCodeRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if mfa::is_mfa_required(&auth.role) {
+        return HttpResponse::Forbidden().json(BaseResponse {
+            code: 403,
+            message: String::from("Two-factor authentication is required for your role!"),
+        });
+    }
+    let user_mfa = match mfa::get_user_mfa(auth.user_id, &client).await {
+        Ok(Some(user_mfa)) if user_mfa.enabled => user_mfa,
+        Ok(_) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Two-factor authentication is not enabled!"),
+            })
+        }
+        Err(err) => return internal_error(err),
+    };
+    if let Some(response) =
+        check_user_code(auth.user_id, &user_mfa.totp_secret, &body.code, &mut client).await
+    {
+        return response;
+    }
+
+    match mfa::disable_mfa(auth.user_id, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Two-factor authentication disabled."),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
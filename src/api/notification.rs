@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use actix_web::{post, web, HttpResponse};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::user,
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse},
+        permission::NotificationCreate,
+        push::{self, PushMessage},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct BroadcastRequest {
+    pub title: String,
+    pub body: String,
+    // Only users with this role; everyone when left out.
+    pub role: Option<String>,
+}
+
+#[post("/api/notifications/broadcast")]
+pub async fn broadcast(
+    _auth: Authorized<NotificationCreate>,
+    body: web::Json<BroadcastRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    if body.title.is_empty() || body.body.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Title and body must not be empty!"),
+        });
+    }
+
+    match user::get_user_ids(body.role.as_deref(), &client).await {
+        Ok(user_ids) => {
+            let recipients = user_ids.len();
+            push::notify_users(
+                data.get_ref().clone(),
+                user_ids,
+                PushMessage {
+                    title: body.title.clone(),
+                    body: body.body.clone(),
+                    data: serde_json::json!({ "type": "broadcast" }),
+                },
+            );
+            HttpResponse::Accepted().json(DataResponse {
+                code: 202,
+                message: String::from("Broadcast queued."),
+                data: Some(recipients),
+            })
+        }
+        Err(e) => {
+            eprintln!("Broadcast error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error sending broadcast!"),
+            })
+        }
+    }
+}
@@ -1,70 +1,115 @@
 use std::sync::Arc;
 
-use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
-use chrono::NaiveDate;
-use serde::Deserialize;
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
 use tokio::sync::Mutex;
 use tokio_postgres::Client;
 
 use crate::{
-    models::order::{self, OrderRequest},
+    models::{
+        order::{
+            self, OrderDetailListQuery, OrderListQuery, OrderMapQuery, OrderOwner, OrderRequest, OrderStatusRequest, ORDER_STATUSES,
+        },
+        product_price, receivable, shop_visit,
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        auth::AuthUser,
+        permission::{OrderApprove, OrderCreate, OrderRead, OrderUpdate, ORDERS},
+        push::{self, PushMessage},
     },
 };
 
+// A message if the order would take the shop over its credit limit.
+async fn credit_limit_excess(
+    body: &OrderRequest,
+    client: &Client,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let Some(credit) = receivable::get_shop_credit(body.shop_id, client).await? else {
+        return Ok(None);
+    };
+    let Some(credit_limit) = credit.credit_limit else {
+        return Ok(None);
+    };
+    let total = order::get_order_total(&body.order_details, client).await?;
+    if credit.balance + total <= credit_limit {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "The order of {:.2} takes the shop's balance of {:.2} over its credit limit of {:.2}!",
+        total, credit.balance, credit_limit
+    )))
+}
+
 #[post("/api/orders")]
 pub async fn add_order(
+    auth: Authorized<OrderCreate>,
     req: HttpRequest,
     body: web::Json<OrderRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> impl Responder {
     let mut client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
+    let user_id = auth.user_id;
+    if let Some(visit_id) = body.visit_id {
+        match shop_visit::get_visit_by_id(visit_id, &client).await {
+            Ok(Some(visit))
+                if visit.user_id == user_id
+                    && visit.shop_id == body.shop_id
+                    && visit.check_out_at.is_none()
+                    && visit.order_id.is_none() => {}
+            Ok(_) => {
                 return HttpResponse::BadRequest().json(BaseResponse {
                     code: 400,
-                    message: String::from("Invalid Authorization header format"),
+                    message: String::from("Order can only be linked to your open visit at the shop!"),
+                })
+            }
+            Err(err) => {
+                println!("{:?}", err);
+                return HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Something went wrong!"),
                 });
             }
         }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
+    }
+
+    let price_ids: Vec<i32> = body.order_details.iter().map(|d| d.price_id).collect();
+    match product_price::get_prices_not_offered(&price_ids, body.shop_id, &client).await {
+        Ok(price_ids) if price_ids.is_empty() => {}
+        Ok(price_ids) => {
+            return HttpResponse::BadRequest().json(DataResponse {
+                code: 400,
+                message: String::from("Some prices are not offered to this shop's channel!"),
+                data: Some(price_ids),
             })
         }
-    };
+        Err(err) => {
+            println!("{:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
+    let credit_warning = match credit_limit_excess(&body, &client).await {
+        Ok(Some(message)) if receivable::block_over_limit() => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message,
             })
         }
+        Ok(warning) => warning,
+        Err(err) => {
+            println!("{:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
     };
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let user_id: i32 = parsed_values[0].parse().unwrap();
-    // let role: &str = parsed_values[1];
-
-    match order::add_order(&body, user_id, &mut client).await {
+    match order::add_order(&body, user_id, &auth.audit_context(&req), &mut client).await {
         Ok(order_id) => {
             if order_id == 0 {
                 return HttpResponse::BadRequest().json(DataResponse {
@@ -77,7 +122,7 @@ pub async fn add_order(
             }
             HttpResponse::Ok().json(DataResponse {
                 code: 200,
-                message: String::from("Successful."),
+                message: credit_warning.unwrap_or_else(|| String::from("Successful.")),
                 data: Some(order_id),
             })
         }
@@ -91,84 +136,18 @@ pub async fn add_order(
     }
 }
 
-#[derive(Deserialize)]
-pub struct GetOrdersQuery {
-    pub search: Option<String>,
-    pub page: Option<usize>,
-    pub per_page: Option<usize>,
-    pub from_date: Option<NaiveDate>,
-    pub to_date: Option<NaiveDate>,
-    pub from_amount: Option<f64>,
-    pub to_amount: Option<f64>,
-    pub status: Option<String>,
-}
-
 #[get("/api/orders")]
 pub async fn get_orders(
-    req: HttpRequest,
+    auth: Authorized<OrderRead>,
     data: web::Data<Arc<Mutex<Client>>>,
-    query: web::Query<GetOrdersQuery>,
+    query: web::Query<OrderListQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let user_id: &str = parsed_values[0];
-    let user_id: i32 = user_id.parse().unwrap();
-    let role: &str = parsed_values[1];
+    // Without `orders:read_all` a caller only sees the orders they and their
+    // team placed.
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
 
-    match order::get_orders(
-        &query.search,
-        query.page,
-        query.per_page,
-        &query.from_date,
-        &query.to_date,
-        &query.from_amount,
-        &query.to_amount,
-        &query.status,
-        user_id,
-        role,
-        &client,
-    )
-    .await
-    {
+    match order::get_orders(&query, scope_user_ids.as_deref(), &client).await {
         Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
             code: 200,
             message: String::from("Successful."),
@@ -189,85 +168,40 @@ pub async fn get_orders(
     }
 }
 
-#[derive(Deserialize)]
-pub struct GetOrderDetailsQuery {
-    pub search: Option<String>,
-    pub page: Option<usize>,
-    pub per_page: Option<usize>,
-    pub order_id: Option<i32>,
-    pub from_amount: Option<f64>,
-    pub to_amount: Option<f64>,
-}
-
-#[get("/api/order-details")]
-pub async fn get_order_details(
-    req: HttpRequest,
+// The filtered orders summed per shop or ward, as GeoJSON for mapping.
+#[get("/api/orders/geojson")]
+pub async fn get_order_features(
+    auth: Authorized<OrderRead>,
     data: web::Data<Arc<Mutex<Client>>>,
-    query: web::Query<GetOrderDetailsQuery>,
+    query: web::Query<OrderListQuery>,
+    map_query: web::Query<OrderMapQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
+
+    match order::get_order_features(&query, map_query.group_by, scope_user_ids.as_deref(), &client).await {
+        Ok(collection) => HttpResponse::Ok()
+            .content_type("application/geo+json")
+            .json(collection),
+        Err(err) => {
+            println!("Error retrieving order features: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all orders from database"),
             })
         }
-    };
-
-    if verify_token_and_get_sub(token).is_none() {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Invalid token"),
-        });
     }
+}
 
-    // let sub = match verify_token_and_get_sub(token) {
-    //     Some(s) => s,
-    //     None => {
-    //         return HttpResponse::Unauthorized().json(BaseResponse {
-    //             code: 401,
-    //             message: String::from("Invalid token"),
-    //         })
-    //     }
-    // };
-
-    // Parse the `sub` value
-    // let parsed_values: Vec<&str> = sub.split(',').collect();
-    // if parsed_values.len() != 3 {
-    //     return HttpResponse::InternalServerError().json(BaseResponse {
-    //         code: 500,
-    //         message: String::from("Invalid sub format in token"),
-    //     });
-    // }
-
-    // let user_id: &str = parsed_values[0];
-    // let user_id: i32 = user_id.parse().unwrap();
-    // let role: &str = parsed_values[1];
-
-    match order::get_order_details(
-        &query.search,
-        query.page,
-        query.per_page,
-        query.order_id,
-        &query.from_amount,
-        &query.to_amount,
-        &client,
-    )
-    .await
-    {
+#[get("/api/order-details")]
+pub async fn get_order_details(
+    auth: Authorized<OrderRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<OrderDetailListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
+    match order::get_order_details(&query, scope_user_ids.as_deref(), &client).await {
         Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
             code: 200,
             message: String::from("Successful."),
@@ -287,3 +221,154 @@ pub async fn get_order_details(
         }
     }
 }
+
+// The order, if the caller may see it.
+async fn get_visible_order(
+    auth: &AuthUser,
+    order_id: i32,
+    client: &Client,
+) -> Result<Option<OrderOwner>, tokio_postgres::Error> {
+    let owner = match order::get_order_owner(order_id, client).await? {
+        Some(owner) => owner,
+        None => return Ok(None),
+    };
+    match auth.visible_user_ids(ORDERS, client).await {
+        Some(user_ids) if !owner.user_id.is_some_and(|id| user_ids.contains(&id)) => Ok(None),
+        _ => Ok(Some(owner)),
+    }
+}
+
+#[put("/api/orders/{order_id}/status")]
+pub async fn update_order_status(
+    auth: Authorized<OrderUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<OrderStatusRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let order_id = path.into_inner();
+    if !ORDER_STATUSES.contains(&body.status.as_str()) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Status must be one of: {}", ORDER_STATUSES.join(", ")),
+        });
+    }
+
+    let owner = match get_visible_order(&auth, order_id, &client).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(BaseResponse {
+                code: 404,
+                message: String::from("Order not found!"),
+            })
+        }
+        Err(err) => {
+            println!("{:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error updating order!"),
+            });
+        }
+    };
+
+    match order::update_order_status(order_id, &body.status, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => {
+            if let Some(user_id) = owner.user_id.filter(|_| owner.status != body.status) {
+                push::notify_users(
+                    data.get_ref().clone(),
+                    vec![user_id],
+                    PushMessage {
+                        title: String::from("Order updated"),
+                        body: format!("Order #{} is now {}.", order_id, body.status),
+                        data: serde_json::json!({
+                            "type": "order_status",
+                            "order_id": order_id,
+                            "status": body.status,
+                        }),
+                    },
+                );
+            }
+            HttpResponse::Ok().json(BaseResponse {
+                code: 200,
+                message: String::from("Order updated successfully"),
+            })
+        }
+        Err(err) => {
+            println!("{:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error updating order!"),
+            })
+        }
+    }
+}
+
+// Managers confirm the pending orders of their team.
+#[post("/api/orders/{order_id}/approve")]
+pub async fn approve_order(
+    auth: Authorized<OrderApprove>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let order_id = path.into_inner();
+    let owner = match get_visible_order(&auth, order_id, &client).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(BaseResponse {
+                code: 404,
+                message: String::from("Order not found!"),
+            })
+        }
+        Err(err) => {
+            println!("{:?}", err);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error approving order!"),
+            });
+        }
+    };
+    if owner.user_id == Some(auth.user_id) {
+        return HttpResponse::Forbidden().json(BaseResponse {
+            code: 403,
+            message: String::from("You cannot approve your own order!"),
+        });
+    }
+
+    match order::approve_order(order_id, &auth.audit_context(&req), &mut client).await {
+        Ok(true) => {
+            if let Some(user_id) = owner.user_id {
+                push::notify_users(
+                    data.get_ref().clone(),
+                    vec![user_id],
+                    PushMessage {
+                        title: String::from("Order approved"),
+                        body: format!("Order #{} has been approved.", order_id),
+                        data: serde_json::json!({
+                            "type": "order_status",
+                            "order_id": order_id,
+                            "status": "Confirmed",
+                        }),
+                    },
+                );
+            }
+            HttpResponse::Ok().json(BaseResponse {
+                code: 200,
+                message: String::from("Order approved successfully"),
+            })
+        }
+        Ok(false) => HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Only pending orders can be approved!"),
+        }),
+        Err(err) => {
+            println!("{:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error approving order!"),
+            })
+        }
+    }
+}
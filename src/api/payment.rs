@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::{
+        payment::{self, PaymentListQuery, PaymentRequest},
+        shop,
+    },
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        permission::{PaymentCreate, PaymentDelete, PaymentRead, ORDERS},
+    },
+};
+
+#[get("/api/payments")]
+pub async fn get_payments(
+    auth: Authorized<PaymentRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<PaymentListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    // Like orders, limited to what the caller's team collected without
+    // `orders:read_all`.
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
+    match payment::get_payments(&query, scope_user_ids.as_deref(), &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving payments: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all payments from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/payments")]
+pub async fn add_payment(
+    auth: Authorized<PaymentCreate>,
+    req: HttpRequest,
+    body: web::Json<PaymentRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.amount <= 0.0 {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Amount must be greater than 0!"),
+        });
+    }
+    if shop::get_shop_by_id(body.shop_id, &client).await.is_none() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Shop does not exist!"),
+        });
+    }
+
+    match payment::add_payment(&body, &auth.audit_context(&req), &mut client).await {
+        Ok(payment_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Payment added successfully"),
+            data: Some(payment_id),
+        }),
+        Err(e) => {
+            eprintln!("Payment adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding payment!"),
+            })
+        }
+    }
+}
+
+#[delete("/api/payments/{payment_id}")]
+pub async fn delete_payment(
+    auth: Authorized<PaymentDelete>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let payment_id = path.into_inner();
+    match payment::get_payment_by_id(payment_id, &client).await {
+        Ok(Some(_)) => {
+            match payment::delete_payment(payment_id, &auth.audit_context(&req), &mut client).await
+            {
+                Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                    code: 204,
+                    message: String::from("Payment deleted successfully"),
+                }),
+                Err(e) => {
+                    eprintln!("Payment deleting error: {}", e);
+                    HttpResponse::InternalServerError().json(BaseResponse {
+                        code: 500,
+                        message: String::from("Error deleting payment!"),
+                    })
+                }
+            }
+        }
+        Ok(None) => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Payment not found!"),
+        }),
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
+        }
+    }
+}
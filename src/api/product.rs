@@ -1,7 +1,6 @@
 use std::sync::Arc;
 
-use actix_web::{get, put, post, delete, web, HttpRequest, HttpResponse, Responder};
-use serde::Deserialize;
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
 use tokio::sync::Mutex;
 use tokio_postgres::Client;
 
@@ -9,72 +8,23 @@ use crate::{
     models::product::{self, ProductRequest, ProductListRequest},
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{ProductCreate, ProductDelete, ProductRead, ProductUpdate},
     },
 };
 
-#[derive(Deserialize)]
-pub struct GetProductsQuery {
-    pub search: Option<String>,
-    pub page: Option<usize>,
-    pub per_page: Option<usize>,
-    pub category_id: Option<usize>,
-    pub brand_id: Option<usize>,
-}
-
 #[post("/api/productlist")]
 pub async fn get_products(
-    req: HttpRequest,
+    auth: Authorized<ProductRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     body: web::Json<ProductListRequest>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-   // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+    let newest_first = auth.can::<ProductCreate>(&client).await;
 
     match product::get_products(
         &body,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -101,61 +51,12 @@ pub async fn get_products(
 
 #[post("/api/products")]
 pub async fn add_product(
+    auth: Authorized<ProductCreate>,
     req: HttpRequest,
     body: web::Json<ProductRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.product_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
@@ -163,77 +64,29 @@ pub async fn add_product(
         });
     }
 
-    match product::add_product(&body, &client).await {
+    match product::add_product(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Product added successfully"),
         }),
         Err(e) => {
             eprintln!("Product adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding product!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/products/{product_id}")]
 pub async fn get_product_by_id(
-    req: HttpRequest,
+    _auth: Authorized<ProductRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let product_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" && role!="Distributor" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     match product::get_product_by_id(product_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
@@ -250,61 +103,14 @@ pub async fn get_product_by_id(
 
 #[put("/api/products/{product_id}")]
 pub async fn update_product(
+    auth: Authorized<ProductUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<ProductRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let product_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.product_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -314,17 +120,17 @@ pub async fn update_product(
     }
 
     match product::get_product_by_id(product_id, &client).await {
-        Some(s) => match product::update_product(product_id, &s.image_url, &body, &client).await {
+        Some(s) => match product::update_product(product_id, &s.image_url, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Product updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Product updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating product!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -336,73 +142,25 @@ pub async fn update_product(
 
 #[delete("/api/products/{product_id}")]
 pub async fn delete_product(
+    auth: Authorized<ProductDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let product_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match product::get_product_by_id(product_id, &client).await {
-        Some(_) => match product::delete_product(product_id, &client).await {
+        Some(_) => match product::delete_product(product_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Product deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Product deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting product!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
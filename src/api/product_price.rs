@@ -6,10 +6,14 @@ use tokio_postgres::Client;
 use tokio::sync::Mutex;
 
 use crate::{
-    models::product_price::{self, PriceRequest},
+    models::{
+        product_price::{self, PriceRequest},
+        shop::SHOP_CHANNELS,
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{PriceCreate, PriceDelete, PriceRead, PriceUpdate},
     },
 };
 
@@ -19,67 +23,21 @@ pub struct GetPricesQuery {
     pub page: Option<usize>,
     pub per_page: Option<usize>,
     pub product_id: i32,
+    // Only prices offered to shops of this channel.
+    pub channel: Option<String>,
 }
 
 #[get("/api/prices")]
 pub async fn get_prices(
-    req: HttpRequest,
+    _auth: Authorized<PriceRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     query: web::Query<GetPricesQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" && role != "Distributor"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     match product_price::get_prices(
         query.product_id,
+        query.channel.as_deref(),
         &query.search,
         query.page,
         query.per_page,
@@ -109,61 +67,12 @@ pub async fn get_prices(
 
 #[post("/api/prices")]
 pub async fn add_price(
+    auth: Authorized<PriceCreate>,
     req: HttpRequest,
     body: web::Json<PriceRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.price_type.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
@@ -177,78 +86,36 @@ pub async fn add_price(
             message: String::from("Invalid Product Type!"),
         });
     }
+    if body.channel.as_deref().is_some_and(|c| !c.is_empty() && !SHOP_CHANNELS.contains(&c)) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Channel must be one of {}!", SHOP_CHANNELS.join(", ")),
+        });
+    }
 
-    match product_price::add_price(&body, &client).await {
+    match product_price::add_price(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Price added successfully"),
         }),
         Err(e) => {
             eprintln!("Price adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding price!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/prices/{price_id}")]
 pub async fn get_price_by_id(
-    req: HttpRequest,
+    _auth: Authorized<PriceRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let price_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" && role != "Distributor"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     match product_price::get_price_by_id(price_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
@@ -265,61 +132,14 @@ pub async fn get_price_by_id(
 
 #[put("/api/prices/{price_id}")]
 pub async fn update_price(
+    auth: Authorized<PriceUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<PriceRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let price_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.price_type.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -334,19 +154,25 @@ pub async fn update_price(
             message: String::from("Invalid Product Type!"),
         });
     }
+    if body.channel.as_deref().is_some_and(|c| !c.is_empty() && !SHOP_CHANNELS.contains(&c)) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Channel must be one of {}!", SHOP_CHANNELS.join(", ")),
+        });
+    }
 
     match product_price::get_price_by_id(price_id, &client).await {
-        Some(_) => match product_price::update_price(price_id, &body, &client).await {
+        Some(_) => match product_price::update_price(price_id, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Price updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Price updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating price!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -358,73 +184,25 @@ pub async fn update_price(
 
 #[delete("/api/prices/{price_id}")]
 pub async fn delete_price(
+    auth: Authorized<PriceDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let price_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match product_price::get_price_by_id(price_id, &client).await {
-        Some(_) => match product_price::delete_price(price_id, &client).await {
+        Some(_) => match product_price::delete_price(price_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Price deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Price deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting price!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
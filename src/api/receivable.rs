@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::Local;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::receivable::{self, AgingQuery, CreditLimitRequest},
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse},
+        permission::{ReceivableRead, ReceivableUpdate, ORDERS},
+    },
+};
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error reading receivables: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Something went wrong!"),
+    })
+}
+
+fn shop_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(BaseResponse {
+        code: 404,
+        message: String::from("Shop not found!"),
+    })
+}
+
+#[get("/api/shops/{shop_id}/credit")]
+pub async fn get_shop_credit(
+    _auth: Authorized<ReceivableRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    match receivable::get_shop_credit(path.into_inner(), &client).await {
+        Ok(Some(credit)) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(credit),
+        }),
+        Ok(None) => shop_not_found(),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[put("/api/shops/{shop_id}/credit-limit")]
+pub async fn set_credit_limit(
+    auth: Authorized<ReceivableUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<CreditLimitRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let shop_id = path.into_inner();
+    if body.credit_limit.is_some_and(|limit| limit < 0.0) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Credit limit must not be negative!"),
+        });
+    }
+    match receivable::get_shop_credit(shop_id, &client).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return shop_not_found(),
+        Err(err) => return internal_error(err),
+    }
+
+    match receivable::set_credit_limit(
+        shop_id,
+        body.credit_limit,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(()) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Credit limit updated successfully"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+// Orders and payments of a shop with the running balance.
+#[get("/api/shops/{shop_id}/ledger")]
+pub async fn get_ledger(
+    _auth: Authorized<ReceivableRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let shop_id = path.into_inner();
+    match receivable::get_shop_credit(shop_id, &client).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return shop_not_found(),
+        Err(err) => return internal_error(err),
+    }
+    match receivable::get_ledger(shop_id, &client).await {
+        Ok(entries) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(entries),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[get("/api/reports/receivables-aging")]
+pub async fn get_aging(
+    auth: Authorized<ReceivableRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<AgingQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    // Limited to orders the caller's team placed without `orders:read_all`.
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
+    let as_of = query.as_of.unwrap_or_else(|| Local::now().date_naive());
+    match receivable::get_aging(query.group_by, as_of, scope_user_ids.as_deref(), &client).await {
+        Ok(rows) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(rows),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
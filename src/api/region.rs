@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::region::{self, RegionRequest},
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        permission::{RegionCreate, RegionDelete, RegionRead, RegionUpdate},
+    },
+};
+
+#[derive(Deserialize)]
+pub struct GetRegionsQuery {
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[get("/api/regions")]
+pub async fn get_regions(
+    auth: Authorized<RegionRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<GetRegionsQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    let newest_first = auth.can::<RegionCreate>(&client).await;
+
+    match region::get_regions(
+        &query.search,
+        query.page,
+        query.per_page,
+        newest_first,
+        &client,
+    )
+    .await
+    {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            // Log the error message here
+            println!("Error retrieving regions: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all regions from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/regions")]
+pub async fn add_region(
+    auth: Authorized<RegionCreate>,
+    req: HttpRequest,
+    body: web::Json<RegionRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.region_name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Name must not be empty!"),
+        });
+    }
+
+    match region::add_region(&body, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Created().json(BaseResponse {
+            code: 201,
+            message: String::from("Region added successfully"),
+        }),
+        Err(e) => {
+            eprintln!("Region adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding region!"),
+            })
+        }
+    }
+}
+
+#[get("/api/regions/{region_id}")]
+pub async fn get_region_by_id(
+    _auth: Authorized<RegionRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let region_id = path.into_inner();
+    match region::get_region_by_id(region_id, &client).await {
+        Some(c) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Region fetched successfully."),
+            data: Some(c),
+        }),
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Region not found!"),
+        }),
+    }
+}
+
+#[put("/api/regions/{region_id}")]
+pub async fn update_region(
+    auth: Authorized<RegionUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<RegionRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let region_id = path.into_inner();
+
+    if body.region_name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Name must not be empty!"),
+        });
+    }
+
+    match region::get_region_by_id(region_id, &client).await {
+        Some(_) => {
+            match region::update_region(region_id, &body, &auth.audit_context(&req), &mut client)
+                .await
+            {
+                Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                    code: 200,
+                    message: String::from("Region updated successfully"),
+                }),
+                Err(e) => {
+                    eprintln!("Region updating error: {}", e);
+                    HttpResponse::InternalServerError().json(BaseResponse {
+                        code: 500,
+                        message: String::from("Error updating region!"),
+                    })
+                }
+            }
+        }
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Region not found!"),
+        }),
+    }
+}
+
+#[delete("/api/regions/{region_id}")]
+pub async fn delete_region(
+    auth: Authorized<RegionDelete>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let region_id = path.into_inner();
+    match region::region_in_use(region_id, &client).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Region still has townships!"),
+            });
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+    match region::get_region_by_id(region_id, &client).await {
+        Some(_) => {
+            match region::delete_region(region_id, &auth.audit_context(&req), &mut client).await {
+                Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                    code: 204,
+                    message: String::from("Region deleted successfully"),
+                }),
+                Err(e) => {
+                    eprintln!("Region deleting error: {}", e);
+                    HttpResponse::InternalServerError().json(BaseResponse {
+                        code: 500,
+                        message: String::from("Error deleting region!"),
+                    })
+                }
+            }
+        }
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Region not found!"),
+        }),
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::role::{self, RoleRequest},
+    utils::{
+        common_struct::{BaseResponse, DataResponse},
+        auth::Authorized,
+        permission::{RoleCreate, RoleDelete, RoleRead, RoleUpdate},
+    },
+};
+
+#[get("/api/permissions")]
+pub async fn get_permissions(
+    _auth: Authorized<RoleRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match role::get_permissions(&client).await {
+        Ok(permissions) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(permissions),
+        }),
+        Err(err) => {
+            println!("Error retrieving permissions: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all permissions from database"),
+            })
+        }
+    }
+}
+
+#[get("/api/roles")]
+pub async fn get_roles(
+    _auth: Authorized<RoleRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match role::get_roles(&client).await {
+        Ok(roles) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(roles),
+        }),
+        Err(err) => {
+            println!("Error retrieving roles: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all roles from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/roles")]
+pub async fn add_role(
+    auth: Authorized<RoleCreate>,
+    req: HttpRequest,
+    body: web::Json<RoleRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.role_name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Role Name must not be empty!"),
+        });
+    }
+
+    match role::role_exists(&body.role_name, &client).await {
+        Ok(true) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Role already exists!"),
+            });
+        }
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
+    match role::add_role(&body, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Created().json(BaseResponse {
+            code: 201,
+            message: String::from("Role added successfully"),
+        }),
+        Err(e) => {
+            eprintln!("Role adding error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding role!"),
+            })
+        }
+    }
+}
+
+#[get("/api/roles/{role_id}")]
+pub async fn get_role_by_id(
+    _auth: Authorized<RoleRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let role_id = path.into_inner();
+    match role::get_role_by_id(role_id, &client).await {
+        Some(r) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Role fetched successfully."),
+            data: Some(r),
+        }),
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Role not found!"),
+        }),
+    }
+}
+
+#[put("/api/roles/{role_id}")]
+pub async fn update_role(
+    auth: Authorized<RoleUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<RoleRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let role_id = path.into_inner();
+
+    if body.role_name.is_empty() {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Role Name must not be empty!"),
+        });
+    }
+
+    match role::get_role_by_id(role_id, &client).await {
+        Some(r) => match role::update_role(role_id, &r.role_name, &body, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                code: 200,
+                message: String::from("Role updated successfully"),
+            }),
+            Err(e) => {
+                eprintln!("Role updating error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error updating role!"),
+                })
+            }
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Role not found!"),
+        }),
+    }
+}
+
+#[delete("/api/roles/{role_id}")]
+pub async fn delete_role(
+    auth: Authorized<RoleDelete>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let role_id = path.into_inner();
+
+    match role::role_in_use(role_id, &client).await {
+        Ok(true) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Role is still assigned to users!"),
+            });
+        }
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
+    match role::get_role_by_id(role_id, &client).await {
+        Some(_) => match role::delete_role(role_id, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                code: 204,
+                message: String::from("Role deleted successfully"),
+            }),
+            Err(e) => {
+                eprintln!("Role deleting error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error deleting role!"),
+                })
+            }
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Role not found!"),
+        }),
+    }
+}
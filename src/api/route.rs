@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use actix_web::{get, web, HttpResponse};
+use chrono::Local;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::{
+        holiday,
+        route::{self, RoutePlan, RouteQuery},
+    },
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse},
+        permission::ShopRead,
+    },
+};
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error planning route: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Error trying to read shops from database"),
+    })
+}
+
+// The caller's shops for the day in visiting order, instead of listing them
+// by weekday through `POST /api/shoplist`.
+#[get("/api/me/route")]
+pub async fn get_my_route(
+    auth: Authorized<ShopRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<RouteQuery>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let start = match (query.lat, query.lng) {
+        (Some(lat), Some(lng))
+            if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng) =>
+        {
+            Some((lat, lng))
+        }
+        (None, None) => None,
+        _ => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Invalid start location!"),
+            })
+        }
+    };
+    let date = query.date.unwrap_or_else(|| Local::now().date_naive());
+    let mut plan = RoutePlan {
+        date,
+        weekday: date.format("%A").to_string(),
+        holiday: None,
+        total_distance_m: 0.0,
+        stops: vec![],
+    };
+
+    match holiday::get_holiday_name(date, &client).await {
+        Ok(Some(name)) => plan.holiday = Some(name),
+        Ok(None) => match route::get_route_stops(auth.user_id, date, &client).await {
+            Ok(stops) => (plan.stops, plan.total_distance_m) = route::order_stops(start, stops),
+            Err(err) => return internal_error(err),
+        },
+        Err(err) => return internal_error(err),
+    }
+    HttpResponse::Ok().json(DataResponse {
+        code: 200,
+        message: String::from("Successful."),
+        data: Some(plan),
+    })
+}
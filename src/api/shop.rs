@@ -1,79 +1,40 @@
 use std::sync::Arc;
 
-use actix_web::{get, put, post, delete, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
 use serde::Deserialize;
 use tokio::sync::Mutex;
 use tokio_postgres::Client;
 
 use crate::{
-    models::shop::{self, ShopRequest, ShopListRequest},
+    models::{
+        shop::{
+            self, NearbyShopsQuery, ShopListRequest, ShopRequest, SHOP_CHANNELS, SHOP_SIZE_CLASSES,
+        },
+        ward,
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        geo::Boundary,
+        permission::{ShopCreate, ShopDelete, ShopRead, ShopUpdate, SHOPS},
     },
 };
 
-#[derive(Deserialize)]
-pub struct GetShopsQuery {
-    pub search: Option<String>,
-    pub page: Option<usize>,
-    pub per_page: Option<usize>,
-    pub weekdays: Option<String>,
-}
-
 #[post("/api/shoplist")]
 pub async fn get_shops(
-    req: HttpRequest,
+    auth: Authorized<ShopRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     body: web::Json<ShopListRequest>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+    // Without `shops:read_all` a caller only sees shops in their own and their
+    // team's wards.
+    let scope_user_ids = auth.visible_user_ids(SHOPS, &client).await;
+    let newest_first = auth.can::<ShopCreate>(&client).await;
 
     match shop::get_shops(
-        user_id,
-        role,
+        scope_user_ids.as_deref(),
+        newest_first,
         &body,
         &client,
     )
@@ -99,63 +60,78 @@ pub async fn get_shops(
     }
 }
 
-#[post("/api/shops")]
-pub async fn add_shop(
-    req: HttpRequest,
-    body: web::Json<ShopRequest>,
-    data: web::Data<Arc<Mutex<Client>>>,
-) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
+// Checks the channel, size class, phone numbers and tags against what a shop
+// may carry.
+pub(crate) fn check_classification(body: &ShopRequest) -> Option<HttpResponse> {
+    let message = if body
+        .channel
+        .as_deref()
+        .is_some_and(|c| !c.is_empty() && !SHOP_CHANNELS.contains(&c))
+    {
+        format!("Channel must be one of {}!", SHOP_CHANNELS.join(", "))
+    } else if body
+        .size_class
+        .as_deref()
+        .is_some_and(|c| !c.is_empty() && !SHOP_SIZE_CLASSES.contains(&c))
+    {
+        format!("Size class must be one of {}!", SHOP_SIZE_CLASSES.join(", "))
+    } else if body.phone_numbers.iter().flatten().any(|p| {
+        !p.chars().any(|c| c.is_ascii_digit())
+            || !p.chars().all(|c| c.is_ascii_digit() || "+-() ".contains(c))
+    }) {
+        String::from("Invalid phone number!")
+    } else if body.tags.iter().flatten().any(|t| t.trim().is_empty()) {
+        String::from("Tags must not be empty!")
+    } else {
+        return None;
     };
+    Some(HttpResponse::BadRequest().json(BaseResponse { code: 400, message }))
+}
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
+// Checks that the ward exists and, when it has a boundary, that the shop's
+// coordinates lie inside it. The rejection names the ward they do fall in.
+pub(crate) async fn check_ward(body: &ShopRequest, client: &Client) -> Option<HttpResponse> {
+    let bad_request = |message: String| {
+        Some(HttpResponse::BadRequest().json(BaseResponse { code: 400, message }))
     };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
+    let Some(ward) = ward::get_ward_by_id(body.ward_id, client).await else {
+        return bad_request(String::from("Ward does not exist!"));
+    };
+    let (Some(latitude), Some(longitude), Some(boundary)) =
+        (body.latitude, body.longitude, &ward.boundary)
+    else {
+        return None;
+    };
+    match Boundary::parse(boundary) {
+        Ok(boundary) if !boundary.contains(latitude, longitude) => {}
+        _ => return None,
     }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
+    match ward::get_wards_at(latitude, longitude, client).await {
+        Ok(wards) => match wards.first() {
+            Some(suggested) => bad_request(format!(
+                "Shop location is outside {}! It lies in {} (ward_id {}).",
+                ward.ward_name, suggested.ward_name, suggested.ward_id
+            )),
+            None => bad_request(format!("Shop location is outside {}!", ward.ward_name)),
+        },
+        Err(err) => {
+            println!("Error locating ward: {:?}", err);
+            Some(HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            }))
+        }
     }
+}
 
+#[post("/api/shops")]
+pub async fn add_shop(
+    auth: Authorized<ShopCreate>,
+    req: HttpRequest,
+    body: web::Json<ShopRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
     if body.shop_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
@@ -168,78 +144,161 @@ pub async fn add_shop(
             message: String::from("Address must not be empty!"),
         });
     }
+    if let Some(response) = check_classification(&body) {
+        return response;
+    }
+    if let Some(response) = check_ward(&body, &client).await {
+        return response;
+    }
 
-    match shop::add_shop(&body, &client).await {
+    match shop::add_shop(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Shop added successfully"),
         }),
         Err(e) => {
             eprintln!("Shop adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding shop!"),
-            });
+            })
         }
     }
 }
 
-#[get("/api/shops/{shop_id}")]
-pub async fn get_shop_by_id(
-    req: HttpRequest,
-    path: web::Path<i32>,
+const DEFAULT_RADIUS_M: f64 = 1000.0;
+const MAX_RADIUS_M: f64 = 50_000.0;
+const DEFAULT_NEARBY_LIMIT: i64 = 50;
+const MAX_NEARBY_LIMIT: i64 = 200;
+
+// Shops around a point, nearest first, e.g. the caller's current location.
+#[get("/api/shops/nearby")]
+pub async fn get_nearby_shops(
+    auth: Authorized<ShopRead>,
     data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<NearbyShopsQuery>,
 ) -> HttpResponse {
     let client = data.lock().await;
-    let shop_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
+    if !(-90.0..=90.0).contains(&query.lat) || !(-180.0..=180.0).contains(&query.lng) {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Invalid coordinates!"),
+        });
+    }
+    let radius_m = query.radius_m.unwrap_or(DEFAULT_RADIUS_M);
+    if radius_m <= 0.0 || radius_m > MAX_RADIUS_M {
+        return HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("radius_m must be between 0 and {}!", MAX_RADIUS_M),
+        });
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_NEARBY_LIMIT)
+        .clamp(1, MAX_NEARBY_LIMIT);
+    let scope_user_ids = auth.visible_user_ids(SHOPS, &client).await;
+
+    match shop::get_nearby_shops(
+        query.lat,
+        query.lng,
+        radius_m,
+        limit,
+        scope_user_ids.as_deref(),
+        &client,
+    )
+    .await
+    {
+        Ok(shops) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(shops),
+        }),
+        Err(err) => {
+            println!("Error retrieving nearby shops: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read shops from database"),
+            })
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ShopMapQuery {
+    pub search: Option<String>,
+    // Weekday ids separated by commas.
+    pub weekdays: Option<String>,
+    pub channel: Option<String>,
+    pub size_class: Option<String>,
+    // Tags separated by commas; shops must carry all of them.
+    pub tags: Option<String>,
+}
+
+// The shops of the shop list as GeoJSON points for mapping, unpaged.
+#[get("/api/shops/geojson")]
+pub async fn get_shop_features(
+    auth: Authorized<ShopRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<ShopMapQuery>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let weekdays = match &query.weekdays {
+        Some(weekdays) => match weekdays
+            .split(',')
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<i32>, _>>()
+        {
+            Ok(weekdays) => Some(weekdays),
+            Err(_) => {
                 return HttpResponse::BadRequest().json(BaseResponse {
                     code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
+                    message: String::from("Invalid weekdays!"),
+                })
             }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
+        },
+        None => None,
+    };
+    let request = ShopListRequest {
+        search: query.search.clone(),
+        page: None,
+        per_page: None,
+        weekdays,
+        channel: query.channel.clone(),
+        size_class: query.size_class.clone(),
+        tags: query.tags.as_ref().map(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .collect()
+        }),
     };
+    let scope_user_ids = auth.visible_user_ids(SHOPS, &client).await;
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
+    match shop::get_shop_features(scope_user_ids.as_deref(), &request, &client).await {
+        Ok(collection) => HttpResponse::Ok()
+            .content_type("application/geo+json")
+            .json(collection),
+        Err(err) => {
+            println!("Error retrieving shop features: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all shops from database"),
             })
         }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
     }
+}
 
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" && role!="Distributor" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
+#[get("/api/shops/{shop_id}")]
+pub async fn get_shop_by_id(
+    _auth: Authorized<ShopRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let shop_id = path.into_inner();
 
     match shop::get_shop_by_id(shop_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
@@ -256,61 +315,14 @@ pub async fn get_shop_by_id(
 
 #[put("/api/shops/{shop_id}")]
 pub async fn update_shop(
+    auth: Authorized<ShopUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<ShopRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let shop_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.shop_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -325,19 +337,25 @@ pub async fn update_shop(
             message: String::from("Address must not be empty!"),
         });
     }
+    if let Some(response) = check_classification(&body) {
+        return response;
+    }
+    if let Some(response) = check_ward(&body, &client).await {
+        return response;
+    }
 
     match shop::get_shop_by_id(shop_id, &client).await {
-        Some(s) => match shop::update_shop(shop_id, &s.image_url, &body, &client).await {
+        Some(s) => match shop::update_shop(shop_id, &s.image_url, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Shop updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Shop updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating shop!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -349,73 +367,25 @@ pub async fn update_shop(
 
 #[delete("/api/shops/{shop_id}")]
 pub async fn delete_shop(
+    auth: Authorized<ShopDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let shop_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match shop::get_shop_by_id(shop_id, &client).await {
-        Some(_) => match shop::delete_shop(shop_id, &client).await {
+        Some(_) => match shop::delete_shop(shop_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Shop deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Shop deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting shop!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -424,3 +394,27 @@ pub async fn delete_shop(
         }),
     }
 }
+
+// Shops filed under a ward their coordinates are not in.
+#[get("/api/reports/misplaced-shops")]
+pub async fn get_misplaced_shops(
+    auth: Authorized<ShopRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> impl Responder {
+    let client = data.lock().await;
+    let scope_user_ids = auth.visible_user_ids(SHOPS, &client).await;
+    match shop::get_misplaced_shops(scope_user_ids.as_deref(), &client).await {
+        Ok(shops) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(shops),
+        }),
+        Err(err) => {
+            println!("Error retrieving misplaced shops: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read shops from database"),
+            })
+        }
+    }
+}
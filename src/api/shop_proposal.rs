@@ -0,0 +1,290 @@
+use std::sync::Arc;
+
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use super::shop::{check_classification, check_ward};
+use crate::{
+    models::{
+        shop::{self, ShopRequest},
+        shop_proposal::{
+            self, ProposalListQuery, ProposalRequest, ReviewRequest, ShopProposal,
+            PROPOSAL_STATUSES,
+        },
+    },
+    utils::{
+        auth::{AuthUser, Authorized},
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        permission::{ProposalCreate, ProposalRead, ProposalReview, SHOP_PROPOSALS},
+        push::{self, PushMessage},
+    },
+};
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(BaseResponse {
+        code: 400,
+        message: String::from(message),
+    })
+}
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error updating shop proposals: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Something went wrong!"),
+    })
+}
+
+fn proposal_not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(BaseResponse {
+        code: 404,
+        message: String::from("Shop proposal not found!"),
+    })
+}
+
+// What a proposal must hold to become a shop: a name, an address, a photo,
+// where it is and the weekdays it is visited on.
+async fn check_shop(body: &ShopRequest, client: &Client) -> Option<HttpResponse> {
+    if body.shop_name.is_empty() {
+        return Some(bad_request("Shop Name must not be empty!"));
+    }
+    if body.address.is_empty() {
+        return Some(bad_request("Address must not be empty!"));
+    }
+    if body.image_url.is_empty() {
+        return Some(bad_request("A photo of the shop is required!"));
+    }
+    match (body.latitude, body.longitude) {
+        (Some(latitude), Some(longitude))
+            if (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude) => {}
+        _ => return Some(bad_request("The shop's location is required!")),
+    }
+    if let Some(response) = check_classification(body) {
+        return Some(response);
+    }
+    match shop::get_unknown_weekday_ids(&body.weekdays, client).await {
+        Ok(unknown) if unknown.is_empty() => {}
+        Ok(unknown) => return Some(bad_request(&format!("Weekday {} does not exist!", unknown[0]))),
+        Err(err) => return Some(internal_error(err)),
+    }
+    check_ward(body, client).await
+}
+
+// The proposal if the caller may see it: their own and their team's, or any
+// with `shop_proposals:read_all`.
+async fn get_visible_proposal(
+    auth: &AuthUser,
+    proposal_id: i32,
+    client: &Client,
+) -> Result<Option<ShopProposal>, tokio_postgres::Error> {
+    let scope_user_ids = auth.visible_user_ids(SHOP_PROPOSALS, client).await;
+    Ok(shop_proposal::get_proposal_by_id(proposal_id, client)
+        .await?
+        .filter(|p| scope_user_ids.is_none_or(|ids| ids.contains(&p.user_id))))
+}
+
+// Tells the distributor who proposed the shop what became of it.
+fn notify_decision(data: &web::Data<Arc<Mutex<Client>>>, proposal: &ShopProposal, status: &str) {
+    push::notify_users(
+        data.get_ref().clone(),
+        vec![proposal.user_id],
+        PushMessage {
+            title: format!("Shop proposal {}", status),
+            body: format!("{} has been {}.", proposal.shop_name, status),
+            data: serde_json::json!({
+                "type": "shop_proposal",
+                "proposal_id": proposal.proposal_id,
+                "status": status,
+            }),
+        },
+    );
+}
+
+#[get("/api/shop-proposals")]
+pub async fn get_proposals(
+    auth: Authorized<ProposalRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<ProposalListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    if query
+        .status
+        .as_deref()
+        .is_some_and(|s| !PROPOSAL_STATUSES.contains(&s))
+    {
+        return bad_request("Invalid status!");
+    }
+    let scope_user_ids = auth.visible_user_ids(SHOP_PROPOSALS, &client).await;
+    match shop_proposal::get_proposals(&query, scope_user_ids.as_deref(), &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving shop proposals: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all shop proposals from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/shop-proposals")]
+pub async fn add_proposal(
+    auth: Authorized<ProposalCreate>,
+    req: HttpRequest,
+    body: web::Json<ProposalRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if let Some(response) = check_shop(&body.shop, &client).await {
+        return response;
+    }
+
+    match shop_proposal::add_proposal(&body, &auth.audit_context(&req), &mut client).await {
+        Ok(proposal_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Shop proposal submitted for approval"),
+            data: Some(proposal_id),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[get("/api/shop-proposals/{proposal_id}")]
+pub async fn get_proposal_by_id(
+    auth: Authorized<ProposalRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    match get_visible_proposal(&auth, path.into_inner(), &client).await {
+        Ok(Some(proposal)) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Shop proposal fetched successfully."),
+            data: Some(proposal),
+        }),
+        Ok(None) => proposal_not_found(),
+        Err(err) => internal_error(err),
+    }
+}
+
+// The reviewer corrects a pending proposal before deciding on it.
+#[put("/api/shop-proposals/{proposal_id}")]
+pub async fn update_proposal(
+    auth: Authorized<ProposalReview>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<ShopRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let proposal_id = path.into_inner();
+    match shop_proposal::get_proposal_by_id(proposal_id, &client).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return proposal_not_found(),
+        Err(err) => return internal_error(err),
+    }
+    if let Some(response) = check_shop(&body, &client).await {
+        return response;
+    }
+
+    match shop_proposal::update_proposal(proposal_id, &body, &auth.audit_context(&req), &mut client)
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Shop proposal updated successfully"),
+        }),
+        Ok(false) => bad_request("Only pending proposals can be changed!"),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[post("/api/shop-proposals/{proposal_id}/approve")]
+pub async fn approve_proposal(
+    auth: Authorized<ProposalReview>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<ReviewRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let proposal_id = path.into_inner();
+    let proposal = match shop_proposal::get_proposal_by_id(proposal_id, &client).await {
+        Ok(Some(proposal)) => proposal,
+        Ok(None) => return proposal_not_found(),
+        Err(err) => return internal_error(err),
+    };
+    // The ward may have been deleted or redrawn since the proposal was made.
+    if let Some(response) = check_ward(&proposal.shop_request(), &client).await {
+        return response;
+    }
+
+    match shop_proposal::approve_proposal(
+        proposal_id,
+        body.review_note.as_deref(),
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(Some(shop_id)) => {
+            notify_decision(&data, &proposal, "approved");
+            HttpResponse::Ok().json(DataResponse {
+                code: 200,
+                message: String::from("Shop proposal approved successfully"),
+                data: Some(shop_id),
+            })
+        }
+        Ok(None) => bad_request("Only pending proposals can be approved!"),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[post("/api/shop-proposals/{proposal_id}/reject")]
+pub async fn reject_proposal(
+    auth: Authorized<ProposalReview>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<ReviewRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let proposal_id = path.into_inner();
+    // The distributor is told why.
+    let Some(review_note) = body.review_note.as_deref().filter(|n| !n.trim().is_empty()) else {
+        return bad_request("A reason for rejecting is required!");
+    };
+    let proposal = match shop_proposal::get_proposal_by_id(proposal_id, &client).await {
+        Ok(Some(proposal)) => proposal,
+        Ok(None) => return proposal_not_found(),
+        Err(err) => return internal_error(err),
+    };
+
+    match shop_proposal::reject_proposal(
+        proposal_id,
+        review_note,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(true) => {
+            notify_decision(&data, &proposal, "rejected");
+            HttpResponse::Ok().json(BaseResponse {
+                code: 200,
+                message: String::from("Shop proposal rejected successfully"),
+            })
+        }
+        Ok(false) => bad_request("Only pending proposals can be rejected!"),
+        Err(err) => internal_error(err),
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::{
+        order, shop,
+        shop_visit::{self, CheckInRequest, CheckOutRequest, VisitListQuery, NO_ORDER_REASONS},
+    },
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        geo::distance_m,
+        permission::{VisitCreate, VisitRead, VISITS},
+    },
+};
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(BaseResponse {
+        code: 400,
+        message: String::from(message),
+    })
+}
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error updating shop visits: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Something went wrong!"),
+    })
+}
+
+fn valid_coordinates(latitude: f64, longitude: f64) -> bool {
+    (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)
+}
+
+#[get("/api/shop-visits")]
+pub async fn get_visits(
+    auth: Authorized<VisitRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<VisitListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    // Without `visits:read_all` a caller only sees their own and their team's
+    // visits.
+    let scope_user_ids = auth.visible_user_ids(VISITS, &client).await;
+    match shop_visit::get_visits(&query, scope_user_ids.as_deref(), &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving shop visits: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read shop visits from database"),
+            })
+        }
+    }
+}
+
+// Starts a visit where the device is. The distance to the shop is kept, and a
+// check-in beyond `VISIT_RADIUS_M` is rejected or flagged.
+#[post("/api/shop-visits/check-in")]
+pub async fn check_in(
+    auth: Authorized<VisitCreate>,
+    req: HttpRequest,
+    body: web::Json<CheckInRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if !valid_coordinates(body.latitude, body.longitude) {
+        return bad_request("Invalid coordinates!");
+    }
+    let shop = match shop::get_shop_by_id(body.shop_id, &client).await {
+        Some(shop) => shop,
+        None => return bad_request("Shop does not exist!"),
+    };
+    match shop_visit::get_open_visit(auth.user_id, &client).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return bad_request("Check out of the current visit first!"),
+        Err(err) => return internal_error(err),
+    }
+
+    // Shops without coordinates are read back as 0, 0.
+    let distance = (shop.latitude != 0.0 || shop.longitude != 0.0).then(|| {
+        distance_m(
+            (body.latitude, body.longitude),
+            (shop.latitude, shop.longitude),
+        )
+    });
+    let radius_m = shop_visit::visit_radius_m();
+    if let Some(distance) = distance.filter(|d| *d > radius_m) {
+        if shop_visit::reject_outside_radius() {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: format!(
+                    "You are {:.0} m from the shop; check in within {:.0} m!",
+                    distance, radius_m
+                ),
+            });
+        }
+    }
+    let flagged = distance.is_none_or(|d| d > radius_m);
+
+    match shop_visit::check_in(
+        &body,
+        distance,
+        flagged,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(visit_id) => match shop_visit::get_visit_by_id(visit_id, &client).await {
+            Ok(visit) => HttpResponse::Created().json(DataResponse {
+                code: 201,
+                message: String::from("Checked in successfully"),
+                data: visit,
+            }),
+            Err(err) => internal_error(err),
+        },
+        Err(err) => internal_error(err),
+    }
+}
+
+// Ends the caller's visit, with the order taken or the reason there was none.
+#[post("/api/shop-visits/{visit_id}/check-out")]
+pub async fn check_out(
+    auth: Authorized<VisitCreate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<CheckOutRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let visit = match shop_visit::get_visit_by_id(path.into_inner(), &client).await {
+        Ok(Some(visit)) if visit.user_id == auth.user_id => visit,
+        Ok(_) => {
+            return HttpResponse::NotFound().json(BaseResponse {
+                code: 404,
+                message: String::from("Shop visit not found!"),
+            })
+        }
+        Err(err) => return internal_error(err),
+    };
+    if visit.check_out_at.is_some() {
+        return bad_request("Already checked out!");
+    }
+    if !valid_coordinates(body.latitude, body.longitude) {
+        return bad_request("Invalid coordinates!");
+    }
+    if let Some(reason) = &body.no_order_reason {
+        if !NO_ORDER_REASONS.contains(&reason.as_str()) {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: format!(
+                    "no_order_reason must be one of: {}",
+                    NO_ORDER_REASONS.join(", ")
+                ),
+            });
+        }
+    }
+    if let Some(order_id) = body.order_id {
+        match order::get_order_owner(order_id, &client).await {
+            Ok(Some(owner))
+                if owner.user_id == Some(auth.user_id) && owner.shop_id == Some(visit.shop_id) => {}
+            Ok(_) => return bad_request("Order was not taken by you at this shop!"),
+            Err(err) => return internal_error(err),
+        }
+    }
+    let has_order = visit.order_id.is_some() || body.order_id.is_some();
+    if has_order == body.no_order_reason.is_some() {
+        return bad_request("Give either an order or a no-order reason!");
+    }
+
+    match shop_visit::check_out(
+        visit.visit_id,
+        &body,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(()) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Checked out successfully"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
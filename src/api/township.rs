@@ -6,15 +6,20 @@ use tokio_postgres::Client;
 use tokio::sync::Mutex;
 
 use crate::{
-    models::township::{self, TownshipRequest},
+    models::{
+        region,
+        township::{self, TownshipRequest},
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        permission::{TownshipCreate, TownshipDelete, TownshipRead, TownshipUpdate},
     },
 };
 
 #[derive(Deserialize)]
 pub struct GetTownshipsQuery {
+    pub region_id: Option<i32>,
     pub search: Option<String>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
@@ -22,59 +27,85 @@ pub struct GetTownshipsQuery {
 
 #[get("/api/townships")]
 pub async fn get_townships(
-    req: HttpRequest,
+    auth: Authorized<TownshipRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     query: web::Query<GetTownshipsQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
+    let newest_first = auth.can::<TownshipCreate>(&client).await;
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
+    match township::get_townships(
+        query.region_id,
+        &query.search,
+        query.page,
+        query.per_page,
+        newest_first,
+        &client,
+    )
+    .await
+    {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            // Log the error message here
+            println!("Error retrieving townships: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all townships from database"),
             })
         }
-    };
+    }
+}
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
+// Checks that the region a township is put in exists, if it is given one.
+async fn check_region(body: &TownshipRequest, client: &Client) -> Option<HttpResponse> {
+    let region_id = body.region_id?;
+    if region::get_region_by_id(region_id, client).await.is_none() {
+        return Some(HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Region does not exist!"),
+        }));
     }
+    None
+}
 
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+#[derive(Deserialize)]
+pub struct GetRegionTownshipsQuery {
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[get("/api/regions/{region_id}/townships")]
+pub async fn get_region_townships(
+    auth: Authorized<TownshipRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<GetRegionTownshipsQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    let region_id = path.into_inner();
+    if region::get_region_by_id(region_id, &client).await.is_none() {
+        return HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Region not found!"),
+        });
+    }
+    let newest_first = auth.can::<TownshipCreate>(&client).await;
 
     match township::get_townships(
+        Some(region_id),
         &query.search,
         query.page,
         query.per_page,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -89,7 +120,6 @@ pub async fn get_townships(
             page_counts: item_result.page_counts,
         }),
         Err(err) => {
-            // Log the error message here
             println!("Error retrieving townships: {:?}", err);
             HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
@@ -101,140 +131,45 @@ pub async fn get_townships(
 
 #[post("/api/townships")]
 pub async fn add_township(
+    auth: Authorized<TownshipCreate>,
     req: HttpRequest,
     body: web::Json<TownshipRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.township_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Name must not be empty!"),
         });
     }
+    if let Some(response) = check_region(&body, &client).await {
+        return response;
+    }
 
-    match township::add_township(&body, &client).await {
+    match township::add_township(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Township added successfully"),
         }),
         Err(e) => {
             eprintln!("Township adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding township!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/townships/{township_id}")]
 pub async fn get_township_by_id(
-    req: HttpRequest,
+    _auth: Authorized<TownshipRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let township_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match township::get_township_by_id(township_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -250,61 +185,14 @@ pub async fn get_township_by_id(
 
 #[put("/api/townships/{township_id}")]
 pub async fn update_township(
+    auth: Authorized<TownshipUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<TownshipRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let township_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.township_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -312,19 +200,22 @@ pub async fn update_township(
             message: String::from("Name must not be empty!"),
         });
     }
+    if let Some(response) = check_region(&body, &client).await {
+        return response;
+    }
 
     match township::get_township_by_id(township_id, &client).await {
-        Some(_) => match township::update_township(township_id, &body, &client).await {
+        Some(_) => match township::update_township(township_id, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Township updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Township updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating township!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -336,73 +227,41 @@ pub async fn update_township(
 
 #[delete("/api/townships/{township_id}")]
 pub async fn delete_township(
+    auth: Authorized<TownshipDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let township_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
+    match township::township_in_use(township_id, &client).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Township still has wards or shops!"),
+            });
         }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
         }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
     }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match township::get_township_by_id(township_id, &client).await {
-        Some(_) => match township::delete_township(township_id, &client).await {
+        Some(_) => match township::delete_township(township_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Township deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Township deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting township!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::trash::{self, RestoreOutcome, TrashEntity, TrashListQuery, TRASH_ENTITIES},
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        permission::{TrashPurge, TrashRead, TrashRestore},
+    },
+};
+
+fn find_entity(entity_type: &str) -> Result<&'static TrashEntity, HttpResponse> {
+    trash::find(entity_type).ok_or_else(|| {
+        let known: Vec<&str> = TRASH_ENTITIES.iter().map(|t| t.entity.name).collect();
+        HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: format!("Unknown entity type! Expected one of: {}", known.join(", ")),
+        })
+    })
+}
+
+#[get("/api/trash")]
+pub async fn get_trash(
+    _auth: Authorized<TrashRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<TrashListQuery>,
+) -> impl Responder {
+    let trash = match find_entity(&query.entity_type) {
+        Ok(trash) => trash,
+        Err(response) => return response,
+    };
+    let client = data.lock().await;
+    match trash::get_trash(trash, &query, &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving trash: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read trash from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/trash/{entity_type}/{entity_id}/restore")]
+pub async fn restore(
+    auth: Authorized<TrashRestore>,
+    req: HttpRequest,
+    path: web::Path<(String, i32)>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let (entity_type, entity_id) = path.into_inner();
+    let trash = match find_entity(&entity_type) {
+        Ok(trash) => trash,
+        Err(response) => return response,
+    };
+    let mut client = data.lock().await;
+    match trash::restore(trash, entity_id, &auth.audit_context(&req), &mut client).await {
+        Ok(RestoreOutcome::Restored) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Restored successfully"),
+        }),
+        Ok(RestoreOutcome::NotInTrash) => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Not found in trash!"),
+        }),
+        Ok(RestoreOutcome::ParentDeleted(parent)) => {
+            HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: format!("The {} it belongs to is deleted, restore it first!", parent),
+            })
+        }
+        Ok(RestoreOutcome::UsernameTaken) => HttpResponse::Conflict().json(BaseResponse {
+            code: 409,
+            message: String::from("Username is already taken by another user!"),
+        }),
+        Ok(RestoreOutcome::RoleNameTaken) => HttpResponse::Conflict().json(BaseResponse {
+            code: 409,
+            message: String::from("Role name is already taken by another role!"),
+        }),
+        Err(err) => {
+            println!("Error restoring {} {}: {:?}", entity_type, entity_id, err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PurgeQuery {
+    // Every entity type without it.
+    pub entity_type: Option<String>,
+}
+
+// Permanently deletes records that have been in the trash longer than
+// `TRASH_RETENTION_DAYS`.
+#[post("/api/trash/purge")]
+pub async fn purge(
+    auth: Authorized<TrashPurge>,
+    req: HttpRequest,
+    query: web::Query<PurgeQuery>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let trash_entities: Vec<&TrashEntity> = match &query.entity_type {
+        Some(entity_type) => match find_entity(entity_type) {
+            Ok(trash) => vec![trash],
+            Err(response) => return response,
+        },
+        None => TRASH_ENTITIES.iter().collect(),
+    };
+    let mut client = data.lock().await;
+    match trash::purge(&trash_entities, &auth.audit_context(&req), &mut client).await {
+        Ok(result) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Trash purged successfully"),
+            data: Some(result),
+        }),
+        Err(err) => {
+            println!("Error purging trash: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            })
+        }
+    }
+}
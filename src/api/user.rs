@@ -1,15 +1,24 @@
 use std::sync::Arc;
 
+use actix_multipart::Multipart;
 use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use futures::StreamExt;
 use serde::Deserialize;
 use tokio::sync::Mutex;
 use tokio_postgres::Client;
 
 use crate::{
-    models::user::{self, AddUserRequest, UpdateUserRequest},
+    models::{
+        login_throttle, mfa, role, session,
+        user::{self, AddUserRequest, ImportOutcome, UpdateUserRequest},
+        user_import::{self, ImportQuery, ImportResult},
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::{AuthUser, Authorized},
+        password::validate_password,
+        permission::{UserCreate, UserDelete, UserRead, UserUpdate},
+        spreadsheet,
     },
 };
 
@@ -18,70 +27,20 @@ pub struct GetUsersQuery {
     pub search: Option<String>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
-    pub role: Option<String>,
 }
 
 #[get("/api/users")]
 pub async fn get_users(
-    req: HttpRequest,
+    _auth: Authorized<UserRead>,
     query: web::Query<GetUsersQuery>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> impl Responder {
-    // Extract the token from the Authorization header
     let client = data.lock().await;
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     match user::get_users(
         &query.search,
         query.page,
         query.per_page,
-        role,
         &client,
     )
     .await
@@ -108,66 +67,57 @@ pub async fn get_users(
 
 #[post("/api/users")]
 pub async fn add_user(
+    auth: Authorized<UserCreate>,
     req: HttpRequest,
     body: web::Json<AddUserRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
+    let mut client = data.lock().await;
     if body.fullname.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Name must not be empty!"),
         });
     }
+    if let Err(message) = validate_password(&body.password) {
+        return HttpResponse::BadRequest().json(BaseResponse { code: 400, message });
+    }
+
+    match role::role_exists(&body.role, &client).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Role does not exist!"),
+            });
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
+
+    if let Some(manager_id) = body.manager_id {
+        match user::is_valid_manager(None, manager_id, &client).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::BadRequest().json(BaseResponse {
+                    code: 400,
+                    message: String::from("Manager does not exist!"),
+                });
+            }
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Something went wrong!"),
+                });
+            }
+        }
+    }
 
     match user::user_exists(&body.username, &client).await {
         Ok(exists) => {
@@ -178,87 +128,181 @@ pub async fn add_user(
                 });
             }
 
-            match user::add_user(&body, &client).await {
+            match user::add_user(&body, &auth.audit_context(&req), &mut client).await {
                 Ok(()) => HttpResponse::Created().json(BaseResponse {
                     code: 201,
                     message: String::from("User added successfully"),
                 }),
                 Err(e) => {
                     eprintln!("User adding error: {}", e);
-                    return HttpResponse::InternalServerError().json(BaseResponse {
+                    HttpResponse::InternalServerError().json(BaseResponse {
                         code: 500,
                         message: String::from("Error adding user!"),
-                    });
+                    })
                 }
             }
         }
         Err(e) => {
             eprintln!("Database error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Something went wrong!"),
-            });
+            })
         }
     }
 }
 
-#[get("/api/users/{user_id}")]
-pub async fn get_user_by_id(
+// Largest file accepted by the user import.
+const MAX_IMPORT_BYTES: usize = 5 * 1024 * 1024;
+
+fn bad_request(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(BaseResponse { code: 400, message })
+}
+
+// Reads the `file` field of the upload as its name and contents.
+async fn read_upload(mut payload: Multipart) -> Result<(String, Vec<u8>), String> {
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| e.to_string())?;
+        if field.name() != "file" {
+            continue;
+        }
+        let filename = field
+            .content_disposition()
+            .get_filename()
+            .unwrap_or_default()
+            .to_string();
+        let mut bytes = vec![];
+        while let Some(chunk) = field.next().await {
+            bytes.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+            if bytes.len() > MAX_IMPORT_BYTES {
+                return Err(String::from("File is too large!"));
+            }
+        }
+        return Ok((filename, bytes));
+    }
+    Err(String::from("No file uploaded!"))
+}
+
+// Creates users in bulk from a CSV or XLSX file with the fields of
+// `POST /api/users` as columns. Either every row is created or, if any row
+// has errors, none; the response lists the errors per row.
+#[post("/api/users/import")]
+pub async fn import_users(
+    auth: Authorized<UserCreate>,
     req: HttpRequest,
-    path: web::Path<i32>,
+    query: web::Query<ImportQuery>,
+    payload: Multipart,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
-    let user_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
+    let (filename, bytes) = match read_upload(payload).await {
+        Ok(upload) => upload,
+        Err(message) => return bad_request(message),
     };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
+    let parsed = spreadsheet::read_rows(&filename, &bytes).and_then(|rows| user_import::parse_rows(&rows));
+    let mut rows = match parsed {
+        Ok(rows) => rows,
+        Err(message) => return bad_request(message),
     };
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
+    let client = data.lock().await;
+    if let Err(err) = user_import::validate_rows(&mut rows, query.generate_passwords, &client).await {
+        println!("Database error: {:?}", err);
         return HttpResponse::InternalServerError().json(BaseResponse {
             code: 500,
-            message: String::from("Invalid sub format in token"),
+            message: String::from("Something went wrong!"),
         });
     }
 
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
+    let (rows, users): (Vec<_>, Vec<_>) = rows.into_iter().map(|r| (r.result, r.user)).unzip();
+    let mut result = ImportResult {
+        dry_run: query.dry_run,
+        created: 0,
+        rows,
+    };
+    if query.dry_run || result.has_errors() {
+        // Passwords are only handed out for users that were created.
+        for row in result.rows.iter_mut() {
+            row.temporary_password = None;
+        }
+        if result.has_errors() {
+            return HttpResponse::BadRequest().json(DataResponse {
+                code: 400,
+                message: String::from("Some rows have errors, no users were added!"),
+                data: Some(result),
+            });
+        }
+        return HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("All rows are valid."),
+            data: Some(result),
         });
     }
 
+    // bcrypt takes a while per row; hash on the blocking pool with the client
+    // released so other requests are not held up.
+    drop(client);
+    let hashed = web::block(move || {
+        let hashed = user::hash_passwords(&users);
+        (users, hashed)
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|(users, hashed)| hashed.map(|h| (users, h)).map_err(|e| e.to_string()));
+    let (users, hashed_passwords) = match hashed {
+        Ok(hashed) => hashed,
+        Err(e) => {
+            println!("Password hashing error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding users!"),
+            });
+        }
+    };
+    let mut client = data.lock().await;
+
+    match user::import_users(&users, &hashed_passwords, &auth.audit_context(&req), &mut client).await {
+        Ok(ImportOutcome::UsernamesTaken(indexes)) => {
+            for (i, row) in result.rows.iter_mut().enumerate() {
+                row.temporary_password = None;
+                if indexes.contains(&i) {
+                    row.errors.push(String::from("User already exists!"));
+                }
+            }
+            HttpResponse::BadRequest().json(DataResponse {
+                code: 400,
+                message: String::from("Some rows have errors, no users were added!"),
+                data: Some(result),
+            })
+        }
+        Ok(ImportOutcome::Created(user_ids)) => {
+            for (row, user_id) in result.rows.iter_mut().zip(user_ids) {
+                row.user_id = Some(user_id);
+            }
+            result.created = result.rows.len();
+            HttpResponse::Created().json(DataResponse {
+                code: 201,
+                message: String::from("Users added successfully"),
+                data: Some(result),
+            })
+        }
+        Err(e) => {
+            println!("User import error: {:?}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error adding users!"),
+            })
+        }
+    }
+}
+
+#[get("/api/users/{user_id}")]
+pub async fn get_user_by_id(
+    _auth: Authorized<UserRead>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let user_id = path.into_inner();
     match user::get_user_by_id(user_id, &client).await {
         Some(u) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -274,81 +318,55 @@ pub async fn get_user_by_id(
 
 #[put("/api/users/{user_id}")]
 pub async fn update_user(
+    auth: Authorized<UserUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<UpdateUserRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let user_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     if body.fullname.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Name must not be empty!"),
         });
     }
+    if let Some(password) = body.password.as_deref().filter(|p| !p.is_empty()) {
+        if let Err(message) = validate_password(password) {
+            return HttpResponse::BadRequest().json(BaseResponse { code: 400, message });
+        }
+    }
+
+    match role::role_exists(&body.role, &client).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::BadRequest().json(BaseResponse {
+                code: 400,
+                message: String::from("Role does not exist!"),
+            });
+        }
+        Err(e) => {
+            eprintln!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Something went wrong!"),
+            });
+        }
+    }
 
     match user::get_user_by_id(user_id, &client).await {
-        Some(u) => match user::update_user(&u.password, &body, user_id, &client).await {
+        Some(_) => match user::update_user(&body, user_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("User updated successfully"),
             }),
             Err(e) => {
                 eprintln!("User updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating user!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -360,73 +378,127 @@ pub async fn update_user(
 
 #[delete("/api/users/{user_id}")]
 pub async fn delete_user(
+    auth: Authorized<UserDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let user_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
+    match user::get_user_by_id(user_id, &client).await {
+        Some(_) => match user::delete_user(user_id, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => HttpResponse::Ok().json(BaseResponse {
+                code: 204,
+                message: String::from("User deleted successfully"),
+            }),
+            Err(e) => {
+                eprintln!("User deleting error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error deleting user!"),
+                })
             }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("User not found!"),
+        }),
+    }
+}
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
+#[delete("/api/users/{user_id}/sessions")]
+pub async fn revoke_user_sessions(
+    _auth: Authorized<UserUpdate>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let user_id = path.into_inner();
+    match session::revoke_user_sessions(user_id, &client).await {
+        Ok(count) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Sessions revoked successfully"),
+            data: Some(count),
+        }),
+        Err(e) => {
+            eprintln!("Session revoking error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error revoking sessions!"),
             })
         }
-    };
+    }
+}
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
+#[post("/api/users/{user_id}/unlock")]
+pub async fn unlock_user(
+    _auth: Authorized<UserUpdate>,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let client = data.lock().await;
+    let user_id = path.into_inner();
+    match user::get_user_by_id(user_id, &client).await {
+        Some(u) => match login_throttle::clear(&login_throttle::username_key(&u.username), &client)
+            .await
+        {
+            Ok(_) => HttpResponse::Ok().json(BaseResponse {
+                code: 200,
+                message: String::from("User unlocked successfully"),
+            }),
+            Err(e) => {
+                eprintln!("User unlocking error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error unlocking user!"),
+                })
+            }
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("User not found!"),
+        }),
     }
+}
 
-    let role: &str = parsed_values[1];
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub new_password: String,
+}
 
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
+// Sets a temporary password. The user is logged out everywhere and has to
+// choose a new password right after the next login.
+#[post("/api/users/{user_id}/reset-password")]
+pub async fn reset_password(
+    auth: Authorized<UserUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<ResetPasswordRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let user_id = path.into_inner();
+    if let Err(message) = validate_password(&body.new_password) {
+        return HttpResponse::BadRequest().json(BaseResponse { code: 400, message });
     }
 
     match user::get_user_by_id(user_id, &client).await {
-        Some(_) => match user::delete_user(user_id, &client).await {
-            Ok(()) => HttpResponse::Ok().json(BaseResponse {
-                code: 204,
-                message: String::from("User deleted successfully"),
-            }),
+        Some(_) => match user::set_password(user_id, &body.new_password, true, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => {
+                if let Err(e) = session::revoke_user_sessions(user_id, &client).await {
+                    eprintln!("Session revoking error: {}", e);
+                }
+                HttpResponse::Ok().json(BaseResponse {
+                    code: 200,
+                    message: String::from("Password reset successfully"),
+                })
+            }
             Err(e) => {
-                eprintln!("User deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                eprintln!("Password resetting error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
-                    message: String::from("Error deleting user!"),
-                });
+                    message: String::from("Error resetting password!"),
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -435,3 +507,118 @@ pub async fn delete_user(
         }),
     }
 }
+
+// For users who lost both their authenticator and their recovery codes. They
+// enroll again on their next login if their role requires it.
+#[delete("/api/users/{user_id}/mfa")]
+pub async fn reset_mfa(
+    auth: Authorized<UserUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let user_id = path.into_inner();
+    match user::get_user_by_id(user_id, &client).await {
+        Some(_) => match mfa::disable_mfa(user_id, &auth.audit_context(&req), &mut client).await {
+            Ok(()) => {
+                if let Err(e) = session::revoke_user_sessions(user_id, &client).await {
+                    eprintln!("Session revoking error: {}", e);
+                }
+                HttpResponse::Ok().json(BaseResponse {
+                    code: 200,
+                    message: String::from("Two-factor authentication reset successfully"),
+                })
+            }
+            Err(e) => {
+                eprintln!("Two-factor resetting error: {}", e);
+                HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Error resetting two-factor authentication!"),
+                })
+            }
+        },
+        None => HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("User not found!"),
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetManagerRequest {
+    // Leave out or null to take the user out of any team.
+    pub manager_id: Option<i32>,
+}
+
+#[put("/api/users/{user_id}/manager")]
+pub async fn set_manager(
+    auth: Authorized<UserUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    body: web::Json<SetManagerRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let user_id = path.into_inner();
+    if user::get_user_by_id(user_id, &client).await.is_none() {
+        return HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("User not found!"),
+        });
+    }
+    if let Some(manager_id) = body.manager_id {
+        match user::is_valid_manager(Some(user_id), manager_id, &client).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return HttpResponse::BadRequest().json(BaseResponse {
+                    code: 400,
+                    message: String::from(
+                        "Manager must be an existing user outside of the user's own team!",
+                    ),
+                });
+            }
+            Err(e) => {
+                eprintln!("Database error: {}", e);
+                return HttpResponse::InternalServerError().json(BaseResponse {
+                    code: 500,
+                    message: String::from("Something went wrong!"),
+                });
+            }
+        }
+    }
+
+    match user::set_manager(user_id, body.manager_id, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Manager updated successfully"),
+        }),
+        Err(e) => {
+            eprintln!("Manager updating error: {}", e);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error updating manager!"),
+            })
+        }
+    }
+}
+
+// Everyone who reports to the caller, directly or through another manager.
+#[get("/api/team")]
+pub async fn get_team(auth: AuthUser, data: web::Data<Arc<Mutex<Client>>>) -> impl Responder {
+    let client = data.lock().await;
+    match user::get_team(auth.user_id, &client).await {
+        Ok(team) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(team),
+        }),
+        Err(err) => {
+            println!("Error retrieving team: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read team from database"),
+            })
+        }
+    }
+}
@@ -6,15 +6,21 @@ use tokio_postgres::Client;
 use tokio::sync::Mutex;
 
 use crate::{
-    models::ward::{self, WardRequest},
+    models::{
+        township,
+        ward::{self, WardRequest},
+    },
     utils::{
         common_struct::{BaseResponse, DataResponse, PaginationResponse},
-        jwt::verify_token_and_get_sub,
+        auth::Authorized,
+        geo::Boundary,
+        permission::{WardCreate, WardDelete, WardRead, WardUpdate},
     },
 };
 
 #[derive(Deserialize)]
 pub struct GetWardsQuery {
+    pub township_id: Option<i32>,
     pub search: Option<String>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
@@ -22,59 +28,19 @@ pub struct GetWardsQuery {
 
 #[get("/api/wards")]
 pub async fn get_wards(
-    req: HttpRequest,
+    auth: Authorized<WardRead>,
     data: web::Data<Arc<Mutex<Client>>>,
     query: web::Query<GetWardsQuery>,
 ) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+    let newest_first = auth.can::<WardCreate>(&client).await;
 
     match ward::get_wards(
+        query.township_id,
         &query.search,
         query.page,
         query.per_page,
-        role,
+        newest_first,
         &client,
     )
     .await
@@ -99,142 +65,145 @@ pub async fn get_wards(
     }
 }
 
-#[post("/api/wards")]
-pub async fn add_ward(
-    req: HttpRequest,
-    body: web::Json<WardRequest>,
+#[derive(Deserialize)]
+pub struct GetTownshipWardsQuery {
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+#[get("/api/townships/{township_id}/wards")]
+pub async fn get_township_wards(
+    auth: Authorized<WardRead>,
+    path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
-) -> HttpResponse {
+    query: web::Query<GetTownshipWardsQuery>,
+) -> impl Responder {
     let client = data.lock().await;
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
+    let township_id = path.into_inner();
+    if township::get_township_by_id(township_id, &client).await.is_none() {
+        return HttpResponse::NotFound().json(BaseResponse {
+            code: 404,
+            message: String::from("Township not found!"),
+        });
+    }
+    let newest_first = auth.can::<WardCreate>(&client).await;
 
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
+    match ward::get_wards(
+        Some(township_id),
+        &query.search,
+        query.page,
+        query.per_page,
+        newest_first,
+        &client,
+    )
+    .await
+    {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving wards: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read all wards from database"),
             })
         }
-    };
+    }
+}
 
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
+// Checks that the township a ward is put in exists and that its boundary, if
+// given, is a valid GeoJSON polygon.
+async fn check_ward_request(body: &WardRequest, client: &Client) -> Option<HttpResponse> {
+    if township::get_township_by_id(body.township_id, client).await.is_none() {
+        return Some(HttpResponse::BadRequest().json(BaseResponse {
+            code: 400,
+            message: String::from("Township does not exist!"),
+        }));
     }
+    if let Some(Err(message)) = body.boundary.as_ref().map(Boundary::parse) {
+        return Some(HttpResponse::BadRequest().json(BaseResponse { code: 400, message }));
+    }
+    None
+}
 
-    // let user_id: i32 = parsed_values[0].parse().unwrap();
-    let role: &str = parsed_values[1];
+#[derive(Deserialize)]
+pub struct LocateWardQuery {
+    pub latitude: f64,
+    pub longitude: f64,
+}
 
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
+// Wards whose boundary holds the point, to suggest one for a shop.
+#[get("/api/wards/locate")]
+pub async fn locate_ward(
+    _auth: Authorized<WardRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<LocateWardQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match ward::get_wards_at(query.latitude, query.longitude, &client).await {
+        Ok(wards) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(wards),
+        }),
+        Err(err) => {
+            println!("Error locating ward: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read wards from database"),
+            })
+        }
     }
+}
 
+#[post("/api/wards")]
+pub async fn add_ward(
+    auth: Authorized<WardCreate>,
+    req: HttpRequest,
+    body: web::Json<WardRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
     if body.ward_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
             code: 400,
             message: String::from("Ward Name must not be empty!"),
         });
     }
+    if let Some(response) = check_ward_request(&body, &client).await {
+        return response;
+    }
 
-    match ward::add_ward(&body, &client).await {
+    match ward::add_ward(&body, &auth.audit_context(&req), &mut client).await {
         Ok(()) => HttpResponse::Created().json(BaseResponse {
             code: 201,
             message: String::from("Ward added successfully"),
         }),
         Err(e) => {
             eprintln!("Ward adding error: {}", e);
-            return HttpResponse::InternalServerError().json(BaseResponse {
+            HttpResponse::InternalServerError().json(BaseResponse {
                 code: 500,
                 message: String::from("Error adding ward!"),
-            });
+            })
         }
     }
 }
 
 #[get("/api/wards/{ward_id}")]
 pub async fn get_ward_by_id(
-    req: HttpRequest,
+    _auth: Authorized<WardRead>,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
     let client = data.lock().await;
     let ward_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match ward::get_ward_by_id(ward_id, &client).await {
         Some(c) => HttpResponse::Ok().json(DataResponse {
             code: 200,
@@ -250,61 +219,14 @@ pub async fn get_ward_by_id(
 
 #[put("/api/wards/{ward_id}")]
 pub async fn update_ward(
+    auth: Authorized<WardUpdate>,
     req: HttpRequest,
     path: web::Path<i32>,
     body: web::Json<WardRequest>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let ward_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin"  {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
 
     if body.ward_name.is_empty() {
         return HttpResponse::BadRequest().json(BaseResponse {
@@ -312,19 +234,22 @@ pub async fn update_ward(
             message: String::from("Ward Name must not be empty!"),
         });
     }
+    if let Some(response) = check_ward_request(&body, &client).await {
+        return response;
+    }
 
     match ward::get_ward_by_id(ward_id, &client).await {
-        Some(_) => match ward::update_ward(ward_id, &body, &client).await {
+        Some(_) => match ward::update_ward(ward_id, &body, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 200,
                 message: String::from("Ward updated successfully"),
             }),
             Err(e) => {
                 eprintln!("Ward updating error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error updating ward!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -336,73 +261,25 @@ pub async fn update_ward(
 
 #[delete("/api/wards/{ward_id}")]
 pub async fn delete_ward(
+    auth: Authorized<WardDelete>,
     req: HttpRequest,
     path: web::Path<i32>,
     data: web::Data<Arc<Mutex<Client>>>,
 ) -> HttpResponse {
-    let client = data.lock().await;
+    let mut client = data.lock().await;
     let ward_id = path.into_inner();
-    // Extract the token from the Authorization header
-    let token = match req.headers().get("Authorization") {
-        Some(value) => {
-            let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
-            if parts.len() == 2 && parts[0] == "Bearer" {
-                parts[1]
-            } else {
-                return HttpResponse::BadRequest().json(BaseResponse {
-                    code: 400,
-                    message: String::from("Invalid Authorization header format"),
-                });
-            }
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Authorization header missing"),
-            })
-        }
-    };
-
-    let sub = match verify_token_and_get_sub(token) {
-        Some(s) => s,
-        None => {
-            return HttpResponse::Unauthorized().json(BaseResponse {
-                code: 401,
-                message: String::from("Invalid token"),
-            })
-        }
-    };
-
-    // Parse the `sub` value
-    let parsed_values: Vec<&str> = sub.split(',').collect();
-    if parsed_values.len() != 3 {
-        return HttpResponse::InternalServerError().json(BaseResponse {
-            code: 500,
-            message: String::from("Invalid sub format in token"),
-        });
-    }
-
-    let role: &str = parsed_values[1];
-
-    if role != "Admin" {
-        return HttpResponse::Unauthorized().json(BaseResponse {
-            code: 401,
-            message: String::from("Unauthorized!"),
-        });
-    }
-
     match ward::get_ward_by_id(ward_id, &client).await {
-        Some(_) => match ward::delete_ward(ward_id, &client).await {
+        Some(_) => match ward::delete_ward(ward_id, &auth.audit_context(&req), &mut client).await {
             Ok(()) => HttpResponse::Ok().json(BaseResponse {
                 code: 204,
                 message: String::from("Ward deleted successfully"),
             }),
             Err(e) => {
                 eprintln!("Ward deleting error: {}", e);
-                return HttpResponse::InternalServerError().json(BaseResponse {
+                HttpResponse::InternalServerError().json(BaseResponse {
                     code: 500,
                     message: String::from("Error deleting ward!"),
-                });
+                })
             }
         },
         None => HttpResponse::NotFound().json(BaseResponse {
@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Days, Local, NaiveDate};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::{
+        user, ward,
+        ward_assignment::{
+            self, DelegationRequest, TerritoryReportQuery, WardAssignmentListQuery,
+            WardAssignmentRequest,
+        },
+    },
+    utils::{
+        auth::Authorized,
+        common_struct::{BaseResponse, DataResponse, PaginationResponse},
+        permission::{TerritoryRead, TerritoryUpdate, ORDERS},
+    },
+};
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponse::BadRequest().json(BaseResponse {
+        code: 400,
+        message: String::from(message),
+    })
+}
+
+fn internal_error(err: tokio_postgres::Error) -> HttpResponse {
+    println!("Error updating ward assignments: {:?}", err);
+    HttpResponse::InternalServerError().json(BaseResponse {
+        code: 500,
+        message: String::from("Something went wrong!"),
+    })
+}
+
+// Current assignments with `active_on`, the full history of a ward or user
+// without it.
+#[get("/api/ward-assignments")]
+pub async fn get_ward_assignments(
+    _auth: Authorized<TerritoryRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<WardAssignmentListQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    match ward_assignment::get_ward_assignments(&query, &client).await {
+        Ok(item_result) => HttpResponse::Ok().json(PaginationResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: item_result.data,
+            total: item_result.total,
+            page: item_result.page,
+            per_page: item_result.per_page,
+            page_counts: item_result.page_counts,
+        }),
+        Err(err) => {
+            println!("Error retrieving ward assignments: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read ward assignments from database"),
+            })
+        }
+    }
+}
+
+#[post("/api/ward-assignments")]
+pub async fn add_ward_assignment(
+    auth: Authorized<TerritoryUpdate>,
+    req: HttpRequest,
+    body: web::Json<WardAssignmentRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let effective_from = body.effective_from.unwrap_or_else(today);
+    if body.effective_to.is_some_and(|to| to < effective_from) {
+        return bad_request("effective_to must not be before effective_from!");
+    }
+    if user::get_user_by_id(body.user_id, &client).await.is_none() {
+        return bad_request("User does not exist!");
+    }
+    if ward::get_ward_by_id(body.ward_id, &client).await.is_none() {
+        return bad_request("Ward does not exist!");
+    }
+    match ward_assignment::has_overlapping_assignment(
+        body.user_id,
+        body.ward_id,
+        effective_from,
+        body.effective_to,
+        &client,
+    )
+    .await
+    {
+        Ok(false) => {}
+        Ok(true) => return bad_request("User is already assigned to the ward in that period!"),
+        Err(err) => return internal_error(err),
+    }
+
+    match ward_assignment::add_ward_assignment(
+        body.user_id,
+        body.ward_id,
+        effective_from,
+        body.effective_to,
+        &auth.audit_context(&req),
+        &mut client,
+    )
+    .await
+    {
+        Ok(assignment_id) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Ward assigned successfully"),
+            data: Some(assignment_id),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EndAssignmentQuery {
+    // Last day the assignment covers. Defaults to yesterday, so it stops today.
+    pub effective_to: Option<NaiveDate>,
+}
+
+// Unassigns a ward. The row is kept as history unless it never took effect.
+#[delete("/api/ward-assignments/{assignment_id}")]
+pub async fn end_ward_assignment(
+    auth: Authorized<TerritoryUpdate>,
+    req: HttpRequest,
+    path: web::Path<i32>,
+    query: web::Query<EndAssignmentQuery>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    let assignment =
+        match ward_assignment::get_ward_assignment_by_id(path.into_inner(), &client).await {
+            Ok(Some(assignment)) => assignment,
+            Ok(None) => {
+                return HttpResponse::NotFound().json(BaseResponse {
+                    code: 404,
+                    message: String::from("Ward assignment not found!"),
+                })
+            }
+            Err(err) => return internal_error(err),
+        };
+    let last_day = query.effective_to.unwrap_or_else(|| today() - Days::new(1));
+    if assignment.effective_to.is_some_and(|to| to <= last_day) {
+        return bad_request("Ward assignment already ends by then!");
+    }
+
+    match ward_assignment::end_ward_assignment(&assignment, last_day, &auth.audit_context(&req), &mut client).await {
+        Ok(()) => HttpResponse::Ok().json(BaseResponse {
+            code: 200,
+            message: String::from("Ward unassigned successfully"),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+// Hands a user's wards to a colleague for a while, e.g. during leave.
+#[post("/api/ward-assignments/delegations")]
+pub async fn add_delegation(
+    auth: Authorized<TerritoryUpdate>,
+    req: HttpRequest,
+    body: web::Json<DelegationRequest>,
+    data: web::Data<Arc<Mutex<Client>>>,
+) -> HttpResponse {
+    let mut client = data.lock().await;
+    if body.effective_to < body.effective_from {
+        return bad_request("effective_to must not be before effective_from!");
+    }
+    if body.from_user_id == body.to_user_id {
+        return bad_request("A user cannot cover for themselves!");
+    }
+    if user::get_user_by_id(body.to_user_id, &client)
+        .await
+        .is_none()
+    {
+        return bad_request("User does not exist!");
+    }
+
+    let assigned = match ward_assignment::get_assigned_ward_ids(
+        body.from_user_id,
+        body.effective_from,
+        &client,
+    )
+    .await
+    {
+        Ok(ward_ids) => ward_ids,
+        Err(err) => return internal_error(err),
+    };
+    let ward_ids = match &body.ward_ids {
+        Some(ward_ids) => {
+            if let Some(ward_id) = ward_ids.iter().find(|w| !assigned.contains(w)) {
+                return HttpResponse::BadRequest().json(BaseResponse {
+                    code: 400,
+                    message: format!("Ward {} is not assigned to the user on that day!", ward_id),
+                });
+            }
+            ward_ids.clone()
+        }
+        None => assigned,
+    };
+    if ward_ids.is_empty() {
+        return bad_request("The user has no wards to hand over on that day!");
+    }
+
+    match ward_assignment::add_delegation(&body, &ward_ids, &auth.audit_context(&req), &mut client).await {
+        Ok(assignment_ids) => HttpResponse::Created().json(DataResponse {
+            code: 201,
+            message: String::from("Wards delegated successfully"),
+            data: Some(assignment_ids),
+        }),
+        Err(err) => internal_error(err),
+    }
+}
+
+#[get("/api/reports/territory")]
+pub async fn get_territory_report(
+    auth: Authorized<TerritoryRead>,
+    data: web::Data<Arc<Mutex<Client>>>,
+    query: web::Query<TerritoryReportQuery>,
+) -> impl Responder {
+    let client = data.lock().await;
+    // Like the order list, limited to the caller's team without `orders:read_all`.
+    let scope_user_ids = auth.visible_user_ids(ORDERS, &client).await;
+    if query.to_date < query.from_date {
+        return bad_request("to_date must not be before from_date!");
+    }
+    match ward_assignment::get_territory_report(&query, scope_user_ids.as_deref(), &client).await {
+        Ok(rows) => HttpResponse::Ok().json(DataResponse {
+            code: 200,
+            message: String::from("Successful."),
+            data: Some(rows),
+        }),
+        Err(err) => {
+            println!("Error retrieving territory report: {:?}", err);
+            HttpResponse::InternalServerError().json(BaseResponse {
+                code: 500,
+                message: String::from("Error trying to read territory report from database"),
+            })
+        }
+    }
+}
@@ -21,8 +21,11 @@ async fn main() -> std::io::Result<()> {
         .parse()
         .expect("Port must be number");
     let conn = env::var("DB_CONNECTION").expect("DB_CONNECTION must be set");
+    // Load the signing keys up front so that a bad key stops startup.
+    utils::keyring::keyring();
     let (client, connection) = tokio_postgres::connect(conn.as_str(), NoTls).await.unwrap();
     let client = Arc::new(Mutex::new(client));
+    tokio::spawn(utils::push::run_discount_notifier(client.clone()));
 
     // The connection object performs the actual communication with the database,
     // so spawn it off to run on its own.
@@ -33,7 +36,7 @@ async fn main() -> std::io::Result<()> {
     });
 
     HttpServer::new(move || {
-        if !std::fs::metadata("./images").is_ok() {
+        if std::fs::metadata("./images").is_err() {
             if let Err(err) = std::fs::create_dir_all("./images") {
                 println!("{:?}", err);
             }
@@ -1,10 +1,27 @@
 pub mod brand;
 pub mod category;
 pub mod order;
+pub mod payment;
+pub mod receivable;
 pub mod user;
+pub mod user_import;
 pub mod shop;
+pub mod shop_proposal;
+pub mod shop_visit;
+pub mod region;
 pub mod township;
 pub mod ward;
+pub mod ward_assignment;
 pub mod discount;
 pub mod product;
 pub mod product_price;
+pub mod api_key;
+pub mod audit;
+pub mod device;
+pub mod holiday;
+pub mod login_throttle;
+pub mod mfa;
+pub mod role;
+pub mod route;
+pub mod session;
+pub mod trash;
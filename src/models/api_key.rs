@@ -0,0 +1,268 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio_postgres::{types::ToSql, Client, Error};
+use uuid::Uuid;
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub api_key_id: i32,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub rate_limit: i32,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+// Keys look like `dk_<prefix>_<secret>`. The prefix is stored in clear to find
+// the key and to tell keys apart in listings; only a SHA-256 hash of the whole
+// key is kept.
+pub fn generate_api_key() -> (String, String) {
+    let prefix = Uuid::new_v4().simple().to_string()[..8].to_string();
+    let key = format!(
+        "dk_{}_{}{}",
+        prefix,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    );
+    (prefix, key)
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+pub fn parse_api_key_prefix(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix("dk_")?;
+    let (prefix, secret) = rest.split_once('_')?;
+    if prefix.is_empty() || secret.is_empty() {
+        return None;
+    }
+    Some(prefix)
+}
+
+fn to_api_key(row: &tokio_postgres::Row) -> ApiKey {
+    ApiKey {
+        api_key_id: row.get("api_key_id"),
+        name: row.get("name"),
+        key_prefix: row.get("key_prefix"),
+        scopes: row.get("scopes"),
+        rate_limit: row.get("rate_limit"),
+        last_used_at: row.get("last_used_at"),
+        revoked_at: row.get("revoked_at"),
+        created_at: row.get("created_at"),
+    }
+}
+
+// The key a request presented, as far as authentication needs to know it.
+pub struct ApiKeyCredentials {
+    pub api_key_id: i32,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub rate_limit: i32,
+    // Requests made with the key are done on this user's behalf.
+    pub created_by: Option<i32>,
+}
+
+// A key stops working once its creator is deleted or their role may no longer
+// create keys.
+pub async fn get_active_api_key(prefix: &str, client: &Client) -> Result<Option<ApiKeyCredentials>, Error> {
+    let row = client
+        .query_opt(
+            "select k.api_key_id, k.key_hash, k.scopes, k.rate_limit, k.created_by from api_keys k
+            join users u on u.user_id = k.created_by and u.deleted_at is null
+            where k.key_prefix = $1 and k.revoked_at is null
+            and exists (select 1 from role_permissions rp
+                join roles r on r.role_id = rp.role_id
+                join permissions p on p.permission_id = rp.permission_id
+                where r.role_name = u.role and r.deleted_at is null and p.resource = 'api_keys' and p.action = 'create')",
+            &[&prefix],
+        )
+        .await?;
+    Ok(row.map(|row| ApiKeyCredentials {
+        api_key_id: row.get("api_key_id"),
+        key_hash: row.get("key_hash"),
+        scopes: row.get("scopes"),
+        rate_limit: row.get("rate_limit"),
+        created_by: row.get("created_by"),
+    }))
+}
+
+pub async fn get_api_keys(client: &Client) -> Result<Vec<ApiKey>, Error> {
+    let rows = client
+        .query(
+            "select api_key_id, name, key_prefix, scopes, rate_limit, last_used_at, revoked_at, created_at from api_keys order by created_at desc",
+            &[],
+        )
+        .await?;
+    Ok(rows.iter().map(to_api_key).collect())
+}
+
+pub async fn get_api_key_by_id(api_key_id: i32, client: &Client) -> Option<ApiKey> {
+    let result = client
+        .query_one(
+            "select api_key_id, name, key_prefix, scopes, rate_limit, last_used_at, revoked_at, created_at from api_keys where api_key_id = $1",
+            &[&api_key_id],
+        )
+        .await;
+
+    match result {
+        Ok(row) => Some(to_api_key(&row)),
+        Err(_) => None,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub rate_limit: Option<i32>,
+}
+
+// Requests per minute a key may make unless a limit is given on creation.
+pub const DEFAULT_RATE_LIMIT: i32 = 60;
+
+pub async fn add_api_key(
+    data: &ApiKeyRequest,
+    key_prefix: &str,
+    key_hash: &str,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let rate_limit = data.rate_limit.unwrap_or(DEFAULT_RATE_LIMIT);
+    let transaction = client.transaction().await?;
+    let api_key_id: i32 = transaction
+        .query_one(
+            "insert into api_keys (name, key_prefix, key_hash, scopes, rate_limit, created_by) values ($1, $2, $3, $4, $5, $6) returning api_key_id",
+            &[&data.name, &key_prefix, &key_hash, &data.scopes, &rate_limit, &context.actor_id],
+        )
+        .await?
+        .get("api_key_id");
+    audit::record(context, "create", &audit::API_KEY, api_key_id, None, &transaction).await?;
+    transaction.commit().await?;
+    Ok(api_key_id)
+}
+
+pub async fn revoke_api_key(
+    api_key_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::API_KEY, api_key_id, &transaction).await?;
+    transaction
+        .execute(
+            "update api_keys set revoked_at = CURRENT_TIMESTAMP where api_key_id = $1 and revoked_at is null",
+            &[&api_key_id],
+        )
+        .await?;
+    audit::record(context, "delete", &audit::API_KEY, api_key_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn count_recent_requests(api_key_id: i32, client: &Client) -> Result<i64, Error> {
+    let row = client
+        .query_one(
+            "select count(*) total from api_key_requests where api_key_id = $1 and created_at > CURRENT_TIMESTAMP - interval '1 minute'",
+            &[&api_key_id],
+        )
+        .await?;
+    Ok(row.get("total"))
+}
+
+pub async fn log_request(
+    api_key_id: i32,
+    method: &str,
+    path: &str,
+    allowed: bool,
+    client: &Client,
+) -> Result<(), Error> {
+    client
+        .execute(
+            "insert into api_key_requests (api_key_id, method, path, allowed) values ($1, $2, $3, $4)",
+            &[&api_key_id, &method, &path, &allowed],
+        )
+        .await?;
+    client
+        .execute(
+            "update api_keys set last_used_at = CURRENT_TIMESTAMP where api_key_id = $1",
+            &[&api_key_id],
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyRequestLog {
+    pub request_id: i64,
+    pub method: String,
+    pub path: String,
+    pub allowed: bool,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn get_api_key_requests(
+    api_key_id: i32,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    client: &Client,
+) -> Result<PaginationResult<ApiKeyRequestLog>, Error> {
+    let base_query = "from api_key_requests r where r.api_key_id = $1".to_string();
+    let params: Vec<Box<dyn ToSql + Sync>> = vec![Box::new(api_key_id)];
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: "r.request_id, r.method, r.path, r.allowed, r.created_at",
+        base_query: &base_query,
+        search_columns: vec![],
+        search: None,
+        order_options: Some("r.created_at desc"),
+        page,
+        per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let requests = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| ApiKeyRequestLog {
+            request_id: row.get("request_id"),
+            method: row.get("method"),
+            path: row.get("path"),
+            allowed: row.get("allowed"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+
+    Ok(PaginationResult {
+        data: requests,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
@@ -0,0 +1,269 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_postgres::{types::ToSql, Client, Error, Transaction};
+
+use crate::utils::{
+    common_struct::PaginationResult,
+    sql::{generate_pagination_query, PaginationOptions},
+};
+
+// Who is making a change, passed down from the handler to the model function
+// that writes the audit event.
+pub struct AuditContext {
+    pub actor_id: i32,
+    pub api_key_id: Option<i32>,
+    pub request_id: String,
+}
+
+// A table whose changes are audited. `extra` adds related rows to the
+// snapshot, e.g. the prices a discount applies to.
+pub struct Entity {
+    pub name: &'static str,
+    pub table: &'static str,
+    pub id_column: &'static str,
+    extra: &'static str,
+}
+
+macro_rules! entity {
+    ($const_name:ident, $name:expr, $table:expr, $id_column:expr) => {
+        entity!($const_name, $name, $table, $id_column, "");
+    };
+    ($const_name:ident, $name:expr, $table:expr, $id_column:expr, $extra:expr) => {
+        pub const $const_name: Entity = Entity {
+            name: $name,
+            table: $table,
+            id_column: $id_column,
+            extra: $extra,
+        };
+    };
+}
+
+entity!(BRAND, "brand", "brands", "brand_id");
+entity!(CATEGORY, "category", "categories", "category_id");
+entity!(
+    PRODUCT,
+    "product",
+    "products",
+    "product_id",
+    "jsonb_build_object('category_ids', (select coalesce(jsonb_agg(pc.category_id), '[]') from product_categories pc where pc.product_id = t.product_id))"
+);
+entity!(PRICE, "price", "product_prices", "price_id");
+entity!(
+    DISCOUNT,
+    "discount",
+    "discounts",
+    "discount_id",
+    "jsonb_build_object('price_ids', (select coalesce(jsonb_agg(pd.price_id), '[]') from product_discounts pd where pd.discount_id = t.discount_id and pd.deleted_at is null))"
+);
+entity!(
+    SHOP,
+    "shop",
+    "shops",
+    "shop_id",
+    "jsonb_build_object('weekday_ids', (select coalesce(jsonb_agg(sw.weekday_id), '[]') from shop_weekdays sw where sw.shop_id = t.shop_id))"
+);
+entity!(REGION, "region", "regions", "region_id");
+entity!(TOWNSHIP, "township", "townships", "township_id");
+entity!(WARD, "ward", "wards", "ward_id");
+entity!(
+    USER,
+    "user",
+    "users",
+    "user_id",
+    "jsonb_build_object('ward_ids', (select coalesce(jsonb_agg(uw.ward_id), '[]') from user_wards uw where uw.user_id = t.user_id), 'mfa_enabled', exists (select 1 from user_mfa m where m.user_id = t.user_id and m.enabled_at is not null))"
+);
+entity!(
+    ROLE,
+    "role",
+    "roles",
+    "role_id",
+    "jsonb_build_object('permission_ids', (select coalesce(jsonb_agg(rp.permission_id), '[]') from role_permissions rp where rp.role_id = t.role_id))"
+);
+entity!(API_KEY, "api_key", "api_keys", "api_key_id");
+entity!(
+    ORDER,
+    "order",
+    "orders",
+    "order_id",
+    "jsonb_build_object('details', (select coalesce(jsonb_agg(to_jsonb(od)), '[]') from order_details od where od.order_id = t.order_id))"
+);
+entity!(PAYMENT, "payment", "payments", "payment_id");
+entity!(HOLIDAY, "holiday", "holidays", "holiday_id");
+entity!(SHOP_PROPOSAL, "shop_proposal", "shop_proposals", "proposal_id");
+entity!(SHOP_VISIT, "shop_visit", "shop_visits", "visit_id");
+entity!(
+    WARD_ASSIGNMENT,
+    "ward_assignment",
+    "ward_assignments",
+    "assignment_id"
+);
+
+// The row as JSON, without secrets. `None` if it does not exist.
+pub async fn snapshot(
+    entity: &Entity,
+    entity_id: i32,
+    transaction: &Transaction<'_>,
+) -> Result<Option<Value>, Error> {
+    let extra = if entity.extra.is_empty() {
+        String::new()
+    } else {
+        format!(" || {}", entity.extra)
+    };
+    let row = transaction
+        .query_opt(
+            &format!(
+                "select (to_jsonb(t) - 'password' - 'key_hash'){extra} snapshot from {} t where t.{} = $1",
+                entity.table, entity.id_column
+            ),
+            &[&entity_id],
+        )
+        .await?;
+    Ok(row.map(|row| row.get("snapshot")))
+}
+
+// Writes an event for a change made in `transaction`, with `before` as the
+// snapshot taken ahead of it. The after state is read here.
+pub async fn record(
+    context: &AuditContext,
+    action: &str,
+    entity: &Entity,
+    entity_id: i32,
+    before: Option<Value>,
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    let after = snapshot(entity, entity_id, transaction).await?;
+    transaction
+        .execute(
+            "insert into audit_events (actor_id, api_key_id, request_id, action, entity_type, entity_id, before, after) values ($1, $2, $3, $4, $5, $6, $7, $8)",
+            &[
+                &context.actor_id,
+                &context.api_key_id,
+                &context.request_id,
+                &action,
+                &entity.name,
+                &entity_id,
+                &before,
+                &after,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEvent {
+    pub event_id: i64,
+    pub actor_id: i32,
+    pub actor_name: String,
+    pub api_key_id: Option<i32>,
+    pub request_id: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditListQuery {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i32>,
+    pub actor_id: Option<i32>,
+    pub action: Option<String>,
+    pub request_id: Option<String>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+pub async fn get_audit_events(
+    query: &AuditListQuery,
+    client: &Client,
+) -> Result<PaginationResult<AuditEvent>, Error> {
+    let mut base_query =
+        "from audit_events e left join users u on u.user_id = e.actor_id where 1 = 1".to_string();
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    if let Some(entity_type) = &query.entity_type {
+        params.push(Box::new(entity_type.clone()));
+        base_query = format!("{base_query} and e.entity_type = ${}", params.len());
+    }
+    if let Some(entity_id) = query.entity_id {
+        params.push(Box::new(entity_id));
+        base_query = format!("{base_query} and e.entity_id = ${}", params.len());
+    }
+    if let Some(actor_id) = query.actor_id {
+        params.push(Box::new(actor_id));
+        base_query = format!("{base_query} and e.actor_id = ${}", params.len());
+    }
+    if let Some(action) = &query.action {
+        params.push(Box::new(action.clone()));
+        base_query = format!("{base_query} and e.action = ${}", params.len());
+    }
+    if let Some(request_id) = &query.request_id {
+        params.push(Box::new(request_id.clone()));
+        base_query = format!("{base_query} and e.request_id = ${}", params.len());
+    }
+    if let Some(from_date) = query.from_date {
+        params.push(Box::new(from_date));
+        base_query = format!("{base_query} and e.created_at::date >= ${}", params.len());
+    }
+    if let Some(to_date) = query.to_date {
+        params.push(Box::new(to_date));
+        base_query = format!("{base_query} and e.created_at::date <= ${}", params.len());
+    }
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: "e.event_id, e.actor_id, coalesce(u.full_name, '') actor_name, e.api_key_id, e.request_id, e.action, e.entity_type, e.entity_id, e.before, e.after, e.created_at",
+        base_query: &base_query,
+        search_columns: vec![],
+        search: None,
+        order_options: Some("e.event_id desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let events = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| AuditEvent {
+            event_id: row.get("event_id"),
+            actor_id: row.get("actor_id"),
+            actor_name: row.get("actor_name"),
+            api_key_id: row.get("api_key_id"),
+            request_id: row.get("request_id"),
+            action: row.get("action"),
+            entity_type: row.get("entity_type"),
+            entity_id: row.get("entity_id"),
+            before: row.get("before"),
+            after: row.get("after"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+
+    Ok(PaginationResult {
+        data: events,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
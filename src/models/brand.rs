@@ -2,9 +2,12 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,17 +21,17 @@ pub async fn get_brands(
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Brand>, Error> {
     let base_query =
         "from brands b where b.deleted_at is null".to_string();
     let params: Vec<Box<dyn ToSql + Sync>> = vec![];
 
-    let order_options = if role == "Distributor" {
-        "brand_name"
-    } else {
+    let order_options = if newest_first {
         "b.created_at desc"
+    } else {
+        "brand_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
@@ -36,7 +39,7 @@ pub async fn get_brands(
         base_query: &base_query,
         search_columns: vec!["b.brand_id::varchar", "b.brand_name"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -49,9 +52,9 @@ pub async fn get_brands(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -82,14 +85,19 @@ pub struct BrandRequest {
 
 pub async fn add_brand(
     data: &BrandRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
-        .execute(
-            "insert into brands (brand_name) values ($1)",
+    let transaction = client.transaction().await?;
+    let brand_id: i32 = transaction
+        .query_one(
+            "insert into brands (brand_name) values ($1) returning brand_id",
             &[&data.brand_name],
         )
-        .await?;
+        .await?
+        .get("brand_id");
+    audit::record(context, "create", &audit::BRAND, brand_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
@@ -114,28 +122,36 @@ pub async fn get_brand_by_id(brand_id: i32, client: &Client) -> Option<Brand> {
 pub async fn update_brand(
     brand_id: i32,
     data: &BrandRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::BRAND, brand_id, &transaction).await?;
+    transaction
         .execute(
             "update brands set brand_name = $1 where brand_id = $2",
             &[&data.brand_name, &brand_id],
         )
         .await?;
-
+    audit::record(context, "update", &audit::BRAND, brand_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn delete_brand(
     brand_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::BRAND, brand_id, &transaction).await?;
+    transaction
         .execute(
             "update brands set deleted_at = CURRENT_TIMESTAMP where brand_id = $1",
             &[&brand_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::BRAND, brand_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
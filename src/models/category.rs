@@ -2,9 +2,12 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,17 +21,17 @@ pub async fn get_categories(
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Category>, Error> {
     let base_query =
         "from categories c where c.deleted_at is null".to_string();
     let params: Vec<Box<dyn ToSql + Sync>> = vec![];
 
-    let order_options = if role == "Distributor" {
-        "category_name"
-    } else {
+    let order_options = if newest_first {
         "c.created_at desc"
+    } else {
+        "category_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
@@ -36,7 +39,7 @@ pub async fn get_categories(
         base_query: &base_query,
         search_columns: vec!["c.category_id::varchar", "c.category_name"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -49,9 +52,9 @@ pub async fn get_categories(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -82,14 +85,19 @@ pub struct CategoryRequest {
 
 pub async fn add_category(
     data: &CategoryRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
-        .execute(
-            "insert into categories (category_name) values ($1)",
+    let transaction = client.transaction().await?;
+    let category_id: i32 = transaction
+        .query_one(
+            "insert into categories (category_name) values ($1) returning category_id",
             &[&data.category_name],
         )
-        .await?;
+        .await?
+        .get("category_id");
+    audit::record(context, "create", &audit::CATEGORY, category_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
@@ -114,28 +122,36 @@ pub async fn get_category_by_id(category_id: i32, client: &Client) -> Option<Cat
 pub async fn update_category(
     category_id: i32,
     data: &CategoryRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::CATEGORY, category_id, &transaction).await?;
+    transaction
         .execute(
             "update categories set category_name = $1 where category_id = $2",
             &[&data.category_name, &category_id],
         )
         .await?;
-
+    audit::record(context, "update", &audit::CATEGORY, category_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn delete_category(
     category_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::CATEGORY, category_id, &transaction).await?;
+    transaction
         .execute(
             "update categories set deleted_at = CURRENT_TIMESTAMP where category_id = $1",
             &[&category_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::CATEGORY, category_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
@@ -0,0 +1,77 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Error};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Device {
+    pub device_id: i32,
+    pub platform: String,
+    pub created_at: NaiveDateTime,
+    pub last_seen_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceRequest {
+    pub token: String,
+    pub platform: Option<String>,
+}
+
+// Registers an FCM token for the user. A token belongs to one app install, so
+// if someone else logged in on the same device before it moves to this user.
+pub async fn add_device(data: &DeviceRequest, user_id: i32, client: &Client) -> Result<i32, Error> {
+    let platform = data.platform.clone().unwrap_or_default();
+    let row = client
+        .query_one(
+            "insert into user_devices (user_id, token, platform) values ($1, $2, $3)
+            on conflict (token) do update set user_id = $1, platform = $3, last_seen_at = CURRENT_TIMESTAMP
+            returning device_id",
+            &[&user_id, &data.token, &platform],
+        )
+        .await?;
+    Ok(row.get("device_id"))
+}
+
+pub async fn get_devices(user_id: i32, client: &Client) -> Result<Vec<Device>, Error> {
+    let rows = client
+        .query(
+            "select device_id, platform, created_at, last_seen_at from user_devices where user_id = $1 order by last_seen_at desc",
+            &[&user_id],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| Device {
+            device_id: row.get("device_id"),
+            platform: row.get("platform"),
+            created_at: row.get("created_at"),
+            last_seen_at: row.get("last_seen_at"),
+        })
+        .collect())
+}
+
+pub async fn delete_device(device_id: i32, user_id: i32, client: &Client) -> Result<bool, Error> {
+    let deleted = client
+        .execute(
+            "delete from user_devices where device_id = $1 and user_id = $2",
+            &[&device_id, &user_id],
+        )
+        .await?;
+    Ok(deleted == 1)
+}
+
+pub async fn get_tokens_for_users(user_ids: &[i32], client: &Client) -> Result<Vec<String>, Error> {
+    let rows = client
+        .query(
+            "select d.token from user_devices d join users u on u.user_id = d.user_id where d.user_id = any($1) and u.deleted_at is null",
+            &[&user_ids],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("token")).collect())
+}
+
+// Tokens FCM reported as no longer registered.
+pub async fn delete_tokens(tokens: &[String], client: &Client) -> Result<u64, Error> {
+    client
+        .execute("delete from user_devices where token = any($1)", &[&tokens])
+        .await
+}
@@ -2,9 +2,12 @@ use chrono::{NaiveDateTime,NaiveDate};
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)] // Add Debug derive
@@ -25,34 +28,41 @@ pub struct Discount {
     pub min_quantity: i32,
     pub max_quantity: i32,
     pub conditions: String,
+    // Applies only to shops of this channel; to every shop when unset.
+    pub channel: Option<String>,
     pub created_at: NaiveDateTime,
     pub product_prices: Vec<ProductPrice>,
 
 }
 
 pub async fn get_discounts(
+    channel: Option<&str>,
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Discount>, Error> {
-    let base_query =
+    let mut base_query =
         "from discounts d where d.deleted_at is null".to_string();
-    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(channel) = channel {
+        params.push(Box::new(channel.to_string()));
+        base_query = format!("{base_query} and (d.channel is null or d.channel = ${})", params.len());
+    }
 
-    let order_options = if role == "Distributor" {
-        "discount_name"
-    } else {
+    let order_options = if newest_first {
         "d.created_at desc"
+    } else {
+        "discount_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "d.discount_id,d.discount_name, d.discount_type,d.discount_value::text as discount_value,d.start_date::text as start_date,d.end_date::text as end_date,d.min_quantity,d.max_quantity,d.conditions,d.created_at",
+        select_columns: "d.discount_id,d.discount_name, d.discount_type,d.discount_value::text as discount_value,d.start_date::text as start_date,d.end_date::text as end_date,d.min_quantity,d.max_quantity,d.conditions,d.channel,d.created_at",
         base_query: &base_query,
         search_columns: vec!["d.discount_id::varchar", "d.discount_name","d.discount_type"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -65,9 +75,9 @@ pub async fn get_discounts(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
     let rows = client
@@ -85,13 +95,13 @@ pub async fn get_discounts(
                 from product_discounts pd 
                 join discounts d on d.discount_id = pd.discount_id
                 join product_prices pp on pp.price_id = pd.price_id
-                join products p on p.product_id = pp.product_id where pd.discount_id = $1",
+                join products p on p.product_id = pp.product_id where pd.discount_id = $1 and pd.deleted_at is null",
                 &[&discount_id],
             )
             .await?;
             discounts.push(
                 Discount {
-                    discount_id: discount_id,
+                    discount_id,
                     discount_name: row.get("discount_name"),
                     discount_type: row.get("discount_type"),
                     discount_value: discount_value_str.parse().unwrap(),
@@ -100,6 +110,7 @@ pub async fn get_discounts(
                     min_quantity: row.get("min_quantity"),
                     max_quantity: row.get("max_quantity"),
                     conditions: row.get("conditions"),
+                    channel: row.get("channel"),
                     created_at: row.get("created_at"),
                     product_prices: product_price_rows
                         .iter()
@@ -134,38 +145,44 @@ pub struct DiscountRequest {
     pub max_quantity: i32,
     pub conditions: String,
     pub price_ids: Vec<i32>,
+    // Left out, an update keeps the discount's channel; an empty string
+    // applies it to every shop.
+    pub channel: Option<String>,
 
 }
 
 pub async fn add_discount(
     data: &DiscountRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    
-   let discount_insert_query = format!("insert into discounts (discount_name,discount_type,discount_value,start_date,end_date,min_quantity,max_quantity,conditions) values ($1,$2,{},$3,$4,$5,$6,$7)  RETURNING discount_id",data.discount_value);
-   let discount_id: i32 = client
+    let transaction = client.transaction().await?;
+    let discount_insert_query = format!("insert into discounts (discount_name,discount_type,discount_value,start_date,end_date,min_quantity,max_quantity,conditions,channel) values ($1,$2,{},$3,$4,$5,$6,$7,nullif($8::text, ''))  RETURNING discount_id",data.discount_value);
+   let discount_id: i32 = transaction
    .query_one(
     &discount_insert_query,
-       &[&data.discount_name, &data.discount_type,  &data.start_date, &data.end_date, &data.min_quantity, &data.max_quantity, &data.conditions ],
+       &[&data.discount_name, &data.discount_type,  &data.start_date, &data.end_date, &data.min_quantity, &data.max_quantity, &data.conditions, &data.channel ],
    )
    .await?
    .get("discount_id"); 
     let product_discounts_insert_query = "insert into product_discounts (price_id, discount_id) values ($1, $2)";
     for price_id in &data.price_ids {
-        client.execute(product_discounts_insert_query, &[&price_id,&discount_id]).await?;
+        transaction.execute(product_discounts_insert_query, &[&price_id,&discount_id]).await?;
     }
+    audit::record(context, "create", &audit::DISCOUNT, discount_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn get_discount_by_id(discount_id: i32, client: &Client) -> Option<Discount> {
-    let result = client.query_one("select discount_id,discount_name, discount_type, discount_value::text as discount_value, start_date::text as start_date, end_date::text as end_date, min_quantity, max_quantity, conditions, created_at from discounts  where deleted_at is null  and discount_id = $1", &[&discount_id]).await;
+    let result = client.query_one("select discount_id,discount_name, discount_type, discount_value::text as discount_value, start_date::text as start_date, end_date::text as end_date, min_quantity, max_quantity, conditions, channel, created_at from discounts  where deleted_at is null  and discount_id = $1", &[&discount_id]).await;
     let product_price_rows = match client
         .query(
             "select pd.price_id,p.product_id, p.product_name
             from product_discounts pd 
             join discounts d on d.discount_id = pd.discount_id
             join product_prices pp on pp.price_id = pd.price_id
-            join products p on p.product_id = pp.product_id where pd.discount_id = $1",
+            join products p on p.product_id = pp.product_id where pd.discount_id = $1 and pd.deleted_at is null",
             &[&discount_id],
         )
         .await
@@ -192,6 +209,7 @@ pub async fn get_discount_by_id(discount_id: i32, client: &Client) -> Option<Dis
                 min_quantity: row.get("min_quantity"),
                 max_quantity: row.get("max_quantity"),
                 conditions: row.get("conditions"),
+                channel: row.get("channel"),
                 created_at: row.get("created_at"),
                 product_prices: product_price_rows
                         .iter()
@@ -214,11 +232,16 @@ pub async fn get_discount_by_id(discount_id: i32, client: &Client) -> Option<Dis
 pub async fn update_discount(
     data: &DiscountRequest,
     discount_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::DISCOUNT, discount_id, &transaction).await?;
+    // Moving the start date means distributors hear about the discount again.
  let query = format!("update discounts set discount_name = $1, discount_type = $2, discount_value = {},
- start_date = $3, end_date = $4, min_quantity = $5, max_quantity = $6, conditions = $7 where discount_id = $8",data.discount_value);
-    client
+ notified_at = case when start_date = $3 then notified_at else null end,
+ start_date = $3, end_date = $4, min_quantity = $5, max_quantity = $6, conditions = $7, channel = case when $8::text is null then channel else nullif($8, '') end where discount_id = $9",data.discount_value);
+    transaction
         .execute(
             &query,
             &[
@@ -229,36 +252,99 @@ pub async fn update_discount(
                 &data.min_quantity,
                 &data.max_quantity,
                 &data.conditions,
+                &data.channel,
                 &discount_id,
             ],
         )
         .await?;
-    client.execute("delete from product_discounts where discount_id = $1",&[&discount_id],).await?;
+    transaction.execute("delete from product_discounts where discount_id = $1",&[&discount_id],).await?;
 
     let product_discounts_insert_query = "insert into product_discounts (price_id, discount_id) values ($1, $2) ";
     for price_id in &data.price_ids {
-        client
+        transaction
             .execute(product_discounts_insert_query, &[&price_id, &discount_id])
             .await?;
     }
+    audit::record(context, "update", &audit::DISCOUNT, discount_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn delete_discount(
     discount_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::DISCOUNT, discount_id, &transaction).await?;
+    transaction
+        .execute(
+            "update discounts set deleted_at = CURRENT_TIMESTAMP where discount_id = $1 and deleted_at is null",
+            &[&discount_id],
+        )
+        .await?;
+    // Kept with the same timestamp as the discount so a restore brings them back.
+    transaction
         .execute(
-            "update discounts set deleted_at = CURRENT_TIMESTAMP where discount_id = $1",
+            "update product_discounts set deleted_at = CURRENT_TIMESTAMP where discount_id = $1 and deleted_at is null",
             &[&discount_id],
         )
         .await?;
+    audit::record(context, "delete", &audit::DISCOUNT, discount_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// A discount that has started but whose distributors were not told yet.
+pub struct StartedDiscount {
+    pub discount_id: i32,
+    pub discount_name: String,
+    // Distributors covering wards with shops that can buy the discounted
+    // prices, and anyone who has ordered the discounted products before.
+    pub user_ids: Vec<i32>,
+}
+
+pub async fn get_started_discounts(client: &Client) -> Result<Vec<StartedDiscount>, Error> {
+    let rows = client
+        .query(
+            "select d.discount_id, d.discount_name, coalesce((
+                select array_agg(distinct t.user_id) from (
+                    select uw.user_id from product_discounts pd
+                    join product_prices pp on pp.price_id = pd.price_id and pp.deleted_at is null
+                    join shops s on s.deleted_at is null and (pp.channel is null or s.channel = pp.channel)
+                    join user_wards uw on uw.ward_id = s.ward_id
+                    where pd.discount_id = d.discount_id and pd.deleted_at is null
+                    and (d.channel is null or s.channel = d.channel)
+                    union
+                    select o.user_id from product_discounts pd
+                    join product_prices pp on pp.price_id = pd.price_id
+                    join product_prices op on op.product_id = pp.product_id
+                    join order_details od on od.price_id = op.price_id and od.deleted_at is null
+                    join orders o on o.order_id = od.order_id and o.deleted_at is null
+                    where pd.discount_id = d.discount_id and pd.deleted_at is null
+                ) t join users u on u.user_id = t.user_id and u.deleted_at is null
+            ), '{}') user_ids
+            from discounts d
+            where d.deleted_at is null and d.notified_at is null and d.start_date <= CURRENT_DATE and (d.end_date is null or d.end_date >= CURRENT_DATE)",
+            &[],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| StartedDiscount {
+            discount_id: row.get("discount_id"),
+            discount_name: row.get("discount_name"),
+            user_ids: row.get("user_ids"),
+        })
+        .collect())
+}
+
+pub async fn mark_discount_notified(discount_id: i32, client: &Client) -> Result<(), Error> {
     client
-    .execute(
-        "delete from product_discounts where discount_id = $1",
-        &[&discount_id],
-    )
-    .await?;
+        .execute(
+            "update discounts set notified_at = CURRENT_TIMESTAMP where discount_id = $1",
+            &[&discount_id],
+        )
+        .await?;
     Ok(())
 }
@@ -0,0 +1,122 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error};
+
+use crate::models::audit::{self, AuditContext};
+
+// A day without shop visits; the route plan for it is empty.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Holiday {
+    pub holiday_id: i32,
+    pub holiday_date: NaiveDate,
+    pub holiday_name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayListQuery {
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+}
+
+pub async fn get_holidays(
+    query: &HolidayListQuery,
+    client: &Client,
+) -> Result<Vec<Holiday>, Error> {
+    let mut sql =
+        "select holiday_id, holiday_date, holiday_name, created_at from holidays where 1 = 1"
+            .to_string();
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(from_date) = query.from_date {
+        params.push(Box::new(from_date));
+        sql = format!("{sql} and holiday_date >= ${}", params.len());
+    }
+    if let Some(to_date) = query.to_date {
+        params.push(Box::new(to_date));
+        sql = format!("{sql} and holiday_date <= ${}", params.len());
+    }
+    sql += " order by holiday_date";
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    Ok(client
+        .query(&sql, &params_slice)
+        .await?
+        .iter()
+        .map(|row| Holiday {
+            holiday_id: row.get("holiday_id"),
+            holiday_date: row.get("holiday_date"),
+            holiday_name: row.get("holiday_name"),
+            created_at: row.get("created_at"),
+        })
+        .collect())
+}
+
+// The name of the holiday on `date`, if it is one.
+pub async fn get_holiday_name(date: NaiveDate, client: &Client) -> Result<Option<String>, Error> {
+    let row = client
+        .query_opt(
+            "select holiday_name from holidays where holiday_date = $1",
+            &[&date],
+        )
+        .await?;
+    Ok(row.map(|row| row.get("holiday_name")))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayRequest {
+    pub holiday_date: NaiveDate,
+    pub holiday_name: String,
+}
+
+pub async fn add_holiday(
+    data: &HolidayRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let transaction = client.transaction().await?;
+    let holiday_id: i32 = transaction
+        .query_one(
+            "insert into holidays (holiday_date, holiday_name) values ($1, $2) returning holiday_id",
+            &[&data.holiday_date, &data.holiday_name],
+        )
+        .await?
+        .get("holiday_id");
+    audit::record(
+        context,
+        "create",
+        &audit::HOLIDAY,
+        holiday_id,
+        None,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(holiday_id)
+}
+
+// Holidays are removed outright; the audit log keeps the record.
+pub async fn delete_holiday(
+    holiday_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<bool, Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::HOLIDAY, holiday_id, &transaction).await?;
+    if before.is_none() {
+        return Ok(false);
+    }
+    transaction
+        .execute("delete from holidays where holiday_id = $1", &[&holiday_id])
+        .await?;
+    audit::record(
+        context,
+        "delete",
+        &audit::HOLIDAY,
+        holiday_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -0,0 +1,157 @@
+use std::env;
+
+use chrono::{Duration, NaiveDateTime};
+use tokio_postgres::{Client, Error};
+
+// Failed logins are counted per username and per client IP. Once a key
+// reaches its limit it is locked, and every further failure doubles the
+// lockout up to `LOGIN_LOCKOUT_MAX_SECONDS`.
+pub fn username_key(username: &str) -> String {
+    format!("user:{}", username.to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn env_or(name: &str, default: i64) -> i64 {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn max_attempts(key: &str) -> i64 {
+    if key.starts_with("ip:") {
+        env_or("LOGIN_MAX_ATTEMPTS_PER_IP", 20)
+    } else {
+        env_or("LOGIN_MAX_ATTEMPTS", 5)
+    }
+}
+
+// Failures older than this no longer count towards a lockout.
+fn attempt_window() -> i64 {
+    env_or("LOGIN_ATTEMPT_WINDOW", 900)
+}
+
+// The failure count after one more failure at `now`. The window runs from the
+// last failure or the end of the lockout, whichever is later, so a lockout
+// longer than the window does not wipe the count and the backoff keeps
+// growing.
+fn next_failed_attempts(
+    failed_attempts: i64,
+    last_failed_at: NaiveDateTime,
+    locked_until: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+    window: i64,
+) -> i64 {
+    let since = locked_until.map_or(last_failed_at, |l| l.max(last_failed_at));
+    if now - since > Duration::seconds(window) {
+        1
+    } else {
+        failed_attempts + 1
+    }
+}
+
+fn lockout_seconds(failed_attempts: i64, max_attempts: i64) -> i64 {
+    if failed_attempts < max_attempts {
+        return 0;
+    }
+    let base = env_or("LOGIN_LOCKOUT_SECONDS", 60);
+    let max = env_or("LOGIN_LOCKOUT_MAX_SECONDS", 3600);
+    let exponent = (failed_attempts - max_attempts).min(30) as u32;
+    base.saturating_mul(2_i64.saturating_pow(exponent)).min(max)
+}
+
+// Seconds until the longest running lockout among `keys` ends, if any.
+pub async fn get_lockout_remaining(keys: &[String], client: &Client) -> Result<Option<i64>, Error> {
+    let row = client
+        .query_one(
+            "select ceil(max(extract(epoch from locked_until - CURRENT_TIMESTAMP)))::bigint remaining from login_throttles where throttle_key = any($1) and locked_until > CURRENT_TIMESTAMP",
+            &[&keys],
+        )
+        .await?;
+    Ok(row.get("remaining"))
+}
+
+pub async fn record_failure(key: &str, client: &mut Client) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute(
+            "insert into login_throttles (throttle_key, failed_attempts) values ($1, 0) on conflict (throttle_key) do nothing",
+            &[&key],
+        )
+        .await?;
+    let row = transaction
+        .query_one(
+            "select failed_attempts, coalesce(last_failed_at, CURRENT_TIMESTAMP::timestamp) last_failed_at, locked_until, CURRENT_TIMESTAMP::timestamp now
+            from login_throttles where throttle_key = $1 for update",
+            &[&key],
+        )
+        .await?;
+    let failed_attempts: i32 = row.get("failed_attempts");
+    let failed_attempts = next_failed_attempts(
+        failed_attempts as i64,
+        row.get("last_failed_at"),
+        row.get("locked_until"),
+        row.get("now"),
+        attempt_window(),
+    );
+    let seconds = lockout_seconds(failed_attempts, max_attempts(key)) as f64;
+    transaction
+        .execute(
+            "update login_throttles set failed_attempts = $2, last_failed_at = CURRENT_TIMESTAMP,
+            locked_until = case when $3::float8 > 0 then CURRENT_TIMESTAMP + make_interval(secs => $3) else locked_until end
+            where throttle_key = $1",
+            &[&key, &(failed_attempts as i32), &seconds],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn clear(key: &str, client: &Client) -> Result<u64, Error> {
+    client
+        .execute("delete from login_throttles where throttle_key = $1", &[&key])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> NaiveDateTime {
+        NaiveDateTime::UNIX_EPOCH + Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn doubles_lockout_up_to_the_maximum() {
+        assert_eq!(lockout_seconds(4, 5), 0);
+        assert_eq!(lockout_seconds(5, 5), 60);
+        assert_eq!(lockout_seconds(6, 5), 120);
+        assert_eq!(lockout_seconds(9, 5), 960);
+        assert_eq!(lockout_seconds(11, 5), 3600);
+        assert_eq!(lockout_seconds(100, 5), 3600);
+    }
+
+    #[test]
+    fn counts_failures_within_the_window() {
+        assert_eq!(next_failed_attempts(3, at(0), None, at(900), 900), 4);
+        assert_eq!(next_failed_attempts(3, at(0), None, at(901), 900), 1);
+    }
+
+    #[test]
+    fn keeps_counting_after_a_lockout_longer_than_the_window() {
+        // The 9th failure locked the key for 960 seconds.
+        let next = |now| next_failed_attempts(9, at(0), Some(at(960)), at(now), 900);
+        assert_eq!(next(961), 10);
+        assert_eq!(next(1860), 10);
+        assert_eq!(next(1861), 1);
+    }
+
+    #[test]
+    fn ignores_an_old_lockout() {
+        let next = next_failed_attempts(5, at(2000), Some(at(60)), at(2100), 900);
+        assert_eq!(next, 6);
+    }
+}
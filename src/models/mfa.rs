@@ -0,0 +1,195 @@
+use std::env;
+
+use sha2::{Digest, Sha256};
+use tokio_postgres::{Client, Error};
+
+use crate::models::audit::{self, AuditContext};
+
+pub struct UserMfa {
+    pub totp_secret: String,
+    pub enabled: bool,
+}
+
+// Roles listed in `MFA_REQUIRED_ROLES` (comma separated, e.g. "Admin") cannot
+// use the API until they have set up a second factor.
+pub fn is_mfa_required(role: &str) -> bool {
+    env::var("MFA_REQUIRED_ROLES")
+        .unwrap_or_default()
+        .split(',')
+        .any(|r| r.trim() == role)
+}
+
+// Recovery codes are stored hashed and compared without dashes or case, the
+// way people tend to type them back.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+pub async fn get_user_mfa(user_id: i32, client: &Client) -> Result<Option<UserMfa>, Error> {
+    let row = client
+        .query_opt(
+            "select totp_secret, enabled_at is not null enabled from user_mfa where user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    Ok(row.map(|row| UserMfa {
+        totp_secret: row.get("totp_secret"),
+        enabled: row.get("enabled"),
+    }))
+}
+
+pub async fn is_mfa_enabled(user_id: i32, client: &Client) -> Result<bool, Error> {
+    Ok(get_user_mfa(user_id, client)
+        .await?
+        .is_some_and(|mfa| mfa.enabled))
+}
+
+// Stores a secret that still has to be confirmed with a code. An enabled
+// secret is never replaced this way.
+pub async fn set_pending_secret(user_id: i32, secret: &str, client: &Client) -> Result<bool, Error> {
+    let updated = client
+        .execute(
+            "insert into user_mfa (user_id, totp_secret) values ($1, $2)
+            on conflict (user_id) do update set totp_secret = $2, last_used_step = null
+            where user_mfa.enabled_at is null",
+            &[&user_id, &secret],
+        )
+        .await?;
+    Ok(updated == 1)
+}
+
+pub async fn enable_mfa(
+    user_id: i32,
+    recovery_code_hashes: &[String],
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    transaction
+        .execute(
+            "update user_mfa set enabled_at = CURRENT_TIMESTAMP where user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    transaction
+        .execute("delete from user_recovery_codes where user_id = $1", &[&user_id])
+        .await?;
+    for code_hash in recovery_code_hashes {
+        transaction
+            .execute(
+                "insert into user_recovery_codes (user_id, code_hash) values ($1, $2)",
+                &[&user_id, &code_hash],
+            )
+            .await?;
+    }
+    audit::record(context, "update", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn disable_mfa(
+    user_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    transaction
+        .execute("delete from user_recovery_codes where user_id = $1", &[&user_id])
+        .await?;
+    transaction
+        .execute("delete from user_mfa where user_id = $1", &[&user_id])
+        .await?;
+    audit::record(context, "update", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Records that the code for `step` was used. Returns false if that step (or a
+// later one) was used before, so a code cannot be replayed.
+pub async fn use_step(user_id: i32, step: u64, client: &Client) -> Result<bool, Error> {
+    let step = step as i64;
+    let updated = client
+        .execute(
+            "update user_mfa set last_used_step = $2 where user_id = $1 and (last_used_step is null or last_used_step < $2)",
+            &[&user_id, &step],
+        )
+        .await?;
+    Ok(updated == 1)
+}
+
+pub async fn use_recovery_code(user_id: i32, code_hash: &str, client: &Client) -> Result<bool, Error> {
+    let updated = client
+        .execute(
+            "update user_recovery_codes set used_at = CURRENT_TIMESTAMP where user_id = $1 and code_hash = $2 and used_at is null",
+            &[&user_id, &code_hash],
+        )
+        .await?;
+    Ok(updated == 1)
+}
+
+pub struct MfaChallenge {
+    pub challenge_id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+}
+
+// Wrong codes a challenge tolerates before it is burnt.
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+pub async fn add_challenge(
+    user_id: i32,
+    token_hash: &str,
+    ttl_seconds: i64,
+    client: &Client,
+) -> Result<i32, Error> {
+    let ttl_seconds = ttl_seconds as f64;
+    let row = client
+        .query_one(
+            "insert into mfa_challenges (user_id, token_hash, expires_at) values ($1, $2, CURRENT_TIMESTAMP + make_interval(secs => $3)) returning challenge_id",
+            &[&user_id, &token_hash, &ttl_seconds],
+        )
+        .await?;
+    Ok(row.get("challenge_id"))
+}
+
+// A challenge that is unused, unexpired and has attempts left.
+pub async fn get_open_challenge(challenge_id: i32, client: &Client) -> Result<Option<MfaChallenge>, Error> {
+    let row = client
+        .query_opt(
+            "select challenge_id, user_id, token_hash from mfa_challenges where challenge_id = $1 and used_at is null and expires_at > CURRENT_TIMESTAMP and attempts < $2",
+            &[&challenge_id, &MAX_CHALLENGE_ATTEMPTS],
+        )
+        .await?;
+    Ok(row.map(|row| MfaChallenge {
+        challenge_id: row.get("challenge_id"),
+        user_id: row.get("user_id"),
+        token_hash: row.get("token_hash"),
+    }))
+}
+
+pub async fn record_challenge_attempt(challenge_id: i32, client: &Client) -> Result<(), Error> {
+    client
+        .execute(
+            "update mfa_challenges set attempts = attempts + 1 where challenge_id = $1",
+            &[&challenge_id],
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn close_challenge(challenge_id: i32, client: &Client) -> Result<bool, Error> {
+    let updated = client
+        .execute(
+            "update mfa_challenges set used_at = CURRENT_TIMESTAMP where challenge_id = $1 and used_at is null",
+            &[&challenge_id],
+        )
+        .await?;
+    Ok(updated == 1)
+}
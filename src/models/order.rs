@@ -2,15 +2,24 @@ use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::{
+        audit::{self, AuditContext},
+        shop_visit,
+    },
+    utils::{
+        common_struct::PaginationResult,
+        geo::{self, Boundary, Feature, FeatureCollection},
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Deserialize)]
 pub struct OrderRequest {
     pub shop_id: i32,
     pub order_details: Vec<OrderDetailRequest>,
+    // The shop visit the order is taken in.
+    pub visit_id: Option<i32>,
 }
 
 #[derive(Deserialize)]
@@ -19,9 +28,26 @@ pub struct OrderDetailRequest {
     pub quantity: i32,
 }
 
+// What the order will come to at current prices, as `add_order` totals it.
+pub async fn get_order_total(
+    order_details: &[OrderDetailRequest],
+    client: &Client,
+) -> Result<f64, Error> {
+    let price_ids: Vec<i32> = order_details.iter().map(|d| d.price_id).collect();
+    let quantities: Vec<i32> = order_details.iter().map(|d| d.quantity).collect();
+    let row = client
+        .query_one(
+            "select coalesce(sum(pp.price * d.quantity), 0.0)::float8 total from unnest($1::int[], $2::int[]) d(price_id, quantity) join product_prices pp on pp.price_id = d.price_id",
+            &[&price_ids, &quantities],
+        )
+        .await?;
+    Ok(row.get("total"))
+}
+
 pub async fn add_order(
     data: &OrderRequest,
     user_id: i32,
+    context: &AuditContext,
     client: &mut Client,
 ) -> Result<i32, Error> {
     let transaction = client.transaction().await?;
@@ -61,27 +87,30 @@ pub async fn add_order(
             )
             .await?;
 
-        // Get discount ID
-        let discount_id = match transaction
-            .query_one(
-                "SELECT discount_id::text FROM product_discounts WHERE price_id = $1 AND deleted_at IS NULL",
-                &[&order_detail.price_id],
+        // Get the running discount, preferring one kept for the shop's channel
+        // over one for every channel
+        let discount_id: Option<i32> = transaction
+            .query_opt(
+                "SELECT pd.discount_id FROM product_discounts pd JOIN discounts d ON d.discount_id = pd.discount_id
+                WHERE pd.price_id = $1 AND pd.deleted_at IS NULL AND d.deleted_at IS NULL
+                AND d.start_date <= CURRENT_DATE AND (d.end_date IS NULL OR d.end_date >= CURRENT_DATE)
+                AND (d.channel IS NULL OR d.channel = (SELECT channel FROM shops WHERE shop_id = $2))
+                ORDER BY d.channel IS NULL LIMIT 1",
+                &[&order_detail.price_id, &data.shop_id],
             )
-            .await
-        {
-            Ok(row) => row.get("discount_id"),
-            Err(_) => "null".to_string(),
-        };
+            .await?
+            .map(|row| row.get("discount_id"));
 
         // Insert order details
         transaction
             .execute(
-                &format!("INSERT INTO order_details (order_id, price_id, quantity, price_at_order, discount_id) VALUES ($1, $2, $3, (SELECT price FROM product_prices WHERE price_id = $4), {discount_id})"),
+                "INSERT INTO order_details (order_id, price_id, quantity, price_at_order, discount_id) VALUES ($1, $2, $3, (SELECT price FROM product_prices WHERE price_id = $4), $5)",
                 &[
                     &order_id,
                     &order_detail.price_id,
                     &order_detail.quantity,
                     &order_detail.price_id,
+                    &discount_id,
                 ],
             )
             .await?;
@@ -94,6 +123,10 @@ pub async fn add_order(
         )
         .await?;
 
+    audit::record(context, "create", &audit::ORDER, order_id, None, &transaction).await?;
+    if let Some(visit_id) = data.visit_id {
+        shop_visit::link_order(visit_id, order_id, context, &transaction).await?;
+    }
     transaction.commit().await?;
     Ok(order_id)
 }
@@ -109,29 +142,51 @@ pub struct Order {
     pub order_date: NaiveDateTime,
     pub status: String,
     pub total_amount: f64,
+    pub approved_by: Option<i32>,
+    pub approved_at: Option<NaiveDateTime>,
 }
 
-pub async fn get_orders(
-    search: &Option<String>,
-    page: Option<usize>,
-    per_page: Option<usize>,
-    from_date: &Option<NaiveDate>,
-    to_date: &Option<NaiveDate>,
-    from_amount: &Option<f64>,
-    to_amount: &Option<f64>,
-    status: &Option<String>,
-    user_id: i32,
-    role: &str,
-    client: &Client,
-) -> Result<PaginationResult<Order>, Error> {
+#[derive(Deserialize)]
+pub struct OrderListQuery {
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub from_amount: Option<f64>,
+    pub to_amount: Option<f64>,
+    pub status: Option<String>,
+}
+
+const ORDER_SEARCH_COLUMNS: [&str; 5] = [
+    "o.order_id::text",
+    "s.shop_name",
+    "s.address",
+    "u.full_name",
+    "o.status",
+];
+
+// The `from` and `where` of the order list, shared with its map export.
+fn order_list_filter(
+    order_list_query: &OrderListQuery,
+    scope_user_ids: Option<&[i32]>,
+) -> (String, Vec<Box<dyn ToSql + Sync>>) {
+    let OrderListQuery {
+        from_date,
+        to_date,
+        from_amount,
+        to_amount,
+        status,
+        ..
+    } = order_list_query;
     let mut base_query =
         "from orders o join shops s on o.shop_id = s.shop_id join users u on u.user_id = o.user_id where o.deleted_at is null and s.deleted_at is null and u.deleted_at is null"
             .to_string();
     let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
 
-    if role == "Disributor" {
-        params.push(Box::new(user_id));
-        base_query = format!("{base_query} and o.user_id = ${}", params.len());
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query = format!("{base_query} and o.user_id = any(${})", params.len());
     }
 
     if from_date.is_some() && to_date.is_some() {
@@ -153,22 +208,32 @@ pub async fn get_orders(
     }
 
     if let Some(s) = status {
-        params.push(Box::new(s));
+        params.push(Box::new(s.clone()));
         base_query = format!("{base_query} and o.status = ${}", params.len());
     }
+    (base_query, params)
+}
+
+pub async fn get_orders(
+    order_list_query: &OrderListQuery,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<PaginationResult<Order>, Error> {
+    let OrderListQuery {
+        search,
+        page,
+        per_page,
+        ..
+    } = order_list_query;
+    let (page, per_page) = (*page, *per_page);
+    let (base_query, params) = order_list_filter(order_list_query, scope_user_ids);
 
     let order_options = "o.created_at desc".to_string();
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "o.order_id, s.shop_name, s.address shop_address, coalesce(s.latitude::text, '0.0') shop_latitude, coalesce(s.longitude::text, '0.0') shop_longitude, u.full_name distributor_name, o.order_date, o.status, o.total_amount::text",
+        select_columns: "o.order_id, s.shop_name, s.address shop_address, coalesce(s.latitude::text, '0.0') shop_latitude, coalesce(s.longitude::text, '0.0') shop_longitude, u.full_name distributor_name, o.order_date, o.status, o.total_amount::text, o.approved_by, o.approved_at",
         base_query: &base_query,
-        search_columns: vec![
-            "o.order_id::text",
-            "s.shop_name",
-            "s.address",
-            "u.full_name",
-            "o.status", 
-        ],
+        search_columns: ORDER_SEARCH_COLUMNS.to_vec(),
         search: search.as_deref(),
         order_options: Some(&order_options),
         page,
@@ -183,9 +248,9 @@ pub async fn get_orders(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -213,6 +278,8 @@ pub async fn get_orders(
                 order_date: row.get("order_date"),
                 status: row.get("status"),
                 total_amount,
+                approved_by: row.get("approved_by"),
+                approved_at: row.get("approved_at"),
             }
         })
         .collect();
@@ -226,6 +293,104 @@ pub async fn get_orders(
     })
 }
 
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderMapGroup {
+    #[default]
+    Shop,
+    Ward,
+}
+
+#[derive(Deserialize)]
+pub struct OrderMapQuery {
+    #[serde(default)]
+    pub group_by: OrderMapGroup,
+}
+
+#[derive(Serialize)]
+pub struct OrderFeatureProperties {
+    // Set when grouped by shop.
+    pub shop_id: Option<i32>,
+    pub shop_name: Option<String>,
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub township_id: Option<i32>,
+    pub township_name: Option<String>,
+    pub shop_count: i64,
+    pub order_count: i64,
+    pub revenue: f64,
+    pub last_order_date: Option<NaiveDateTime>,
+}
+
+// The orders of the order list, with the same filters, summed per shop or per
+// ward. Shops are points; wards are their boundary, or the mean position of
+// their shops without one.
+pub async fn get_order_features(
+    order_list_query: &OrderListQuery,
+    group_by: OrderMapGroup,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<FeatureCollection<OrderFeatureProperties>, Error> {
+    let (base_query, params) = order_list_filter(order_list_query, scope_user_ids);
+    let filter = generate_pagination_query(PaginationOptions {
+        select_columns: "o.order_id",
+        base_query: &base_query,
+        search_columns: ORDER_SEARCH_COLUMNS.to_vec(),
+        search: order_list_query.search.as_deref(),
+        order_options: None,
+        page: None,
+        per_page: None,
+    });
+    let (columns, group) = match group_by {
+        OrderMapGroup::Shop => (
+            "s.shop_id, s.shop_name, s.latitude::float8 latitude, s.longitude::float8 longitude, null::jsonb boundary",
+            "s.shop_id, w.ward_id, t.township_id order by s.shop_id",
+        ),
+        OrderMapGroup::Ward => (
+            "null::int shop_id, null::varchar shop_name, avg(s.latitude)::float8 latitude, avg(s.longitude)::float8 longitude, w.boundary",
+            "w.ward_id, t.township_id order by w.ward_id",
+        ),
+    };
+    let query = format!(
+        "select {columns}, w.ward_id, w.ward_name, t.township_id, t.township_name,
+        count(distinct s.shop_id) shop_count, count(*) order_count, coalesce(sum(o.total_amount), 0)::float8 revenue, max(o.order_date) last_order_date
+        from orders o join shops s on s.shop_id = o.shop_id join wards w on w.ward_id = s.ward_id
+        left join townships t on t.township_id = w.township_id
+        where o.order_id in (select f.order_id from ({}) f)
+        group by {group}",
+        filter.query
+    );
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let features = client
+        .query(&query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| {
+            let boundary: Option<serde_json::Value> = row.get("boundary");
+            let geometry = boundary
+                .filter(|b| Boundary::parse(b).is_ok())
+                .or_else(|| geo::point(row.get("latitude"), row.get("longitude")));
+            Feature::new(
+                geometry,
+                OrderFeatureProperties {
+                    shop_id: row.get("shop_id"),
+                    shop_name: row.get("shop_name"),
+                    ward_id: row.get("ward_id"),
+                    ward_name: row.get("ward_name"),
+                    township_id: row.get("township_id"),
+                    township_name: row.get("township_name"),
+                    shop_count: row.get("shop_count"),
+                    order_count: row.get("order_count"),
+                    revenue: row.get("revenue"),
+                    last_order_date: row.get("last_order_date"),
+                },
+            )
+        })
+        .collect();
+    Ok(FeatureCollection::new(features))
+}
+
 #[derive(Serialize)]
 pub struct OrderDetail {
     pub order_detail_id: i32,
@@ -239,15 +404,30 @@ pub struct OrderDetail {
     pub discount_name: String,
 }
 
+#[derive(Deserialize)]
+pub struct OrderDetailListQuery {
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+    pub order_id: Option<i32>,
+    pub from_amount: Option<f64>,
+    pub to_amount: Option<f64>,
+}
+
 pub async fn get_order_details(
-    search: &Option<String>,
-    page: Option<usize>,
-    per_page: Option<usize>,
-    order_id: Option<i32>,
-    from_amount: &Option<f64>,
-    to_amount: &Option<f64>,
+    order_detail_list_query: &OrderDetailListQuery,
+    scope_user_ids: Option<&[i32]>,
     client: &Client,
 ) -> Result<PaginationResult<OrderDetail>, Error> {
+    let OrderDetailListQuery {
+        search,
+        page,
+        per_page,
+        order_id,
+        from_amount,
+        to_amount,
+    } = order_detail_list_query;
+    let (page, per_page, order_id) = (*page, *per_page, *order_id);
     let mut base_query =
         "from order_details od join orders o on o.order_id = od.order_id join product_prices pp on pp.price_id = od.price_id join products p on p.product_id = pp.product_id left join discounts d on d.discount_id = od.discount_id where o.deleted_at is null and od.deleted_at is null and pp.deleted_at is null and p.deleted_at is null".to_string();
     let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
@@ -257,6 +437,11 @@ pub async fn get_order_details(
         base_query = format!("{base_query} and od.order_id = ${}", params.len());
     }
 
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query = format!("{base_query} and o.user_id = any(${})", params.len());
+    }
+
     if from_amount.is_some() && to_amount.is_some() {
         base_query = format!(
             "{base_query} and od.price_at_order between {} and {}",
@@ -290,9 +475,9 @@ pub async fn get_order_details(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -326,3 +511,73 @@ pub async fn get_order_details(
         page_counts,
     })
 }
+
+// Statuses an order can be moved to, roughly in the order it goes through them.
+pub const ORDER_STATUSES: &[&str] = &["Pending", "Confirmed", "Shipped", "Delivered", "Cancelled"];
+
+#[derive(Deserialize)]
+pub struct OrderStatusRequest {
+    pub status: String,
+}
+
+// Who placed an order and where it stands, for deciding who may change it and
+// whom to tell.
+pub struct OrderOwner {
+    pub user_id: Option<i32>,
+    pub shop_id: Option<i32>,
+    pub status: String,
+}
+
+pub async fn get_order_owner(order_id: i32, client: &Client) -> Result<Option<OrderOwner>, Error> {
+    let row = client
+        .query_opt(
+            "select user_id, shop_id, coalesce(status, '') status from orders where order_id = $1 and deleted_at is null",
+            &[&order_id],
+        )
+        .await?;
+    Ok(row.map(|row| OrderOwner {
+        user_id: row.get("user_id"),
+        shop_id: row.get("shop_id"),
+        status: row.get("status"),
+    }))
+}
+
+pub async fn update_order_status(
+    order_id: i32,
+    status: &str,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::ORDER, order_id, &transaction).await?;
+    transaction
+        .execute(
+            "update orders set status = $1 where order_id = $2",
+            &[&status, &order_id],
+        )
+        .await?;
+    audit::record(context, "update", &audit::ORDER, order_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Approves a pending order. Returns false if it was not pending any more.
+pub async fn approve_order(
+    order_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<bool, Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::ORDER, order_id, &transaction).await?;
+    let updated = transaction
+        .execute(
+            "update orders set status = 'Confirmed', approved_by = $2, approved_at = CURRENT_TIMESTAMP where order_id = $1 and status = 'Pending'",
+            &[&order_id, &context.actor_id],
+        )
+        .await?;
+    if updated == 1 {
+        audit::record(context, "update", &audit::ORDER, order_id, before, &transaction).await?;
+    }
+    transaction.commit().await?;
+    Ok(updated == 1)
+}
@@ -0,0 +1,206 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error, Row};
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+// Money a shop paid towards what it owes for its orders.
+#[derive(Debug, Serialize)]
+pub struct Payment {
+    pub payment_id: i32,
+    pub shop_id: i32,
+    pub shop_name: String,
+    // Who collected it.
+    pub user_id: i32,
+    pub full_name: String,
+    pub amount: f64,
+    pub paid_at: NaiveDate,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+const PAYMENT_COLUMNS: &str = "p.payment_id, p.shop_id, s.shop_name, p.user_id, u.full_name, p.amount::float8 amount, p.paid_at, p.reference, p.note, p.created_at";
+
+const PAYMENT_FROM: &str =
+    "from payments p join shops s on s.shop_id = p.shop_id join users u on u.user_id = p.user_id";
+
+fn payment_from_row(row: &Row) -> Payment {
+    Payment {
+        payment_id: row.get("payment_id"),
+        shop_id: row.get("shop_id"),
+        shop_name: row.get("shop_name"),
+        user_id: row.get("user_id"),
+        full_name: row.get("full_name"),
+        amount: row.get("amount"),
+        paid_at: row.get("paid_at"),
+        reference: row.get("reference"),
+        note: row.get("note"),
+        created_at: row.get("created_at"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentListQuery {
+    pub shop_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+pub async fn get_payments(
+    query: &PaymentListQuery,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<PaginationResult<Payment>, Error> {
+    let mut base_query = format!("{PAYMENT_FROM} where p.deleted_at is null");
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query = format!("{base_query} and p.user_id = any(${})", params.len());
+    }
+    if let Some(shop_id) = query.shop_id {
+        params.push(Box::new(shop_id));
+        base_query = format!("{base_query} and p.shop_id = ${}", params.len());
+    }
+    if let Some(user_id) = query.user_id {
+        params.push(Box::new(user_id));
+        base_query = format!("{base_query} and p.user_id = ${}", params.len());
+    }
+    if let Some(from_date) = query.from_date {
+        params.push(Box::new(from_date));
+        base_query = format!("{base_query} and p.paid_at >= ${}", params.len());
+    }
+    if let Some(to_date) = query.to_date {
+        params.push(Box::new(to_date));
+        base_query = format!("{base_query} and p.paid_at <= ${}", params.len());
+    }
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: PAYMENT_COLUMNS,
+        base_query: &base_query,
+        search_columns: vec![],
+        search: None,
+        order_options: Some("p.paid_at desc, p.payment_id desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let payments = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(payment_from_row)
+        .collect();
+
+    Ok(PaginationResult {
+        data: payments,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+pub async fn get_payment_by_id(payment_id: i32, client: &Client) -> Result<Option<Payment>, Error> {
+    let row = client
+        .query_opt(
+            &format!("select {PAYMENT_COLUMNS} {PAYMENT_FROM} where p.deleted_at is null and p.payment_id = $1"),
+            &[&payment_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(payment_from_row))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentRequest {
+    pub shop_id: i32,
+    pub amount: f64,
+    // Defaults to today.
+    pub paid_at: Option<NaiveDate>,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+}
+
+pub async fn add_payment(
+    data: &PaymentRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let transaction = client.transaction().await?;
+    let payment_id: i32 = transaction
+        .query_one(
+            "insert into payments (shop_id, user_id, amount, paid_at, reference, note) values ($1, $2, $3::float8, coalesce($4, CURRENT_DATE), $5, $6) returning payment_id",
+            &[
+                &data.shop_id,
+                &context.actor_id,
+                &data.amount,
+                &data.paid_at,
+                &data.reference,
+                &data.note,
+            ],
+        )
+        .await?
+        .get("payment_id");
+    audit::record(
+        context,
+        "create",
+        &audit::PAYMENT,
+        payment_id,
+        None,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(payment_id)
+}
+
+// Voids a payment recorded by mistake; the shop owes that amount again.
+pub async fn delete_payment(
+    payment_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::PAYMENT, payment_id, &transaction).await?;
+    transaction
+        .execute(
+            "update payments set deleted_at = CURRENT_TIMESTAMP where payment_id = $1",
+            &[&payment_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "delete",
+        &audit::PAYMENT,
+        payment_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
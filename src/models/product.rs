@@ -3,9 +3,12 @@ use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 use std::{fs, option::Option, path::Path};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,7 +38,7 @@ pub struct ProductListRequest {
 
 pub async fn get_products(
     product_list_request: &ProductListRequest,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Product>, Error> {
     let mut base_query = "from products p, brands b, categories c, product_categories pc  
@@ -51,8 +54,8 @@ pub async fn get_products(
         }
         None => None,
     };
-    if categories.is_some() && categories.clone().unwrap() != "".to_string() {
-        base_query += &format!(" AND c.category_id IN ({})", categories.unwrap());
+    if let Some(categories) = categories.filter(|c| !c.is_empty()) {
+        base_query += &format!(" AND c.category_id IN ({})", categories);
     }
     let brands = match &product_list_request.brands {
         Some(brands) => {
@@ -64,13 +67,13 @@ pub async fn get_products(
         }
         None => None,
     };
-    if brands.is_some() && brands.clone().unwrap() != "".to_string() {
-        base_query += &format!(" AND b.brand_id IN ({})", brands.unwrap());
+    if let Some(brands) = brands.filter(|b| !b.is_empty()) {
+        base_query += &format!(" AND b.brand_id IN ({})", brands);
     }
-    let order_options = if role == "Distributor" {
-        "p.product_name"
-    } else {
+    let order_options = if newest_first {
         "p.created_at desc"
+    } else {
+        "p.product_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
@@ -78,7 +81,7 @@ pub async fn get_products(
         base_query: &base_query,
         search_columns: vec!["p.product_id::varchar", "p.product_name", "b.brand_name", "c.category_name"],
         search: product_list_request.search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page: product_list_request.page,
         per_page: product_list_request.per_page,
     });
@@ -91,9 +94,9 @@ pub async fn get_products(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if product_list_request.page.is_some() && product_list_request.per_page.is_some() {
-        current_page = product_list_request.page.unwrap();
-        limit = product_list_request.per_page.unwrap();
+    if let (Some(p), Some(pp)) = (product_list_request.page, product_list_request.per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -104,7 +107,7 @@ pub async fn get_products(
 
         let categories_rows = client.query("select c.category_id, c.category_name from product_categories pc join categories c on pc.category_id = c.category_id where pc.product_id = $1", &[&product_id]).await?;
         products.push(Product {
-            product_id: product_id,
+            product_id,
             product_name: row.get("product_name"),
             image_url: row.get("image_url"),
             brand_id: row.get("brand_id"),
@@ -152,7 +155,7 @@ pub async fn get_product_by_id(product_id: i32, client: &Client) -> Option<Produ
     match result {
         Ok(row) => {
             Some(Product {
-                product_id: product_id,
+                product_id,
                 product_name: row.get("product_name"),
                 image_url: row.get("image_url"),
                 brand_id: row.get("brand_id"),
@@ -181,9 +184,11 @@ pub struct ProductRequest {
 
 pub async fn add_product(
     data: &ProductRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let row = client
+    let transaction = client.transaction().await?;
+    let row = transaction
         .query_one(
             "INSERT INTO products (product_name, image_url, brand_id) VALUES ($1, $2, $3) RETURNING product_id",
             &[&data.product_name, &data.image_url, &data.brand_id],
@@ -191,13 +196,15 @@ pub async fn add_product(
         .await?;
     let id: i32 = row.get("product_id");
     for category_id in &data.categories {
-        client
+        transaction
             .execute(
                 "INSERT INTO product_categories (product_id, category_id) VALUES ($1, $2)",
                 &[&id, &category_id],
             )
             .await?;
     }
+    audit::record(context, "create", &audit::PRODUCT, id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
@@ -205,15 +212,18 @@ pub async fn update_product(
     product_id: i32,
     old_image_url: &str,
     data: &ProductRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::PRODUCT, product_id, &transaction).await?;
+    transaction
         .execute(
             "UPDATE products SET product_name = $1, image_url=$2, brand_id=$3 WHERE product_id = $4",
             &[&data.product_name, &data.image_url, &data.brand_id, &product_id],
         )
         .await?;
-    client
+    transaction
         .execute(
             "DELETE FROM product_categories WHERE product_id = $1",
             &[&product_id],
@@ -221,15 +231,17 @@ pub async fn update_product(
         .await?;
 
     for category_id in &data.categories {
-        client
+        transaction
             .execute(
                 "INSERT INTO product_categories (product_id, category_id) VALUES ($1, $2)",
                 &[&product_id, &category_id],
             )
             .await?;
     }
+    audit::record(context, "update", &audit::PRODUCT, product_id, before, &transaction).await?;
+    transaction.commit().await?;
 
-    if old_image_url != &data.image_url {
+    if old_image_url != data.image_url {
         match fs::remove_file(old_image_url) {
             Ok(_) => println!("File deleted successfully!"),
             Err(e) => println!("Error deleting file: {}", e),
@@ -253,13 +265,20 @@ pub async fn update_product(
     Ok(())
 }
 
-pub async fn delete_product(product_id: i32, client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    client
+pub async fn delete_product(
+    product_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::PRODUCT, product_id, &transaction).await?;
+    transaction
         .execute(
             "update products set deleted_at = CURRENT_TIMESTAMP where product_id = $1",
             &[&product_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::PRODUCT, product_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
@@ -2,9 +2,12 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,27 +18,34 @@ pub struct Price {
     pub price_type: String,
     pub package_quantity: i32,
     pub remaining_quantity: i32,
+    // Offered only to shops of this channel; to every shop when unset.
+    pub channel: Option<String>,
     pub created_at: NaiveDateTime,
 }
 
 pub async fn get_prices(
     product_id: i32,
+    channel: Option<&str>,
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
     client: &Client,
 ) -> Result<PaginationResult<Price>, Error> {
-    let base_query = format!("from product_prices p where p.product_id = {} and p.deleted_at is null", product_id);
-    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    let mut base_query = format!("from product_prices p where p.product_id = {} and p.deleted_at is null", product_id);
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(channel) = channel {
+        params.push(Box::new(channel.to_string()));
+        base_query = format!("{base_query} and (p.channel is null or p.channel = ${})", params.len());
+    }
 
     let order_options = "p.price_type";
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "p.price_id, p.product_id, p.price::text as price, p.price_type, p.package_quantity, p.remaining_quantity, p.created_at",
+        select_columns: "p.price_id, p.product_id, p.price::text as price, p.price_type, p.package_quantity, p.remaining_quantity, p.channel, p.created_at",
         base_query: &base_query,
         search_columns: vec!["p.price_id::varchar", "p.price_type"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -48,9 +58,9 @@ pub async fn get_prices(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -68,6 +78,7 @@ pub async fn get_prices(
                 price_type: row.get("price_type"),
                 package_quantity: row.get("package_quantity"),
                 remaining_quantity: row.get("remaining_quantity"),
+                channel: row.get("channel"),
                 created_at: row.get("created_at"),
             }
         );
@@ -89,26 +100,34 @@ pub struct PriceRequest {
     pub price_type: String,
     pub package_quantity: i32,
     pub remaining_quantity: i32,
+    // Left out, an update keeps the price's channel; an empty string offers it
+    // to every shop.
+    pub channel: Option<String>,
 }
 
 pub async fn add_price(
     data: &PriceRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("insert into product_prices (product_id, price, price_type, package_quantity, remaining_quantity) values ($1, {}, $2, $3, $4)", data.price);
-    client
-        .execute(
-            &query,
-            &[&data.product_id, &data.price_type, &data.package_quantity, &data.remaining_quantity],
+    let transaction = client.transaction().await?;
+    let query = format!("insert into product_prices (product_id, price, price_type, package_quantity, remaining_quantity, channel) values ($1, {}, $2, $3, $4, nullif($5::text, ''))", data.price);
+    let price_id: i32 = transaction
+        .query_one(
+            &format!("{query} returning price_id"),
+            &[&data.product_id, &data.price_type, &data.package_quantity, &data.remaining_quantity, &data.channel],
         )
-        .await?;
+        .await?
+        .get("price_id");
+    audit::record(context, "create", &audit::PRICE, price_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn get_price_by_id(price_id: i32, client: &Client) -> Option<Price> {
     let result = client
         .query_one(
-            "select p.price_id, p.product_id, p.price::text as price, p.price_type, p.package_quantity, p.remaining_quantity, p.created_at from product_prices p where p.deleted_at is null and p.price_id = $1",
+            "select p.price_id, p.product_id, p.price::text as price, p.price_type, p.package_quantity, p.remaining_quantity, p.channel, p.created_at from product_prices p where p.deleted_at is null and p.price_id = $1",
             &[&price_id],
         )
         .await;
@@ -124,6 +143,7 @@ pub async fn get_price_by_id(price_id: i32, client: &Client) -> Option<Price> {
                     price_type: row.get("price_type"),
                     package_quantity: row.get("package_quantity"),
                     remaining_quantity: row.get("remaining_quantity"),
+                    channel: row.get("channel"),
                     created_at: row.get("created_at"),
                 })
         },
@@ -134,29 +154,54 @@ pub async fn get_price_by_id(price_id: i32, client: &Client) -> Option<Price> {
 pub async fn update_price(
     price_id: i32,
     data: &PriceRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let query = format!("update product_prices set price = {}, price_type=$1, package_quantity=$2, remaining_quantity=$3 where price_id = $4", data.price);
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::PRICE, price_id, &transaction).await?;
+    let query = format!("update product_prices set price = {}, price_type=$1, package_quantity=$2, remaining_quantity=$3, channel = case when $4::text is null then channel else nullif($4, '') end where price_id = $5", data.price);
+    transaction
         .execute(
             &query,
-            &[&data.price_type, &data.package_quantity, &data.remaining_quantity, &price_id],
+            &[&data.price_type, &data.package_quantity, &data.remaining_quantity, &data.channel, &price_id],
         )
         .await?;
-
+    audit::record(context, "update", &audit::PRICE, price_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn delete_price(
     price_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::PRICE, price_id, &transaction).await?;
+    transaction
         .execute(
             "update product_prices set deleted_at = CURRENT_TIMESTAMP where price_id = $1",
             &[&price_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::PRICE, price_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
+
+// Those of the prices that are kept for a channel other than the shop's.
+pub async fn get_prices_not_offered(
+    price_ids: &[i32],
+    shop_id: i32,
+    client: &Client,
+) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            "select pp.price_id from product_prices pp, shops s
+            where s.shop_id = $2 and pp.price_id = any($1)
+            and pp.channel is not null and pp.channel is distinct from s.channel",
+            &[&price_ids, &shop_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("price_id")).collect())
+}
@@ -0,0 +1,216 @@
+use std::env;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error};
+
+use crate::models::audit::{self, AuditContext};
+
+// What a shop owes: its orders that were not cancelled, less its payments.
+const OWED_ORDERS: &str = "o.deleted_at is null and o.status is distinct from 'Cancelled'";
+
+// Whether an order over a shop's credit limit is refused (`block`) or taken
+// with a warning (`warn`, the default).
+pub fn block_over_limit() -> bool {
+    env::var("CREDIT_LIMIT_MODE").is_ok_and(|v| v == "block")
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShopCredit {
+    pub shop_id: i32,
+    // No limit when unset.
+    pub credit_limit: Option<f64>,
+    pub balance: f64,
+    pub available: Option<f64>,
+}
+
+pub async fn get_shop_credit(shop_id: i32, client: &Client) -> Result<Option<ShopCredit>, Error> {
+    let row = client
+        .query_opt(
+            &format!(
+                "select s.shop_id, s.credit_limit::float8 credit_limit,
+                (coalesce((select sum(o.total_amount) from orders o where o.shop_id = s.shop_id and {OWED_ORDERS}), 0)
+                - coalesce((select sum(p.amount) from payments p where p.shop_id = s.shop_id and p.deleted_at is null), 0))::float8 balance
+                from shops s where s.shop_id = $1 and s.deleted_at is null"
+            ),
+            &[&shop_id],
+        )
+        .await?;
+    Ok(row.map(|row| {
+        let credit_limit: Option<f64> = row.get("credit_limit");
+        let balance: f64 = row.get("balance");
+        ShopCredit {
+            shop_id: row.get("shop_id"),
+            credit_limit,
+            balance,
+            available: credit_limit.map(|limit| limit - balance),
+        }
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreditLimitRequest {
+    // Unset to lift the limit.
+    pub credit_limit: Option<f64>,
+}
+
+pub async fn set_credit_limit(
+    shop_id: i32,
+    credit_limit: Option<f64>,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP, shop_id, &transaction).await?;
+    transaction
+        .execute(
+            "update shops set credit_limit = $1::float8 where shop_id = $2",
+            &[&credit_limit, &shop_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "update",
+        &audit::SHOP,
+        shop_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct LedgerEntry {
+    pub entry_date: NaiveDateTime,
+    // `order` or `payment`.
+    pub kind: String,
+    pub reference_id: i32,
+    // Positive for orders, negative for payments.
+    pub amount: f64,
+    pub balance: f64,
+}
+
+// Orders and payments of the shop, oldest first, with the balance after each.
+pub async fn get_ledger(shop_id: i32, client: &Client) -> Result<Vec<LedgerEntry>, Error> {
+    let rows = client
+        .query(
+            &format!(
+                "select l.entry_date, l.kind, l.reference_id, l.amount::float8 amount,
+                (sum(l.amount) over (order by l.entry_date, l.kind, l.reference_id))::float8 balance
+                from (
+                    select o.order_date entry_date, 'order' kind, o.order_id reference_id, o.total_amount amount
+                    from orders o where o.shop_id = $1 and {OWED_ORDERS}
+                    union all
+                    select p.paid_at::timestamp, 'payment', p.payment_id, -p.amount
+                    from payments p where p.shop_id = $1 and p.deleted_at is null
+                ) l
+                order by l.entry_date, l.kind, l.reference_id"
+            ),
+            &[&shop_id],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| LedgerEntry {
+            entry_date: row.get("entry_date"),
+            kind: row.get("kind"),
+            reference_id: row.get("reference_id"),
+            amount: row.get("amount"),
+            balance: row.get("balance"),
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AgingGroup {
+    #[default]
+    Shop,
+    Ward,
+    Distributor,
+}
+
+#[derive(Deserialize)]
+pub struct AgingQuery {
+    #[serde(default)]
+    pub group_by: AgingGroup,
+    // Defaults to today.
+    pub as_of: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgingRow {
+    // The shop, ward or distributor; unset for orders without a distributor.
+    pub group_id: Option<i32>,
+    pub group_name: Option<String>,
+    pub outstanding: f64,
+    pub days_0_30: f64,
+    pub days_31_60: f64,
+    pub days_61_90: f64,
+    pub days_over_90: f64,
+}
+
+// Unpaid order amounts by how long ago the order was placed. A shop's payments
+// settle its oldest orders first.
+pub async fn get_aging(
+    group_by: AgingGroup,
+    as_of: NaiveDate,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<Vec<AgingRow>, Error> {
+    let (group_id, group_name) = match group_by {
+        AgingGroup::Shop => ("s.shop_id", "s.shop_name"),
+        AgingGroup::Ward => ("w.ward_id", "w.ward_name"),
+        AgingGroup::Distributor => ("u.user_id", "u.full_name"),
+    };
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![Box::new(as_of)];
+    let mut scope = String::new();
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        scope = format!(" and u.user_id = any(${})", params.len());
+    }
+    let query = format!(
+        "with paid as (
+            select p.shop_id, sum(p.amount) paid from payments p
+            where p.deleted_at is null and p.paid_at <= $1 group by p.shop_id
+        ), owed as (
+            select o.shop_id, o.user_id, $1 - o.order_date::date age, o.total_amount,
+            sum(o.total_amount) over (partition by o.shop_id order by o.order_date, o.order_id) running_total
+            from orders o where {OWED_ORDERS} and o.order_date::date <= $1
+        ), open as (
+            select owed.shop_id, owed.user_id, owed.age,
+            greatest(0, least(owed.total_amount, owed.running_total - coalesce(paid.paid, 0))) unpaid
+            from owed left join paid on paid.shop_id = owed.shop_id
+        )
+        select {group_id} group_id, {group_name} group_name,
+        sum(open.unpaid)::float8 outstanding,
+        coalesce(sum(open.unpaid) filter (where open.age <= 30), 0)::float8 days_0_30,
+        coalesce(sum(open.unpaid) filter (where open.age between 31 and 60), 0)::float8 days_31_60,
+        coalesce(sum(open.unpaid) filter (where open.age between 61 and 90), 0)::float8 days_61_90,
+        coalesce(sum(open.unpaid) filter (where open.age > 90), 0)::float8 days_over_90
+        from open join shops s on s.shop_id = open.shop_id
+        join wards w on w.ward_id = s.ward_id
+        left join users u on u.user_id = open.user_id
+        where open.unpaid > 0{scope}
+        group by {group_id}, {group_name}
+        order by outstanding desc"
+    );
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    Ok(client
+        .query(&query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| AgingRow {
+            group_id: row.get("group_id"),
+            group_name: row.get("group_name"),
+            outstanding: row.get("outstanding"),
+            days_0_30: row.get("days_0_30"),
+            days_31_60: row.get("days_31_60"),
+            days_61_90: row.get("days_61_90"),
+            days_over_90: row.get("days_over_90"),
+        })
+        .collect())
+}
@@ -0,0 +1,191 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error};
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Region {
+    pub region_id: i32,
+    pub region_name: String,
+    pub created_at: NaiveDateTime,
+}
+
+pub async fn get_regions(
+    search: &Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+    newest_first: bool,
+    client: &Client,
+) -> Result<PaginationResult<Region>, Error> {
+    let base_query = "from regions b where b.deleted_at is null".to_string();
+    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    let order_options = if newest_first {
+        "b.created_at desc"
+    } else {
+        "region_name"
+    };
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: "b.region_id, b.region_name,b.created_at",
+        base_query: &base_query,
+        search_columns: vec!["b.region_id::varchar", "b.region_name"],
+        search: search.as_deref(),
+        order_options: Some(order_options),
+        page,
+        per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let regions: Vec<Region> = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| Region {
+            region_id: row.get("region_id"),
+            region_name: row.get("region_name"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+
+    Ok(PaginationResult {
+        data: regions,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegionRequest {
+    pub region_name: String,
+}
+
+pub async fn add_region(
+    data: &RegionRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let region_id: i32 = transaction
+        .query_one(
+            "insert into regions (region_name) values ($1) returning region_id",
+            &[&data.region_name],
+        )
+        .await?
+        .get("region_id");
+    audit::record(
+        context,
+        "create",
+        &audit::REGION,
+        region_id,
+        None,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn get_region_by_id(region_id: i32, client: &Client) -> Option<Region> {
+    let result = client
+        .query_one(
+            "select region_id, region_name,created_at from regions  where deleted_at is null and region_id = $1",
+            &[&region_id],
+        )
+        .await;
+
+    match result {
+        Ok(row) => Some(Region {
+            region_id: row.get("region_id"),
+            region_name: row.get("region_name"),
+            created_at: row.get("created_at"),
+        }),
+        Err(_) => None,
+    }
+}
+
+pub async fn update_region(
+    region_id: i32,
+    data: &RegionRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::REGION, region_id, &transaction).await?;
+    transaction
+        .execute(
+            "update regions set region_name = $1 where region_id = $2",
+            &[&data.region_name, &region_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "update",
+        &audit::REGION,
+        region_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// A region with live townships cannot be deleted.
+pub async fn region_in_use(region_id: i32, client: &Client) -> Result<bool, Error> {
+    let row = client
+        .query_opt(
+            "select township_id from townships where region_id = $1 and deleted_at is null limit 1",
+            &[&region_id],
+        )
+        .await?;
+    Ok(row.is_some())
+}
+
+pub async fn delete_region(
+    region_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::REGION, region_id, &transaction).await?;
+    transaction
+        .execute(
+            "update regions set deleted_at = CURRENT_TIMESTAMP where region_id = $1",
+            &[&region_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "delete",
+        &audit::REGION,
+        region_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
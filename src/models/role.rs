@@ -0,0 +1,226 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Error};
+
+use crate::models::audit::{self, AuditContext};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Permission {
+    pub permission_id: i32,
+    pub resource: String,
+    pub action: String,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Role {
+    pub role_id: i32,
+    pub role_name: String,
+    pub permissions: Vec<Permission>,
+    pub created_at: NaiveDateTime,
+}
+
+fn to_permission(row: &tokio_postgres::Row) -> Permission {
+    Permission {
+        permission_id: row.get("permission_id"),
+        resource: row.get("resource"),
+        action: row.get("action"),
+        description: row.get("description"),
+    }
+}
+
+async fn get_role_permissions(role_id: i32, client: &Client) -> Result<Vec<Permission>, Error> {
+    let rows = client
+        .query(
+            "select p.permission_id, p.resource, p.action, coalesce(p.description, '') description
+            from role_permissions rp join permissions p on p.permission_id = rp.permission_id
+            where rp.role_id = $1 order by p.resource, p.action",
+            &[&role_id],
+        )
+        .await?;
+    Ok(rows.iter().map(to_permission).collect())
+}
+
+pub async fn has_permission(
+    role_name: &str,
+    resource: &str,
+    action: &str,
+    client: &Client,
+) -> Result<bool, Error> {
+    let row = client
+        .query_opt(
+            "select rp.role_id from role_permissions rp
+            join roles r on r.role_id = rp.role_id
+            join permissions p on p.permission_id = rp.permission_id
+            where r.role_name = $1 and r.deleted_at is null and p.resource = $2 and p.action = $3",
+            &[&role_name, &resource, &action],
+        )
+        .await?;
+    Ok(row.is_some())
+}
+
+pub async fn get_permissions(client: &Client) -> Result<Vec<Permission>, Error> {
+    let rows = client
+        .query(
+            "select permission_id, resource, action, coalesce(description, '') description from permissions order by resource, action",
+            &[],
+        )
+        .await?;
+    Ok(rows.iter().map(to_permission).collect())
+}
+
+pub async fn get_roles(client: &Client) -> Result<Vec<Role>, Error> {
+    let rows = client
+        .query(
+            "select role_id, role_name, created_at from roles where deleted_at is null order by role_name",
+            &[],
+        )
+        .await?;
+    let mut roles: Vec<Role> = vec![];
+    for row in &rows {
+        let role_id: i32 = row.get("role_id");
+        roles.push(Role {
+            role_id,
+            role_name: row.get("role_name"),
+            permissions: get_role_permissions(role_id, client).await?,
+            created_at: row.get("created_at"),
+        });
+    }
+    Ok(roles)
+}
+
+pub async fn get_role_by_id(role_id: i32, client: &Client) -> Option<Role> {
+    let result = client
+        .query_one(
+            "select role_id, role_name, created_at from roles where deleted_at is null and role_id = $1",
+            &[&role_id],
+        )
+        .await;
+
+    match result {
+        Ok(row) => {
+            let permissions = match get_role_permissions(role_id, client).await {
+                Ok(permissions) => permissions,
+                Err(err) => {
+                    println!("{:?}", err);
+                    vec![]
+                }
+            };
+            Some(Role {
+                role_id: row.get("role_id"),
+                role_name: row.get("role_name"),
+                permissions,
+                created_at: row.get("created_at"),
+            })
+        }
+        Err(_) => None,
+    }
+}
+
+pub async fn role_exists(role_name: &str, client: &Client) -> Result<bool, Error> {
+    let row = client
+        .query_opt(
+            "select role_id from roles where role_name = $1 and deleted_at is null",
+            &[&role_name],
+        )
+        .await?;
+    Ok(row.is_some())
+}
+
+pub async fn role_in_use(role_id: i32, client: &Client) -> Result<bool, Error> {
+    let row = client
+        .query_opt(
+            "select u.user_id from users u join roles r on r.role_name = u.role where r.role_id = $1 and u.deleted_at is null limit 1",
+            &[&role_id],
+        )
+        .await?;
+    Ok(row.is_some())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleRequest {
+    pub role_name: String,
+    pub permission_ids: Vec<i32>,
+}
+
+pub async fn add_role(
+    data: &RoleRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let role_id: i32 = transaction
+        .query_one(
+            "insert into roles (role_name) values ($1) returning role_id",
+            &[&data.role_name],
+        )
+        .await?
+        .get("role_id");
+    for permission_id in &data.permission_ids {
+        transaction
+            .execute(
+                "insert into role_permissions (role_id, permission_id) values ($1, $2)",
+                &[&role_id, &permission_id],
+            )
+            .await?;
+    }
+    audit::record(context, "create", &audit::ROLE, role_id, None, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn update_role(
+    role_id: i32,
+    old_role_name: &str,
+    data: &RoleRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::ROLE, role_id, &transaction).await?;
+    transaction
+        .execute(
+            "update roles set role_name = $1 where role_id = $2",
+            &[&data.role_name, &role_id],
+        )
+        .await?;
+    // Users refer to their role by name, so a rename has to follow through.
+    transaction
+        .execute(
+            "update users set role = $1 where role = $2",
+            &[&data.role_name, &old_role_name],
+        )
+        .await?;
+    transaction
+        .execute("delete from role_permissions where role_id = $1", &[&role_id])
+        .await?;
+    for permission_id in &data.permission_ids {
+        transaction
+            .execute(
+                "insert into role_permissions (role_id, permission_id) values ($1, $2)",
+                &[&role_id, &permission_id],
+            )
+            .await?;
+    }
+    audit::record(context, "update", &audit::ROLE, role_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_role(
+    role_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::ROLE, role_id, &transaction).await?;
+    transaction
+        .execute(
+            "update roles set deleted_at = CURRENT_TIMESTAMP where role_id = $1",
+            &[&role_id],
+        )
+        .await?;
+    audit::record(context, "delete", &audit::ROLE, role_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Error};
+
+use crate::utils::{geo::distance_m, route::plan_route};
+
+#[derive(Debug, Deserialize)]
+pub struct RouteQuery {
+    // Defaults to today.
+    pub date: Option<NaiveDate>,
+    // Where the day starts, e.g. the distributor's current location.
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteStop {
+    pub sequence: usize,
+    pub shop_id: i32,
+    pub shop_name: String,
+    pub address: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub ward_id: i32,
+    pub ward_name: String,
+    // From the previous stop, or from the start location for the first one.
+    // Unknown for shops without coordinates, which come last.
+    pub distance_m: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoutePlan {
+    pub date: NaiveDate,
+    pub weekday: String,
+    // Set when the date is a holiday; there are no stops then.
+    pub holiday: Option<String>,
+    pub total_distance_m: f64,
+    pub stops: Vec<RouteStop>,
+}
+
+// Shops to visit on `date`: those in the wards the user covers that day whose
+// visiting days include its weekday.
+pub async fn get_route_stops(
+    user_id: i32,
+    date: NaiveDate,
+    client: &Client,
+) -> Result<Vec<RouteStop>, Error> {
+    let weekday = date.format("%A").to_string();
+    let rows = client
+        .query(
+            "select distinct s.shop_id, s.shop_name, coalesce(s.address, '') address, s.latitude::float8 latitude, s.longitude::float8 longitude, w.ward_id, w.ward_name
+            from ward_assignments a
+            join shops s on s.ward_id = a.ward_id
+            join wards w on w.ward_id = s.ward_id
+            join shop_weekdays sw on sw.shop_id = s.shop_id
+            join weekdays wd on wd.weekday_id = sw.weekday_id
+            where a.user_id = $1 and a.effective_from <= $2 and (a.effective_to is null or a.effective_to >= $2)
+            and wd.weekday_name = $3 and s.deleted_at is null and w.deleted_at is null
+            order by s.shop_id",
+            &[&user_id, &date, &weekday],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| RouteStop {
+            sequence: 0,
+            shop_id: row.get("shop_id"),
+            shop_name: row.get("shop_name"),
+            address: row.get("address"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
+            ward_id: row.get("ward_id"),
+            ward_name: row.get("ward_name"),
+            distance_m: None,
+        })
+        .collect())
+}
+
+// Puts the stops in visiting order from `start` and fills in the distances.
+pub fn order_stops(start: Option<(f64, f64)>, stops: Vec<RouteStop>) -> (Vec<RouteStop>, f64) {
+    let (located, unlocated): (Vec<RouteStop>, Vec<RouteStop>) = stops
+        .into_iter()
+        .partition(|s| s.latitude.is_some() && s.longitude.is_some());
+    let points: Vec<(f64, f64)> = located
+        .iter()
+        .map(|s| (s.latitude.unwrap(), s.longitude.unwrap()))
+        .collect();
+    let order = plan_route(start, &points);
+
+    let mut located: Vec<Option<RouteStop>> = located.into_iter().map(Some).collect();
+    let mut previous = start;
+    let mut total_distance_m = 0.0;
+    let mut ordered = vec![];
+    for i in order {
+        let mut stop = located[i].take().unwrap();
+        stop.distance_m = Some(previous.map_or(0.0, |p| distance_m(p, points[i])));
+        total_distance_m += stop.distance_m.unwrap();
+        previous = Some(points[i]);
+        ordered.push(stop);
+    }
+    ordered.extend(unlocated);
+    for (i, stop) in ordered.iter_mut().enumerate() {
+        stop.sequence = i + 1;
+    }
+    (ordered, total_distance_m)
+}
@@ -0,0 +1,149 @@
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use tokio_postgres::{Client, Error};
+use uuid::Uuid;
+
+pub struct Session {
+    pub session_id: i32,
+    pub user_id: i32,
+    pub refresh_token_hash: String,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+// Refresh tokens have the form `<session_id>.<secret>`. Only a SHA-256 hash of
+// the secret is stored, so a database leak does not hand out live sessions.
+pub fn generate_refresh_secret() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_refresh_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+pub fn parse_refresh_token(refresh_token: &str) -> Option<(i32, &str)> {
+    let (session_id, secret) = refresh_token.split_once('.')?;
+    Some((session_id.parse().ok()?, secret))
+}
+
+pub async fn add_session(
+    user_id: i32,
+    refresh_token_hash: &str,
+    user_agent: &str,
+    ttl_seconds: i64,
+    client: &Client,
+) -> Result<i32, Error> {
+    let ttl_seconds = ttl_seconds as f64;
+    let row = client
+        .query_one(
+            "insert into user_sessions (user_id, refresh_token_hash, user_agent, expires_at) values ($1, $2, $3, CURRENT_TIMESTAMP + make_interval(secs => $4)) returning session_id",
+            &[&user_id, &refresh_token_hash, &user_agent, &ttl_seconds],
+        )
+        .await?;
+    Ok(row.get("session_id"))
+}
+
+pub async fn get_session(session_id: i32, client: &Client) -> Option<Session> {
+    let result = client
+        .query_one(
+            "select session_id, user_id, refresh_token_hash, revoked_at from user_sessions where session_id = $1",
+            &[&session_id],
+        )
+        .await;
+
+    match result {
+        Ok(row) => Some(Session {
+            session_id: row.get("session_id"),
+            user_id: row.get("user_id"),
+            refresh_token_hash: row.get("refresh_token_hash"),
+            revoked_at: row.get("revoked_at"),
+        }),
+        Err(_) => None,
+    }
+}
+
+// Replaces the refresh token hash and bumps the generation, which invalidates
+// every access token issued for the previous generation. Returns the new
+// generation, or `None` if the session was revoked or expired in the meantime.
+pub async fn rotate_session(
+    session_id: i32,
+    refresh_token_hash: &str,
+    client: &Client,
+) -> Result<Option<i32>, Error> {
+    let row = client
+        .query_opt(
+            "update user_sessions set refresh_token_hash = $1, generation = generation + 1, last_used_at = CURRENT_TIMESTAMP where session_id = $2 and revoked_at is null and expires_at > CURRENT_TIMESTAMP returning generation",
+            &[&refresh_token_hash, &session_id],
+        )
+        .await?;
+    Ok(row.map(|r| r.get("generation")))
+}
+
+pub async fn revoke_session(session_id: i32, client: &Client) -> Result<(), Error> {
+    client
+        .execute(
+            "update user_sessions set revoked_at = CURRENT_TIMESTAMP where session_id = $1 and revoked_at is null",
+            &[&session_id],
+        )
+        .await?;
+    Ok(())
+}
+
+// Also revokes the user's tokens that carry no session, see `is_user_active`.
+pub async fn revoke_user_sessions(user_id: i32, client: &Client) -> Result<u64, Error> {
+    client
+        .execute(
+            "update users set tokens_revoked_at = CURRENT_TIMESTAMP where user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    client
+        .execute(
+            "update user_sessions set revoked_at = CURRENT_TIMESTAMP where user_id = $1 and revoked_at is null",
+            &[&user_id],
+        )
+        .await
+}
+
+pub async fn is_session_active(
+    session_id: i32,
+    user_id: i32,
+    generation: i32,
+    client: &Client,
+) -> bool {
+    let result = client
+        .query_opt(
+            "select s.session_id from user_sessions s join users u on u.user_id = s.user_id
+            where s.session_id = $1 and s.user_id = $2 and s.generation = $3
+            and s.revoked_at is null and s.expires_at > CURRENT_TIMESTAMP and u.deleted_at is null",
+            &[&session_id, &user_id, &generation],
+        )
+        .await;
+
+    match result {
+        Ok(row) => row.is_some(),
+        Err(err) => {
+            println!("{:?}", err);
+            false
+        }
+    }
+}
+
+// For tokens without a session: the user must not be deleted, nor have had
+// their sessions revoked since the token was issued.
+pub async fn is_user_active(user_id: i32, issued_at: usize, client: &Client) -> bool {
+    let result = client
+        .query_opt(
+            "select user_id from users where user_id = $1 and deleted_at is null
+            and (tokens_revoked_at is null or tokens_revoked_at < to_timestamp($2::float8)::timestamp)",
+            &[&user_id, &(issued_at as f64)],
+        )
+        .await;
+
+    match result {
+        Ok(row) => row.is_some(),
+        Err(err) => {
+            println!("{:?}", err);
+            false
+        }
+    }
+}
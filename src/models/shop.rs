@@ -1,11 +1,18 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{types::ToSql, Client, Error};
+use tokio_postgres::{types::ToSql, Client, Error, Transaction};
 use std::{fs, option::Option, path::Path};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::{
+        audit::{self, AuditContext},
+        ward,
+    },
+    utils::{
+        common_struct::PaginationResult,
+        geo::{self, bounding_box, Feature, FeatureCollection, EARTH_RADIUS_M},
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +26,11 @@ pub struct Shop {
     pub ward_id: i32,
     pub ward_name: String,
     pub weekdays: Vec<Weekdays>,
+    pub owner_name: Option<String>,
+    pub phone_numbers: Vec<String>,
+    pub channel: Option<String>,
+    pub size_class: Option<String>,
+    pub tags: Vec<String>,
     pub created_at: NaiveDateTime,
 }
 #[derive(Debug, Serialize, Deserialize)]
@@ -27,20 +39,40 @@ pub struct Weekdays {
     pub weekday_name: String,
 }
 
+// The kinds of outlet a shop can be; prices and discounts can target one.
+pub const SHOP_CHANNELS: &[&str] = &["retail", "wholesale", "pharmacy", "supermarket"];
+
+pub const SHOP_SIZE_CLASSES: &[&str] = &["small", "medium", "large"];
+
 #[derive(Debug, Deserialize)]
 pub struct ShopListRequest {
     pub search: Option<String>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
     pub weekdays: Option<Vec<i32>>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub size_class: Option<String>,
+    // Shops carrying every one of these tags.
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
 }
 
-pub async fn get_shops(
-    user_id: i32,
-    role: &str,
+const SHOP_SEARCH_COLUMNS: [&str; 6] = [
+    "s.shop_id::varchar",
+    "s.shop_name",
+    "s.address",
+    "w.ward_name",
+    "s.owner_name",
+    "array_to_string(s.phone_numbers, ' ')",
+];
+
+// The `from` and `where` of the shop list, shared with its map export.
+fn shop_list_filter(
+    scope_user_ids: Option<&[i32]>,
     shop_list_request: &ShopListRequest,
-    client: &Client,
-) -> Result<PaginationResult<Shop>, Error> {
+) -> (String, Vec<Box<dyn ToSql + Sync>>) {
     let weekdays = match &shop_list_request.weekdays {
         Some(weekdays) => {
             let mut weekdays_str = String::new();
@@ -51,41 +83,54 @@ pub async fn get_shops(
         }
         None => None,
     };
-    let mut base_query = if role == "Distributor" {
-        format!(
-            "from 
-        users u, user_wards uw, shops s, shop_weekdays sw, wards w
-        where u.user_id = uw.user_id and uw.ward_id=s.ward_id and s.shop_id=sw.shop_id
-        and w.ward_id=s.ward_id
-        and s.deleted_at is null and u.user_id={}",
-            user_id
-        )
-    } else {
-        "from 
+    let mut base_query = "from 
         users u, user_wards uw, shops s, shop_weekdays sw, wards w
         where u.user_id = uw.user_id and uw.ward_id=s.ward_id and s.shop_id=sw.shop_id
         and w.ward_id=s.ward_id
-        and s.deleted_at is null".to_string()
-    };
-    if weekdays.is_some()
-        && !weekdays.as_ref().unwrap_or(&String::new()).is_empty()
-    {
-        base_query += &format!(" AND sw.weekday_id IN ({})", weekdays.as_ref().unwrap());
+        and s.deleted_at is null".to_string();
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query += &format!(" and u.user_id = any(${})", params.len());
+    }
+    if let Some(weekdays) = weekdays.filter(|w| !w.is_empty()) {
+        base_query += &format!(" AND sw.weekday_id IN ({})", weekdays);
     }
-    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(channel) = &shop_list_request.channel {
+        params.push(Box::new(channel.clone()));
+        base_query += &format!(" and s.channel = ${}", params.len());
+    }
+    if let Some(size_class) = &shop_list_request.size_class {
+        params.push(Box::new(size_class.clone()));
+        base_query += &format!(" and s.size_class = ${}", params.len());
+    }
+    if let Some(tags) = shop_list_request.tags.as_ref().filter(|t| !t.is_empty()) {
+        params.push(Box::new(tags.clone()));
+        base_query += &format!(" and s.tags @> ${}::text[]", params.len());
+    }
+    (base_query, params)
+}
 
-    let order_options = if role == "Distributor" {
-        "s.shop_name"
-    } else {
+pub async fn get_shops(
+    scope_user_ids: Option<&[i32]>,
+    newest_first: bool,
+    shop_list_request: &ShopListRequest,
+    client: &Client,
+) -> Result<PaginationResult<Shop>, Error> {
+    let (base_query, params) = shop_list_filter(scope_user_ids, shop_list_request);
+
+    let order_options = if newest_first {
         "s.created_at desc"
+    } else {
+        "s.shop_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "distinct s.shop_id, s.shop_name, s.address, COALESCE(s.latitude,0.0)::text as latitude, COALESCE(s.longitude,0.0)::text as longitude, image_url, w.ward_id, w.ward_name, s.created_at",
+        select_columns: "distinct s.shop_id, s.shop_name, s.address, COALESCE(s.latitude,0.0)::text as latitude, COALESCE(s.longitude,0.0)::text as longitude, image_url, w.ward_id, w.ward_name, s.owner_name, s.phone_numbers, s.channel, s.size_class, s.tags, s.created_at",
         base_query: &base_query,
-        search_columns: vec!["s.shop_id::varchar", "s.shop_name", "s.address", "w.ward_name"],
+        search_columns: SHOP_SEARCH_COLUMNS.to_vec(),
         search: shop_list_request.search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page: shop_list_request.page,
         per_page: shop_list_request.per_page,
     });
@@ -98,9 +143,9 @@ pub async fn get_shops(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if shop_list_request.page.is_some() && shop_list_request.per_page.is_some() {
-        current_page = shop_list_request.page.unwrap();
-        limit = shop_list_request.per_page.unwrap();
+    if let (Some(p), Some(pp)) = (shop_list_request.page, shop_list_request.per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -113,7 +158,7 @@ pub async fn get_shops(
         let latitude_str: &str = row.get("latitude");
         let longitude_str: &str = row.get("longitude");
         shops.push(Shop {
-            shop_id: shop_id,
+            shop_id,
             shop_name: row.get("shop_name"),
             address: row.get("address"),
             latitude: latitude_str.parse().unwrap(),
@@ -128,6 +173,11 @@ pub async fn get_shops(
                     weekday_name: row.get("weekday_name"),
                 })
                 .collect(),
+            owner_name: row.get("owner_name"),
+            phone_numbers: row.get("phone_numbers"),
+            channel: row.get("channel"),
+            size_class: row.get("size_class"),
+            tags: row.get("tags"),
             created_at: row.get("created_at"),
         });
     }
@@ -144,7 +194,7 @@ pub async fn get_shops(
 pub async fn get_shop_by_id(shop_id: i32, client: &Client) -> Option<Shop> {
     let result = client
         .query_one(
-            "select s.shop_id, s.shop_name, s.address, COALESCE(s.latitude,0.0)::text as latitude, COALESCE(s.longitude,0.0)::text as longitude, image_url, w.ward_id, w.ward_name, s.created_at 
+            "select s.shop_id, s.shop_name, s.address, COALESCE(s.latitude,0.0)::text as latitude, COALESCE(s.longitude,0.0)::text as longitude, image_url, w.ward_id, w.ward_name, s.owner_name, s.phone_numbers, s.channel, s.size_class, s.tags, s.created_at 
             from shops s, wards w where w.ward_id=s.ward_id and s.deleted_at is null and s.shop_id = $1",
             &[&shop_id],
         )
@@ -166,7 +216,7 @@ pub async fn get_shop_by_id(shop_id: i32, client: &Client) -> Option<Shop> {
             let latitude_str: &str = row.get("latitude");
             let longitude_str: &str = row.get("longitude");
             Some(Shop {
-                shop_id: shop_id,
+                shop_id,
                 shop_name: row.get("shop_name"),
                 address: row.get("address"),
                 latitude: latitude_str.parse().unwrap(),
@@ -181,6 +231,11 @@ pub async fn get_shop_by_id(shop_id: i32, client: &Client) -> Option<Shop> {
                         weekday_name: row.get("weekday_name"),
                     })
                     .collect(),
+                owner_name: row.get("owner_name"),
+                phone_numbers: row.get("phone_numbers"),
+                channel: row.get("channel"),
+                size_class: row.get("size_class"),
+                tags: row.get("tags"),
                 created_at: row.get("created_at"),
             })
         }
@@ -197,54 +252,112 @@ pub struct ShopRequest {
     pub image_url: String,
     pub ward_id: i32,
     pub weekdays: Vec<i32>,
+    // Left out, these keep what the shop has; an empty string or list clears
+    // them.
+    pub owner_name: Option<String>,
+    pub phone_numbers: Option<Vec<String>>,
+    // One of SHOP_CHANNELS.
+    pub channel: Option<String>,
+    // One of SHOP_SIZE_CLASSES.
+    pub size_class: Option<String>,
+    pub tags: Option<Vec<String>>,
 }
 
 pub async fn add_shop(
     data: &ShopRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    insert_shop(data, context, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// The ids among `weekday_ids` that are not weekdays.
+pub async fn get_unknown_weekday_ids(weekday_ids: &[i32], client: &Client) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            "select id from unnest($1::int[]) id where id not in (select weekday_id from weekdays)",
+            &[&weekday_ids],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+}
+
+// Creates the shop as part of a larger change, e.g. approving a proposal.
+pub async fn insert_shop(
+    data: &ShopRequest,
+    context: &AuditContext,
+    transaction: &Transaction<'_>,
+) -> Result<i32, Error> {
     let query = format!(
-        "INSERT INTO shops (shop_name, address, latitude, longitude, image_url, ward_id) VALUES ($1, $2, {}, {}, $3, $4) RETURNING shop_id",
+        "INSERT INTO shops (shop_name, address, latitude, longitude, image_url, ward_id, owner_name, phone_numbers, channel, size_class, tags) VALUES ($1, $2, {}, {}, $3, $4, nullif($5::text, ''), coalesce($6::text[], '{{}}'), nullif($7::text, ''), nullif($8::text, ''), coalesce($9::text[], '{{}}')) RETURNING shop_id",
         data.latitude.map_or("NULL".to_string(), |v| v.to_string()),
         data.longitude.map_or("NULL".to_string(), |v| v.to_string())
     );
     
-    let row = client
+    let row = transaction
         .query_one(
             &query,
-            &[&data.shop_name, &data.address, &data.image_url, &data.ward_id],
+            &[
+                &data.shop_name,
+                &data.address,
+                &data.image_url,
+                &data.ward_id,
+                &data.owner_name,
+                &data.phone_numbers,
+                &data.channel,
+                &data.size_class,
+                &data.tags,
+            ],
         )
         .await?;
     let id: i32 = row.get("shop_id");
     for weekday_id in &data.weekdays {
-        client
+        transaction
             .execute(
                 "INSERT INTO shop_weekdays (shop_id, weekday_id) VALUES ($1, $2)",
                 &[&id, &weekday_id],
             )
             .await?;
     }
-    Ok(())
+    audit::record(context, "create", &audit::SHOP, id, None, transaction).await?;
+    Ok(id)
 }
 
 pub async fn update_shop(
     shop_id: i32,
     old_image_url: &str,
     data: &ShopRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP, shop_id, &transaction).await?;
     let query = format!(
-        "UPDATE shops SET shop_name = $1, address = $2, latitude={}, longitude={}, image_url=$3, ward_id=$4 WHERE shop_id = $5",
+        "UPDATE shops SET shop_name = $1, address = $2, latitude={}, longitude={}, image_url=$3, ward_id=$4, owner_name = case when $5::text is null then owner_name else nullif($5, '') end, phone_numbers = coalesce($6::text[], phone_numbers), channel = case when $7::text is null then channel else nullif($7, '') end, size_class = case when $8::text is null then size_class else nullif($8, '') end, tags = coalesce($9::text[], tags) WHERE shop_id = $10",
         data.latitude.map_or("NULL".to_string(), |v| v.to_string()),
         data.longitude.map_or("NULL".to_string(), |v| v.to_string())
     );
-    client
+    transaction
         .execute(
             &query,
-            &[&data.shop_name, &data.address, &data.image_url, &data.ward_id, &shop_id],
+            &[
+                &data.shop_name,
+                &data.address,
+                &data.image_url,
+                &data.ward_id,
+                &data.owner_name,
+                &data.phone_numbers,
+                &data.channel,
+                &data.size_class,
+                &data.tags,
+                &shop_id,
+            ],
         )
         .await?;
-    client
+    transaction
         .execute(
             "DELETE FROM shop_weekdays WHERE shop_id = $1",
             &[&shop_id],
@@ -252,7 +365,7 @@ pub async fn update_shop(
         .await?;
 
     for weekday_id in &data.weekdays {
-        client
+        transaction
             .execute(
                 "INSERT INTO shop_weekdays (shop_id, weekday_id) VALUES ($1, $2)",
                 &[&shop_id, &weekday_id],
@@ -260,7 +373,10 @@ pub async fn update_shop(
             .await?;
     }
 
-    if old_image_url != &data.image_url {
+    audit::record(context, "update", &audit::SHOP, shop_id, before, &transaction).await?;
+    transaction.commit().await?;
+
+    if old_image_url != data.image_url {
         match fs::remove_file(old_image_url) {
             Ok(_) => println!("File deleted successfully!"),
             Err(e) => println!("Error deleting file: {}", e),
@@ -284,13 +400,241 @@ pub async fn update_shop(
     Ok(())
 }
 
-pub async fn delete_shop(shop_id: i32, client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    client
+pub async fn delete_shop(
+    shop_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP, shop_id, &transaction).await?;
+    transaction
         .execute(
             "update shops set deleted_at = CURRENT_TIMESTAMP where shop_id = $1",
             &[&shop_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::SHOP, shop_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
+
+#[derive(Debug, Serialize)]
+pub struct MisplacedShop {
+    pub shop_id: i32,
+    pub shop_name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub ward_id: i32,
+    pub ward_name: String,
+    // The ward the coordinates do fall in, if any has a boundary there.
+    pub suggested_ward_id: Option<i32>,
+    pub suggested_ward_name: Option<String>,
+}
+
+// Shops whose coordinates lie outside the boundary of their ward. Shops without
+// coordinates and wards without a boundary are left out.
+pub async fn get_misplaced_shops(
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<Vec<MisplacedShop>, Error> {
+    let mut query = "select s.shop_id, s.shop_name, s.latitude::float8 latitude, s.longitude::float8 longitude, s.ward_id
+        from shops s
+        where s.deleted_at is null and s.latitude is not null and s.longitude is not null"
+        .to_string();
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        query += &format!(
+            " and exists (select 1 from user_wards uw where uw.ward_id = s.ward_id and uw.user_id = any(${}))",
+            params.len()
+        );
+    }
+    query += " order by s.shop_id";
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let boundaries = ward::get_ward_boundaries(client).await?;
+    let mut shops = vec![];
+    for row in client.query(&query, &params_slice).await? {
+        let ward_id: i32 = row.get("ward_id");
+        let latitude: f64 = row.get("latitude");
+        let longitude: f64 = row.get("longitude");
+        let Some((ward, boundary)) = boundaries.iter().find(|(w, _)| w.ward_id == ward_id) else {
+            continue;
+        };
+        if boundary.contains(latitude, longitude) {
+            continue;
+        }
+        let suggested = boundaries
+            .iter()
+            .find(|(_, b)| b.contains(latitude, longitude))
+            .map(|(w, _)| w);
+        shops.push(MisplacedShop {
+            shop_id: row.get("shop_id"),
+            shop_name: row.get("shop_name"),
+            latitude,
+            longitude,
+            ward_id,
+            ward_name: ward.ward_name.clone(),
+            suggested_ward_id: suggested.map(|w| w.ward_id),
+            suggested_ward_name: suggested.map(|w| w.ward_name.clone()),
+        });
+    }
+    Ok(shops)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NearbyShopsQuery {
+    pub lat: f64,
+    pub lng: f64,
+    pub radius_m: Option<f64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NearbyShop {
+    pub shop_id: i32,
+    pub shop_name: String,
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub image_url: String,
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub distance_m: f64,
+}
+
+// Shops within `radius_m` of the point, nearest first. A bounding box on the
+// indexed coordinates narrows the rows before the haversine distance is taken.
+pub async fn get_nearby_shops(
+    latitude: f64,
+    longitude: f64,
+    radius_m: f64,
+    limit: i64,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<Vec<NearbyShop>, Error> {
+    let (min_lat, max_lat, min_lng, max_lng) = bounding_box(latitude, longitude, radius_m);
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![
+        Box::new(latitude),
+        Box::new(longitude),
+        Box::new(EARTH_RADIUS_M),
+        Box::new(min_lat),
+        Box::new(max_lat),
+        Box::new(min_lng),
+        Box::new(max_lng),
+        Box::new(radius_m),
+        Box::new(limit),
+    ];
+    let mut scope = String::new();
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        scope = format!(
+            " and exists (select 1 from user_wards uw where uw.ward_id = s.ward_id and uw.user_id = any(${}))",
+            params.len()
+        );
+    }
+    let query = format!(
+        "select * from (
+            select s.shop_id, s.shop_name, s.address, s.latitude::float8 latitude, s.longitude::float8 longitude, s.image_url, w.ward_id, w.ward_name,
+            2 * $3::float8 * asin(sqrt(power(sin(radians(s.latitude::float8 - $1::float8) / 2), 2) + cos(radians($1)) * cos(radians(s.latitude::float8)) * power(sin(radians(s.longitude::float8 - $2::float8) / 2), 2))) distance_m
+            from shops s join wards w on w.ward_id = s.ward_id
+            where s.deleted_at is null
+            and s.latitude between $4::float8::numeric and $5::float8::numeric and s.longitude between $6::float8::numeric and $7::float8::numeric{scope}
+        ) n where n.distance_m <= $8 order by n.distance_m limit $9"
+    );
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    Ok(client
+        .query(&query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| NearbyShop {
+            shop_id: row.get("shop_id"),
+            shop_name: row.get("shop_name"),
+            address: row.get("address"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
+            image_url: row.get("image_url"),
+            ward_id: row.get("ward_id"),
+            ward_name: row.get("ward_name"),
+            distance_m: row.get("distance_m"),
+        })
+        .collect())
+}
+
+#[derive(Serialize)]
+pub struct ShopFeatureProperties {
+    pub shop_id: i32,
+    pub shop_name: String,
+    pub address: String,
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub township_id: Option<i32>,
+    pub township_name: Option<String>,
+    pub channel: Option<String>,
+    pub size_class: Option<String>,
+    pub order_count: i64,
+    // Total of the shop's orders that were not cancelled.
+    pub revenue: f64,
+    pub last_order_date: Option<NaiveDateTime>,
+}
+
+// The shops of the shop list, with the same filters and without paging, as
+// point features.
+pub async fn get_shop_features(
+    scope_user_ids: Option<&[i32]>,
+    shop_list_request: &ShopListRequest,
+    client: &Client,
+) -> Result<FeatureCollection<ShopFeatureProperties>, Error> {
+    let (base_query, params) = shop_list_filter(scope_user_ids, shop_list_request);
+    let filter = generate_pagination_query(PaginationOptions {
+        select_columns: "s.shop_id",
+        base_query: &base_query,
+        search_columns: SHOP_SEARCH_COLUMNS.to_vec(),
+        search: shop_list_request.search.as_deref(),
+        order_options: None,
+        page: None,
+        per_page: None,
+    });
+    let query = format!(
+        "select s.shop_id, s.shop_name, coalesce(s.address, '') address, s.latitude::float8 latitude, s.longitude::float8 longitude,
+        w.ward_id, w.ward_name, t.township_id, t.township_name, s.channel, s.size_class,
+        os.order_count, coalesce(os.revenue, 0)::float8 revenue, os.last_order_date
+        from shops s join wards w on w.ward_id = s.ward_id
+        left join townships t on t.township_id = w.township_id
+        left join lateral (
+            select count(*) order_count, sum(o.total_amount) filter (where o.status is distinct from 'Cancelled') revenue, max(o.order_date) last_order_date
+            from orders o where o.shop_id = s.shop_id and o.deleted_at is null
+        ) os on true
+        where s.shop_id in (select f.shop_id from ({}) f)
+        order by s.shop_id",
+        filter.query
+    );
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let features = client
+        .query(&query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| {
+            Feature::new(
+                geo::point(row.get("latitude"), row.get("longitude")),
+                ShopFeatureProperties {
+                    shop_id: row.get("shop_id"),
+                    shop_name: row.get("shop_name"),
+                    address: row.get("address"),
+                    ward_id: row.get("ward_id"),
+                    ward_name: row.get("ward_name"),
+                    township_id: row.get("township_id"),
+                    township_name: row.get("township_name"),
+                    channel: row.get("channel"),
+                    size_class: row.get("size_class"),
+                    order_count: row.get("order_count"),
+                    revenue: row.get("revenue"),
+                    last_order_date: row.get("last_order_date"),
+                },
+            )
+        })
+        .collect();
+    Ok(FeatureCollection::new(features))
+}
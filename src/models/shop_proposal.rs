@@ -0,0 +1,356 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error, Row};
+
+use crate::{
+    models::{
+        audit::{self, AuditContext},
+        shop::{self, ShopRequest},
+    },
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+pub const PROPOSAL_STATUSES: &[&str] = &["pending", "approved", "rejected"];
+
+// A shop a distributor found in the field. It only becomes a shop once
+// approved.
+#[derive(Debug, Serialize)]
+pub struct ShopProposal {
+    pub proposal_id: i32,
+    // Who proposed it.
+    pub user_id: i32,
+    pub full_name: String,
+    pub status: String,
+    pub shop_name: String,
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub image_url: String,
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub weekday_ids: Vec<i32>,
+    pub owner_name: Option<String>,
+    pub phone_numbers: Vec<String>,
+    pub channel: Option<String>,
+    pub size_class: Option<String>,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub review_note: Option<String>,
+    // The shop made from it, once approved.
+    pub shop_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ShopProposal {
+    // The shop as proposed, for creating it on approval.
+    pub fn shop_request(&self) -> ShopRequest {
+        ShopRequest {
+            shop_name: self.shop_name.clone(),
+            address: self.address.clone(),
+            latitude: Some(self.latitude),
+            longitude: Some(self.longitude),
+            image_url: self.image_url.clone(),
+            ward_id: self.ward_id,
+            weekdays: self.weekday_ids.clone(),
+            owner_name: self.owner_name.clone(),
+            phone_numbers: Some(self.phone_numbers.clone()),
+            channel: self.channel.clone(),
+            size_class: self.size_class.clone(),
+            tags: Some(self.tags.clone()),
+        }
+    }
+}
+
+const PROPOSAL_COLUMNS: &str = "sp.proposal_id, sp.user_id, u.full_name, sp.status, sp.shop_name, sp.address, sp.latitude::float8 latitude, sp.longitude::float8 longitude, sp.image_url, sp.ward_id, w.ward_name, sp.weekday_ids, sp.owner_name, sp.phone_numbers, sp.channel, sp.size_class, sp.tags, sp.note, sp.reviewed_by, sp.reviewed_at, sp.review_note, sp.shop_id, sp.created_at";
+
+const PROPOSAL_FROM: &str = "from shop_proposals sp join users u on u.user_id = sp.user_id join wards w on w.ward_id = sp.ward_id";
+
+fn proposal_from_row(row: &Row) -> ShopProposal {
+    ShopProposal {
+        proposal_id: row.get("proposal_id"),
+        user_id: row.get("user_id"),
+        full_name: row.get("full_name"),
+        status: row.get("status"),
+        shop_name: row.get("shop_name"),
+        address: row.get("address"),
+        latitude: row.get("latitude"),
+        longitude: row.get("longitude"),
+        image_url: row.get("image_url"),
+        ward_id: row.get("ward_id"),
+        ward_name: row.get("ward_name"),
+        weekday_ids: row.get("weekday_ids"),
+        owner_name: row.get("owner_name"),
+        phone_numbers: row.get("phone_numbers"),
+        channel: row.get("channel"),
+        size_class: row.get("size_class"),
+        tags: row.get("tags"),
+        note: row.get("note"),
+        reviewed_by: row.get("reviewed_by"),
+        reviewed_at: row.get("reviewed_at"),
+        review_note: row.get("review_note"),
+        shop_id: row.get("shop_id"),
+        created_at: row.get("created_at"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProposalListQuery {
+    pub status: Option<String>,
+    pub user_id: Option<i32>,
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+pub async fn get_proposals(
+    query: &ProposalListQuery,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<PaginationResult<ShopProposal>, Error> {
+    let mut base_query = format!("{PROPOSAL_FROM} where 1 = 1");
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query = format!("{base_query} and sp.user_id = any(${})", params.len());
+    }
+    if let Some(status) = &query.status {
+        params.push(Box::new(status.clone()));
+        base_query = format!("{base_query} and sp.status = ${}", params.len());
+    }
+    if let Some(user_id) = query.user_id {
+        params.push(Box::new(user_id));
+        base_query = format!("{base_query} and sp.user_id = ${}", params.len());
+    }
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: PROPOSAL_COLUMNS,
+        base_query: &base_query,
+        search_columns: vec!["sp.shop_name", "sp.address", "w.ward_name", "u.full_name"],
+        search: query.search.as_deref(),
+        order_options: Some("sp.created_at desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let proposals = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(proposal_from_row)
+        .collect();
+
+    Ok(PaginationResult {
+        data: proposals,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+pub async fn get_proposal_by_id(
+    proposal_id: i32,
+    client: &Client,
+) -> Result<Option<ShopProposal>, Error> {
+    let row = client
+        .query_opt(
+            &format!("select {PROPOSAL_COLUMNS} {PROPOSAL_FROM} where sp.proposal_id = $1"),
+            &[&proposal_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(proposal_from_row))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProposalRequest {
+    #[serde(flatten)]
+    pub shop: ShopRequest,
+    // Anything the reviewer should know.
+    pub note: Option<String>,
+}
+
+pub async fn add_proposal(
+    data: &ProposalRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let shop = &data.shop;
+    let transaction = client.transaction().await?;
+    let proposal_id: i32 = transaction
+        .query_one(
+            "insert into shop_proposals (user_id, shop_name, address, latitude, longitude, image_url, ward_id, weekday_ids, owner_name, phone_numbers, channel, size_class, tags, note)
+            values ($1, $2, $3, $4::float8, $5::float8, $6, $7, $8, nullif($9::text, ''), coalesce($10::text[], '{}'), nullif($11::text, ''), nullif($12::text, ''), coalesce($13::text[], '{}'), $14) returning proposal_id",
+            &[
+                &context.actor_id,
+                &shop.shop_name,
+                &shop.address,
+                &shop.latitude,
+                &shop.longitude,
+                &shop.image_url,
+                &shop.ward_id,
+                &shop.weekdays,
+                &shop.owner_name,
+                &shop.phone_numbers,
+                &shop.channel,
+                &shop.size_class,
+                &shop.tags,
+                &data.note,
+            ],
+        )
+        .await?
+        .get("proposal_id");
+    audit::record(
+        context,
+        "create",
+        &audit::SHOP_PROPOSAL,
+        proposal_id,
+        None,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(proposal_id)
+}
+
+// A reviewer's corrections to a pending proposal. False if it was already
+// decided.
+pub async fn update_proposal(
+    proposal_id: i32,
+    data: &ShopRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<bool, Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP_PROPOSAL, proposal_id, &transaction).await?;
+    let updated = transaction
+        .execute(
+            "update shop_proposals set shop_name = $1, address = $2, latitude = $3::float8, longitude = $4::float8, image_url = $5, ward_id = $6, weekday_ids = $7, owner_name = case when $8::text is null then owner_name else nullif($8, '') end, phone_numbers = coalesce($9::text[], phone_numbers), channel = case when $10::text is null then channel else nullif($10, '') end, size_class = case when $11::text is null then size_class else nullif($11, '') end, tags = coalesce($12::text[], tags)
+            where proposal_id = $13 and status = 'pending'",
+            &[
+                &data.shop_name,
+                &data.address,
+                &data.latitude,
+                &data.longitude,
+                &data.image_url,
+                &data.ward_id,
+                &data.weekdays,
+                &data.owner_name,
+                &data.phone_numbers,
+                &data.channel,
+                &data.size_class,
+                &data.tags,
+                &proposal_id,
+            ],
+        )
+        .await?;
+    if updated == 1 {
+        audit::record(
+            context,
+            "update",
+            &audit::SHOP_PROPOSAL,
+            proposal_id,
+            before,
+            &transaction,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(updated == 1)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewRequest {
+    pub review_note: Option<String>,
+}
+
+// Makes the shop from a pending proposal and returns its id, or `None` if the
+// proposal was already decided.
+pub async fn approve_proposal(
+    proposal_id: i32,
+    review_note: Option<&str>,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<Option<i32>, Error> {
+    let transaction = client.transaction().await?;
+    let proposal = transaction
+        .query_opt(
+            &format!("select {PROPOSAL_COLUMNS} {PROPOSAL_FROM} where sp.proposal_id = $1 and sp.status = 'pending' for update of sp"),
+            &[&proposal_id],
+        )
+        .await?
+        .as_ref()
+        .map(proposal_from_row);
+    let Some(proposal) = proposal else {
+        transaction.rollback().await?;
+        return Ok(None);
+    };
+    let before = audit::snapshot(&audit::SHOP_PROPOSAL, proposal_id, &transaction).await?;
+    let shop_id = shop::insert_shop(&proposal.shop_request(), context, &transaction).await?;
+    transaction
+        .execute(
+            "update shop_proposals set status = 'approved', shop_id = $1, reviewed_by = $2, reviewed_at = CURRENT_TIMESTAMP, review_note = $3 where proposal_id = $4",
+            &[&shop_id, &context.actor_id, &review_note, &proposal_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "update",
+        &audit::SHOP_PROPOSAL,
+        proposal_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(Some(shop_id))
+}
+
+// False if the proposal was already decided.
+pub async fn reject_proposal(
+    proposal_id: i32,
+    review_note: &str,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<bool, Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP_PROPOSAL, proposal_id, &transaction).await?;
+    let updated = transaction
+        .execute(
+            "update shop_proposals set status = 'rejected', reviewed_by = $1, reviewed_at = CURRENT_TIMESTAMP, review_note = $2 where proposal_id = $3 and status = 'pending'",
+            &[&context.actor_id, &review_note, &proposal_id],
+        )
+        .await?;
+    if updated == 1 {
+        audit::record(
+            context,
+            "update",
+            &audit::SHOP_PROPOSAL,
+            proposal_id,
+            before,
+            &transaction,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(updated == 1)
+}
@@ -0,0 +1,302 @@
+use std::env;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error, Row, Transaction};
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+// Why a visit ended without an order.
+pub const NO_ORDER_REASONS: &[&str] = &[
+    "shop_closed",
+    "owner_absent",
+    "enough_stock",
+    "no_budget",
+    "price_too_high",
+    "other",
+];
+
+// How far from the shop, in metres, a check-in may be.
+pub fn visit_radius_m() -> f64 {
+    env::var("VISIT_RADIUS_M")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(200.0)
+}
+
+// Whether a check-in beyond the radius is turned away (`reject`) or accepted
+// and flagged for review (`flag`, the default).
+pub fn reject_outside_radius() -> bool {
+    env::var("VISIT_OUTSIDE_RADIUS").is_ok_and(|v| v == "reject")
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShopVisit {
+    pub visit_id: i32,
+    pub shop_id: i32,
+    pub shop_name: String,
+    pub user_id: i32,
+    pub full_name: String,
+    pub check_in_at: NaiveDateTime,
+    pub check_in_latitude: f64,
+    pub check_in_longitude: f64,
+    // From the shop's coordinates; unknown if the shop has none.
+    pub check_in_distance_m: Option<f64>,
+    // Checked in too far from the shop, or where that could not be told.
+    pub flagged: bool,
+    pub check_out_at: Option<NaiveDateTime>,
+    pub check_out_latitude: Option<f64>,
+    pub check_out_longitude: Option<f64>,
+    pub order_id: Option<i32>,
+    pub no_order_reason: Option<String>,
+    pub note: Option<String>,
+}
+
+const VISIT_COLUMNS: &str = "v.visit_id, v.shop_id, s.shop_name, v.user_id, u.full_name, v.check_in_at, v.check_in_latitude::float8 check_in_latitude, v.check_in_longitude::float8 check_in_longitude, v.check_in_distance_m, v.flagged, v.check_out_at, v.check_out_latitude::float8 check_out_latitude, v.check_out_longitude::float8 check_out_longitude, v.order_id, v.no_order_reason, v.note";
+
+const VISIT_FROM: &str =
+    "from shop_visits v join shops s on s.shop_id = v.shop_id join users u on u.user_id = v.user_id";
+
+fn visit_from_row(row: &Row) -> ShopVisit {
+    ShopVisit {
+        visit_id: row.get("visit_id"),
+        shop_id: row.get("shop_id"),
+        shop_name: row.get("shop_name"),
+        user_id: row.get("user_id"),
+        full_name: row.get("full_name"),
+        check_in_at: row.get("check_in_at"),
+        check_in_latitude: row.get("check_in_latitude"),
+        check_in_longitude: row.get("check_in_longitude"),
+        check_in_distance_m: row.get("check_in_distance_m"),
+        flagged: row.get("flagged"),
+        check_out_at: row.get("check_out_at"),
+        check_out_latitude: row.get("check_out_latitude"),
+        check_out_longitude: row.get("check_out_longitude"),
+        order_id: row.get("order_id"),
+        no_order_reason: row.get("no_order_reason"),
+        note: row.get("note"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisitListQuery {
+    pub user_id: Option<i32>,
+    pub shop_id: Option<i32>,
+    pub from_date: Option<NaiveDate>,
+    pub to_date: Option<NaiveDate>,
+    pub flagged: Option<bool>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+pub async fn get_visits(
+    query: &VisitListQuery,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<PaginationResult<ShopVisit>, Error> {
+    let mut base_query = format!("{VISIT_FROM} where 1 = 1");
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    if let Some(user_ids) = scope_user_ids {
+        params.push(Box::new(user_ids.to_vec()));
+        base_query = format!("{base_query} and v.user_id = any(${})", params.len());
+    }
+    if let Some(user_id) = query.user_id {
+        params.push(Box::new(user_id));
+        base_query = format!("{base_query} and v.user_id = ${}", params.len());
+    }
+    if let Some(shop_id) = query.shop_id {
+        params.push(Box::new(shop_id));
+        base_query = format!("{base_query} and v.shop_id = ${}", params.len());
+    }
+    if let Some(from_date) = query.from_date {
+        params.push(Box::new(from_date));
+        base_query = format!("{base_query} and v.check_in_at::date >= ${}", params.len());
+    }
+    if let Some(to_date) = query.to_date {
+        params.push(Box::new(to_date));
+        base_query = format!("{base_query} and v.check_in_at::date <= ${}", params.len());
+    }
+    if let Some(flagged) = query.flagged {
+        params.push(Box::new(flagged));
+        base_query = format!("{base_query} and v.flagged = ${}", params.len());
+    }
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: VISIT_COLUMNS,
+        base_query: &base_query,
+        search_columns: vec![],
+        search: None,
+        order_options: Some("v.check_in_at desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let visits = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(visit_from_row)
+        .collect();
+
+    Ok(PaginationResult {
+        data: visits,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+pub async fn get_visit_by_id(visit_id: i32, client: &Client) -> Result<Option<ShopVisit>, Error> {
+    let row = client
+        .query_opt(
+            &format!("select {VISIT_COLUMNS} {VISIT_FROM} where v.visit_id = $1"),
+            &[&visit_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(visit_from_row))
+}
+
+// The visit the user has checked in to and not yet out of.
+pub async fn get_open_visit(user_id: i32, client: &Client) -> Result<Option<ShopVisit>, Error> {
+    let row = client
+        .query_opt(
+            &format!("select {VISIT_COLUMNS} {VISIT_FROM} where v.user_id = $1 and v.check_out_at is null"),
+            &[&user_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(visit_from_row))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckInRequest {
+    pub shop_id: i32,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+pub async fn check_in(
+    data: &CheckInRequest,
+    distance_m: Option<f64>,
+    flagged: bool,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let transaction = client.transaction().await?;
+    let visit_id: i32 = transaction
+        .query_one(
+            "insert into shop_visits (shop_id, user_id, check_in_latitude, check_in_longitude, check_in_distance_m, flagged) values ($1, $2, $3::float8, $4::float8, $5, $6) returning visit_id",
+            &[
+                &data.shop_id,
+                &context.actor_id,
+                &data.latitude,
+                &data.longitude,
+                &distance_m,
+                &flagged,
+            ],
+        )
+        .await?
+        .get("visit_id");
+    audit::record(
+        context,
+        "create",
+        &audit::SHOP_VISIT,
+        visit_id,
+        None,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(visit_id)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckOutRequest {
+    pub latitude: f64,
+    pub longitude: f64,
+    // An order placed during the visit, if it was not linked when placed.
+    pub order_id: Option<i32>,
+    pub no_order_reason: Option<String>,
+    pub note: Option<String>,
+}
+
+pub async fn check_out(
+    visit_id: i32,
+    data: &CheckOutRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::SHOP_VISIT, visit_id, &transaction).await?;
+    transaction
+        .execute(
+            "update shop_visits set check_out_at = CURRENT_TIMESTAMP, check_out_latitude = $1::float8, check_out_longitude = $2::float8, order_id = coalesce($3, order_id), no_order_reason = $4, note = $5 where visit_id = $6",
+            &[
+                &data.latitude,
+                &data.longitude,
+                &data.order_id,
+                &data.no_order_reason,
+                &data.note,
+                &visit_id,
+            ],
+        )
+        .await?;
+    audit::record(
+        context,
+        "update",
+        &audit::SHOP_VISIT,
+        visit_id,
+        before,
+        &transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Links an order to the visit it was placed in, as part of placing it.
+pub async fn link_order(
+    visit_id: i32,
+    order_id: i32,
+    context: &AuditContext,
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    let before = audit::snapshot(&audit::SHOP_VISIT, visit_id, transaction).await?;
+    transaction
+        .execute(
+            "update shop_visits set order_id = $1 where visit_id = $2",
+            &[&order_id, &visit_id],
+        )
+        .await?;
+    audit::record(
+        context,
+        "update",
+        &audit::SHOP_VISIT,
+        visit_id,
+        before,
+        transaction,
+    )
+    .await?;
+    Ok(())
+}
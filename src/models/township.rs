@@ -2,41 +2,50 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use tokio_postgres::{types::ToSql, Client, Error};
 
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Township {
     pub township_id: i32,
+    pub region_id: Option<i32>,
     pub township_name: String,
     pub created_at: NaiveDateTime,
 }
 
 pub async fn get_townships(
+    region_id: Option<i32>,
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Township>, Error> {
-    let base_query =
+    let mut base_query =
         "from townships b where b.deleted_at is null".to_string();
-    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(region_id) = region_id {
+        params.push(Box::new(region_id));
+        base_query = format!("{base_query} and b.region_id = ${}", params.len());
+    }
 
-    let order_options = if role == "Distributor" {
-        "township_name"
-    } else {
+    let order_options = if newest_first {
         "b.created_at desc"
+    } else {
+        "township_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "b.township_id, b.township_name,b.created_at",
+        select_columns: "b.township_id, b.region_id, b.township_name,b.created_at",
         base_query: &base_query,
-        search_columns: vec!["b.id::varchar", "b.township_name"],
+        search_columns: vec!["b.township_id::varchar", "b.township_name"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -49,9 +58,9 @@ pub async fn get_townships(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -61,6 +70,7 @@ pub async fn get_townships(
         .iter()
         .map(|row| Township {
             township_id: row.get("township_id"),
+            region_id: row.get("region_id"),
             township_name: row.get("township_name"),
             created_at: row.get("created_at"),
         })
@@ -78,25 +88,31 @@ pub async fn get_townships(
 #[derive(Debug, Deserialize)]
 pub struct TownshipRequest {
     pub township_name: String,
+    pub region_id: Option<i32>,
 }
 
 pub async fn add_township(
     data: &TownshipRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
-        .execute(
-            "insert into townships (township_name) values ($1)",
-            &[&data.township_name],
+    let transaction = client.transaction().await?;
+    let township_id: i32 = transaction
+        .query_one(
+            "insert into townships (township_name, region_id) values ($1, $2) returning township_id",
+            &[&data.township_name, &data.region_id],
         )
-        .await?;
+        .await?
+        .get("township_id");
+    audit::record(context, "create", &audit::TOWNSHIP, township_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn get_township_by_id(township_id: i32, client: &Client) -> Option<Township> {
     let result = client
         .query_one(
-            "select township_id, township_name,created_at from townships  where deleted_at is null and township_id = $1",
+            "select township_id, region_id, township_name,created_at from townships  where deleted_at is null and township_id = $1",
             &[&township_id],
         )
         .await;
@@ -104,6 +120,7 @@ pub async fn get_township_by_id(township_id: i32, client: &Client) -> Option<Tow
     match result {
         Ok(row) => Some(Township {
             township_id: row.get("township_id"),
+            region_id: row.get("region_id"),
             township_name: row.get("township_name"),
             created_at: row.get("created_at"),
         }),
@@ -114,28 +131,48 @@ pub async fn get_township_by_id(township_id: i32, client: &Client) -> Option<Tow
 pub async fn update_township(
     township_id: i32,
     data: &TownshipRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::TOWNSHIP, township_id, &transaction).await?;
+    transaction
         .execute(
-            "update townships set township_name = $1 where township_id = $2",
-            &[&data.township_name, &township_id],
+            "update townships set township_name = $1, region_id = $2 where township_id = $3",
+            &[&data.township_name, &data.region_id, &township_id],
         )
         .await?;
-
+    audit::record(context, "update", &audit::TOWNSHIP, township_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
+// A township with live wards, or shops in them, cannot be deleted.
+pub async fn township_in_use(township_id: i32, client: &Client) -> Result<bool, Error> {
+    let row = client
+        .query_one(
+            "select exists (select 1 from wards w where w.township_id = $1 and w.deleted_at is null)
+            or exists (select 1 from shops s join wards w on w.ward_id = s.ward_id where w.township_id = $1 and s.deleted_at is null) in_use",
+            &[&township_id],
+        )
+        .await?;
+    Ok(row.get("in_use"))
+}
+
 pub async fn delete_township(
     township_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::TOWNSHIP, township_id, &transaction).await?;
+    transaction
         .execute(
             "update townships set deleted_at = CURRENT_TIMESTAMP where township_id = $1",
             &[&township_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::TOWNSHIP, township_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
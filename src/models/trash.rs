@@ -0,0 +1,413 @@
+use std::env;
+
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_postgres::{types::ToSql, Client, Error, Transaction};
+
+use crate::{
+    models::{
+        audit::{self, AuditContext, Entity},
+        ward_assignment,
+    },
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+// A soft-deleted table that can be listed, restored and purged.
+pub struct TrashEntity {
+    pub entity: &'static Entity,
+    // SQL naming a row of `t`, shown in the list and searched.
+    label: &'static str,
+    // Column pointing at a row that has to be live before this one can be
+    // restored, with that row's entity.
+    parent: Option<(&'static str, &'static Entity)>,
+    // Rows owned by the entity, as (table, column), removed along with it on
+    // purge.
+    dependents: &'static [(&'static str, &'static str)],
+}
+
+// In purge order: rows that refer to others come before what they refer to.
+pub const TRASH_ENTITIES: &[TrashEntity] = &[
+    TrashEntity {
+        entity: &audit::DISCOUNT,
+        label: "t.discount_name",
+        parent: None,
+        dependents: &[("product_discounts", "discount_id")],
+    },
+    TrashEntity {
+        entity: &audit::PRICE,
+        label: "concat((select p.product_name from products p where p.product_id = t.product_id), ' - ', t.price_type)",
+        parent: Some(("product_id", &audit::PRODUCT)),
+        dependents: &[("product_discounts", "price_id")],
+    },
+    TrashEntity {
+        entity: &audit::PRODUCT,
+        label: "t.product_name",
+        parent: Some(("brand_id", &audit::BRAND)),
+        dependents: &[("product_categories", "product_id")],
+    },
+    TrashEntity {
+        entity: &audit::BRAND,
+        label: "t.brand_name",
+        parent: None,
+        dependents: &[],
+    },
+    TrashEntity {
+        entity: &audit::CATEGORY,
+        label: "t.category_name",
+        parent: None,
+        dependents: &[("product_categories", "category_id")],
+    },
+    TrashEntity {
+        entity: &audit::SHOP,
+        label: "t.shop_name",
+        parent: Some(("ward_id", &audit::WARD)),
+        dependents: &[("shop_weekdays", "shop_id")],
+    },
+    TrashEntity {
+        entity: &audit::WARD,
+        label: "t.ward_name",
+        parent: Some(("township_id", &audit::TOWNSHIP)),
+        dependents: &[],
+    },
+    TrashEntity {
+        entity: &audit::TOWNSHIP,
+        label: "t.township_name",
+        parent: Some(("region_id", &audit::REGION)),
+        dependents: &[],
+    },
+    TrashEntity {
+        entity: &audit::REGION,
+        label: "t.region_name",
+        parent: None,
+        dependents: &[],
+    },
+    TrashEntity {
+        entity: &audit::USER,
+        label: "concat(t.full_name, ' (', t.username, ')')",
+        parent: None,
+        dependents: &[
+            ("ward_assignments", "user_id"),
+            ("user_sessions", "user_id"),
+            ("user_devices", "user_id"),
+            ("user_mfa", "user_id"),
+            ("user_recovery_codes", "user_id"),
+            ("mfa_challenges", "user_id"),
+        ],
+    },
+    TrashEntity {
+        entity: &audit::ROLE,
+        label: "t.role_name",
+        parent: None,
+        dependents: &[("role_permissions", "role_id")],
+    },
+];
+
+pub fn find(entity_type: &str) -> Option<&'static TrashEntity> {
+    TRASH_ENTITIES.iter().find(|t| t.entity.name == entity_type)
+}
+
+// Days a record stays in the trash before it may be purged.
+pub fn retention_days() -> i64 {
+    env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashItem {
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub label: String,
+    pub deleted_at: NaiveDateTime,
+    pub deleted_by: Option<i32>,
+    pub deleted_by_name: Option<String>,
+    // From then on the record is removed by a purge.
+    pub purge_after: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrashListQuery {
+    pub entity_type: String,
+    pub search: Option<String>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+pub async fn get_trash(
+    trash: &TrashEntity,
+    query: &TrashListQuery,
+    client: &Client,
+) -> Result<PaginationResult<TrashItem>, Error> {
+    let entity = trash.entity;
+    // Who deleted it is taken from the audit log, so it is unknown for
+    // records deleted before the log existed.
+    let base_query = format!(
+        "from {} t
+        left join lateral (select e.actor_id from audit_events e where e.entity_type = '{}' and e.entity_id = t.{id} and e.action = 'delete' order by e.event_id desc limit 1) e on true
+        left join users u on u.user_id = e.actor_id
+        where t.deleted_at is not null",
+        entity.table,
+        entity.name,
+        id = entity.id_column
+    );
+    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    let select_columns = format!(
+        "t.{} entity_id, {} label, t.deleted_at, e.actor_id deleted_by, u.full_name deleted_by_name",
+        entity.id_column, trash.label
+    );
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: &select_columns,
+        base_query: &base_query,
+        search_columns: vec![trash.label],
+        search: query.search.as_deref(),
+        order_options: Some("t.deleted_at desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let retention = Duration::days(retention_days());
+    let items = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(|row| {
+            let deleted_at: NaiveDateTime = row.get("deleted_at");
+            TrashItem {
+                entity_type: entity.name.to_string(),
+                entity_id: row.get("entity_id"),
+                label: row.get("label"),
+                deleted_at,
+                deleted_by: row.get("deleted_by"),
+                deleted_by_name: row.get("deleted_by_name"),
+                purge_after: deleted_at + retention,
+            }
+        })
+        .collect();
+
+    Ok(PaginationResult {
+        data: items,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+pub enum RestoreOutcome {
+    Restored,
+    NotInTrash,
+    // The row it belongs to is deleted too and has to be restored first.
+    ParentDeleted(&'static str),
+    // Another live user has taken the username in the meantime.
+    UsernameTaken,
+    // A live role has been created with the same name in the meantime.
+    RoleNameTaken,
+}
+
+// Brings back what deleting a discount or user took away besides the row: the
+// prices a discount applies to and the wards a user covered. Those rows were
+// marked with the same deletion time as the record, so this runs before the
+// record's own `deleted_at` is cleared.
+async fn restore_links(
+    entity: &Entity,
+    entity_id: i32,
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    if entity.name == audit::DISCOUNT.name {
+        transaction
+            .execute(
+                "update product_discounts pd set deleted_at = null
+                from discounts d, product_prices p
+                where d.discount_id = $1 and pd.discount_id = d.discount_id and pd.deleted_at = d.deleted_at
+                and p.price_id = pd.price_id and p.deleted_at is null",
+                &[&entity_id],
+            )
+            .await?;
+    } else if entity.name == audit::USER.name {
+        let deleted_at: NaiveDateTime = transaction
+            .query_one(
+                "select deleted_at from users where user_id = $1",
+                &[&entity_id],
+            )
+            .await?
+            .get("deleted_at");
+        ward_assignment::restore_user_assignments(entity_id, deleted_at, transaction).await?;
+    }
+    Ok(())
+}
+
+pub async fn restore(
+    trash: &TrashEntity,
+    entity_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<RestoreOutcome, Error> {
+    let entity = trash.entity;
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(entity, entity_id, &transaction).await?;
+    if before
+        .as_ref()
+        .and_then(|b| b.get("deleted_at"))
+        .is_none_or(Value::is_null)
+    {
+        return Ok(RestoreOutcome::NotInTrash);
+    }
+
+    if let Some((column, parent)) = trash.parent {
+        let parent_deleted = transaction
+            .query_one(
+                &format!(
+                    "select exists (select 1 from {} t join {} p on p.{pid} = t.{column} where t.{} = $1 and p.deleted_at is not null) parent_deleted",
+                    entity.table,
+                    parent.table,
+                    entity.id_column,
+                    pid = parent.id_column
+                ),
+                &[&entity_id],
+            )
+            .await?
+            .get("parent_deleted");
+        if parent_deleted {
+            return Ok(RestoreOutcome::ParentDeleted(parent.name));
+        }
+    }
+    if entity.name == audit::USER.name {
+        let taken = transaction
+            .query_one(
+                "select exists (select 1 from users u join users t on lower(t.username) = lower(u.username) where t.user_id = $1 and u.user_id <> $1 and u.deleted_at is null) taken",
+                &[&entity_id],
+            )
+            .await?
+            .get("taken");
+        if taken {
+            return Ok(RestoreOutcome::UsernameTaken);
+        }
+    }
+    if entity.name == audit::ROLE.name {
+        let taken = transaction
+            .query_one(
+                "select exists (select 1 from roles r join roles t on t.role_name = r.role_name where t.role_id = $1 and r.role_id <> $1 and r.deleted_at is null) taken",
+                &[&entity_id],
+            )
+            .await?
+            .get("taken");
+        if taken {
+            return Ok(RestoreOutcome::RoleNameTaken);
+        }
+    }
+
+    restore_links(entity, entity_id, &transaction).await?;
+    transaction
+        .execute(
+            &format!(
+                "update {} set deleted_at = null where {} = $1",
+                entity.table, entity.id_column
+            ),
+            &[&entity_id],
+        )
+        .await?;
+    audit::record(context, "restore", entity, entity_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(RestoreOutcome::Restored)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeResult {
+    pub purged: i64,
+    // Still referenced, e.g. a shop with orders, so left in the trash.
+    pub kept: i64,
+}
+
+// Permanently deletes what has been in the trash longer than the retention
+// period, optionally for one entity type. Each row is removed on its own so
+// one that is still referenced does not stop the rest.
+pub async fn purge(
+    trash_entities: &[&TrashEntity],
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<PurgeResult, Error> {
+    let mut result = PurgeResult::default();
+    let mut transaction = client.transaction().await?;
+    for trash in trash_entities {
+        let entity = trash.entity;
+        let entity_ids: Vec<i32> = transaction
+            .query(
+                &format!(
+                    "select {id} from {} where deleted_at < CURRENT_TIMESTAMP - make_interval(days => $1) order by {id}",
+                    entity.table,
+                    id = entity.id_column
+                ),
+                &[&(retention_days() as i32)],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        for entity_id in entity_ids {
+            let savepoint = transaction.savepoint("purge").await?;
+            match purge_one(trash, entity_id, context, &savepoint).await {
+                Ok(()) => {
+                    savepoint.commit().await?;
+                    result.purged += 1;
+                }
+                Err(err) => {
+                    println!("Keeping {} {} in trash: {:?}", entity.name, entity_id, err);
+                    savepoint.rollback().await?;
+                    result.kept += 1;
+                }
+            }
+        }
+    }
+    transaction.commit().await?;
+    Ok(result)
+}
+
+async fn purge_one(
+    trash: &TrashEntity,
+    entity_id: i32,
+    context: &AuditContext,
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    let entity = trash.entity;
+    let before = audit::snapshot(entity, entity_id, transaction).await?;
+    for (table, column) in trash.dependents {
+        transaction
+            .execute(
+                &format!("delete from {table} where {column} = $1"),
+                &[&entity_id],
+            )
+            .await?;
+    }
+    transaction
+        .execute(
+            &format!(
+                "delete from {} where {} = $1",
+                entity.table, entity.id_column
+            ),
+            &[&entity_id],
+        )
+        .await?;
+    audit::record(context, "purge", entity, entity_id, before, transaction).await?;
+    Ok(())
+}
@@ -1,11 +1,17 @@
 use bcrypt::{hash, DEFAULT_COST};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{types::ToSql, Client, Error};
-
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use tokio_postgres::{types::ToSql, Client, Error, Transaction};
+
+use crate::{
+    models::{
+        audit::{self, AuditContext},
+        session, ward_assignment,
+    },
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)] // Add Debug derive
@@ -19,35 +25,98 @@ pub struct User {
     pub userid: i32,
     pub fullname: String,
     pub username: String,
-    pub password: String,
     pub role: String,
+    pub must_change_password: bool,
+    // The manager this user reports to.
+    pub manager_id: Option<i32>,
     pub created_at: NaiveDateTime,
     pub wards: Vec<UserWard>,
 }
 
+// What login and password changes need to know about a user. Kept apart from
+// `User` so that the hash never ends up in a response.
+pub struct UserCredentials {
+    pub userid: i32,
+    pub fullname: String,
+    pub username: String,
+    pub password_hash: String,
+    pub role: String,
+    pub must_change_password: bool,
+}
+
+fn to_credentials(row: &tokio_postgres::Row) -> UserCredentials {
+    UserCredentials {
+        userid: row.get("user_id"),
+        fullname: row.get("full_name"),
+        username: row.get("username"),
+        password_hash: row.get("password"),
+        role: row.get("role"),
+        must_change_password: row.get("must_change_password"),
+    }
+}
 
-pub async fn get_user(username: &str, client: &Client) -> Option<User> {
+pub async fn get_user_credentials(username: &str, client: &Client) -> Option<UserCredentials> {
     let result = client
         .query_one(
-            "select user_id,full_name,username,password,role,created_at from users  where username = $1 and deleted_at is null",
+            "select user_id,full_name,username,password,role,must_change_password from users  where username = $1 and deleted_at is null",
             &[&username],
         )
         .await;
 
     match result {
-        Ok(row) => Some(User {
-            userid: row.get("user_id"),
-            fullname: row.get("full_name"),
-            username: row.get("username"),
-            password: row.get("password"),
-            role: row.get("role"),
-            created_at: row.get("created_at"),
-            wards: Vec::new(), 
-        }),
+        Ok(row) => Some(to_credentials(&row)),
         Err(_) => None,
     }
 }
 
+pub async fn get_user_credentials_by_id(user_id: i32, client: &Client) -> Option<UserCredentials> {
+    let result = client
+        .query_one(
+            "select user_id,full_name,username,password,role,must_change_password from users  where user_id = $1 and deleted_at is null",
+            &[&user_id],
+        )
+        .await;
+
+    match result {
+        Ok(row) => Some(to_credentials(&row)),
+        Err(_) => None,
+    }
+}
+
+async fn store_password(
+    user_id: i32,
+    password: &str,
+    must_change_password: bool,
+    transaction: &Transaction<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let hashed_password =
+        hash(password, DEFAULT_COST).map_err(|e| format!("Failed to hash password: {}", e))?;
+    transaction
+        .execute(
+            "update users set password = $1, must_change_password = $2 where user_id = $3",
+            &[&hashed_password, &must_change_password, &user_id],
+        )
+        .await?;
+    Ok(())
+}
+
+// Stores a new password. `must_change_password` is set when someone other
+// than the user picked it, so that the user has to replace it after logging in.
+pub async fn set_password(
+    user_id: i32,
+    password: &str,
+    must_change_password: bool,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    store_password(user_id, password, must_change_password, &transaction).await?;
+    audit::record(context, "update", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
 #[derive(Deserialize)]
 pub struct AddUserRequest {
     pub fullname: String,
@@ -55,71 +124,124 @@ pub struct AddUserRequest {
     pub password: String,
     pub role: String,
     pub ward_ids: Vec<i32>,
-
+    pub manager_id: Option<i32>,
 }
 
-
-
-pub async fn add_user(
+async fn insert_user(
     data: &AddUserRequest,
-    client: &Client,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let hashed_password = hash(&data.password, DEFAULT_COST)
-        .map_err(|e| format!("Failed to hash password: {}", e))?;
-
+    hashed_password: &str,
+    must_change_password: bool,
+    context: &AuditContext,
+    transaction: &Transaction<'_>,
+) -> Result<i32, Box<dyn std::error::Error>> {
     // Insert user into the users table
     let user_insert_query = "
-        insert into users (full_name, username, password, role)
-        values ($1, $2, $3, $4)
+        insert into users (full_name, username, password, role, manager_id, must_change_password)
+        values ($1, $2, $3, $4, $5, $6)
         RETURNING user_id
     ";
-    let user_id: i32 = client
+    let user_id: i32 = transaction
         .query_one(
             user_insert_query,
-            &[&data.fullname, &data.username, &hashed_password, &data.role],
+            &[
+                &data.fullname,
+                &data.username,
+                &hashed_password,
+                &data.role,
+                &data.manager_id,
+                &must_change_password,
+            ],
         )
         .await?
         .get("user_id");
 
-    // Insert user-ward relationship into the user_wards table
-    let user_wards_insert_query = "
-        insert into user_wards (user_id, ward_id)
-        values ($1, $2)
-    ";
-    for ward_id in &data.ward_ids {
-        client
-            .execute(user_wards_insert_query, &[&user_id, &ward_id])
-            .await?;
-    }
+    ward_assignment::set_current_wards(user_id, &data.ward_ids, transaction).await?;
+    audit::record(context, "create", &audit::USER, user_id, None, transaction).await?;
+    Ok(user_id)
+}
 
+pub async fn add_user(
+    data: &AddUserRequest,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let hashed_password = hash(&data.password, DEFAULT_COST)
+        .map_err(|e| format!("Failed to hash password: {}", e))?;
+    let transaction = client.transaction().await?;
+    insert_user(data, &hashed_password, false, context, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
+// Hashes the passwords of users about to be imported. Slow on purpose, so
+// callers run it off the async workers and without holding the client.
+pub fn hash_passwords(users: &[AddUserRequest]) -> Result<Vec<String>, bcrypt::BcryptError> {
+    users.iter().map(|u| hash(&u.password, DEFAULT_COST)).collect()
+}
 
+pub enum ImportOutcome {
+    Created(Vec<i32>),
+    // Indexes of the users whose username was taken after the file was
+    // checked. Nobody was created.
+    UsernamesTaken(Vec<usize>),
+}
+
+// Creates all users or, if any of them fails, none. Their passwords were
+// picked for them, so they have to change them after logging in.
+// `hashed_passwords` are those of `users`, in the same order.
+pub async fn import_users(
+    users: &[AddUserRequest],
+    hashed_passwords: &[String],
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<ImportOutcome, Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    // The client was let go while the passwords were hashed, so the usernames
+    // are checked again.
+    let usernames: Vec<String> = users.iter().map(|u| u.username.to_lowercase()).collect();
+    let taken: Vec<String> = transaction
+        .query(
+            "select lower(username) username from users where lower(username) = any($1) and deleted_at is null",
+            &[&usernames],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("username"))
+        .collect();
+    if !taken.is_empty() {
+        transaction.rollback().await?;
+        return Ok(ImportOutcome::UsernamesTaken(
+            (0..usernames.len())
+                .filter(|&i| taken.contains(&usernames[i]))
+                .collect(),
+        ));
+    }
+    let mut user_ids = vec![];
+    for (data, hashed_password) in users.iter().zip(hashed_passwords) {
+        user_ids.push(insert_user(data, hashed_password, true, context, &transaction).await?);
+    }
+    transaction.commit().await?;
+    Ok(ImportOutcome::Created(user_ids))
+}
 
 pub async fn get_users(
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
     client: &Client,
 ) -> Result<PaginationResult<User>, Error> {
     let base_query =
         "from users u where u.deleted_at is null".to_string();
     let params: Vec<Box<dyn ToSql + Sync>> = vec![];
 
-    let order_options = if role == "Distributor" {
-        "full_name"
-    } else {
-        "u.created_at desc"
-    };
+    let order_options = "u.created_at desc";
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "u.user_id, u.full_name,u.username,u.password, u.role, u.created_at",
+        select_columns: "u.user_id, u.full_name,u.username, u.role, u.must_change_password, u.manager_id, u.created_at",
         base_query: &base_query,
         search_columns: vec!["u.user_id, u.full_name,u.username, u.role"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -132,9 +254,9 @@ pub async fn get_users(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -155,8 +277,9 @@ pub async fn get_users(
             userid: user_id,
             fullname: row.get("full_name"),
             username: row.get("username"),
-            password: row.get("password"),
             role: row.get("role"),
+            must_change_password: row.get("must_change_password"),
+            manager_id: row.get("manager_id"),
             created_at: row.get("created_at"),
             wards: wards_rows
                 .iter()
@@ -178,7 +301,7 @@ pub async fn get_users(
 }
 
 pub async fn get_user_by_id(user_id: i32, client: &Client) -> Option<User> {
-    let result = client.query_one("select user_id,full_name, username, password, role, created_at from users  where deleted_at is null  and user_id = $1", &[&user_id]).await;
+    let result = client.query_one("select user_id,full_name, username, role, must_change_password, manager_id, created_at from users  where deleted_at is null  and user_id = $1", &[&user_id]).await;
     let wards_rows = match client
         .query(
             "SELECT uw.ward_id, w.ward_name FROM user_wards uw JOIN wards w ON w.ward_id = uw.ward_id WHERE uw.user_id = $1",
@@ -197,8 +320,9 @@ pub async fn get_user_by_id(user_id: i32, client: &Client) -> Option<User> {
             userid: row.get("user_id"),
             fullname: row.get("full_name"),
             username: row.get("username"),
-            password: row.get("password"),
             role: row.get("role"),
+            must_change_password: row.get("must_change_password"),
+            manager_id: row.get("manager_id"),
             created_at: row.get("created_at"),
             wards: wards_rows
                     .iter()
@@ -218,72 +342,166 @@ pub async fn get_user_by_id(user_id: i32, client: &Client) -> Option<User> {
 #[derive(Deserialize)]
 pub struct UpdateUserRequest {
     pub fullname: String,
-    pub password: String,
+    pub password: Option<String>,
     pub role: String,
-    pub ward_ids: Vec<i32>,
+    pub ward_ids: Option<Vec<i32>>,
 }
 
 pub async fn update_user(
-    old_password: &str,
     data: &UpdateUserRequest,
     user_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let password: &str = &data.password;
-    let mut hashed_password = password.to_string();
-
-    if password != old_password {
-        hashed_password = hash(&data.password.as_str(), DEFAULT_COST)
-            .map_err(|e| format!("Failed to hash password: {}", e))?;
-    }
-
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    transaction
         .execute(
-            "update users set full_name = $1, password = $2, role = $3 where user_id = $4",
-            &[
-                &data.fullname,
-                &hashed_password,
-                &data.role,
-                &user_id,
-            ],
+            "update users set full_name = $1, role = $2 where user_id = $3",
+            &[&data.fullname, &data.role, &user_id],
         )
         .await?;
-    client.execute("delete from user_wards where user_id = $1",&[&user_id],).await?;
-
-    let user_wards_insert_query = "insert into user_wards (user_id, ward_id) values ($1, $2) ";
-    for ward_id in &data.ward_ids {
-        client
-            .execute(user_wards_insert_query, &[&user_id, &ward_id])
-            .await?;
+    let password = data.password.as_deref().filter(|p| !p.is_empty());
+    if let Some(password) = password {
+        store_password(user_id, password, true, &transaction).await?;
+    }
+    // Leaving the wards out keeps the territory as it is; it is managed
+    // through the ward assignment endpoints.
+    if let Some(ward_ids) = &data.ward_ids {
+        ward_assignment::set_current_wards(user_id, ward_ids, &transaction).await?;
+    }
+    audit::record(context, "update", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    if password.is_some() {
+        session::revoke_user_sessions(user_id, client).await?;
     }
     Ok(())
 }
 
-pub async fn delete_user(user_id: i32, client: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    client
+pub async fn delete_user(
+    user_id: i32,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    transaction
         .execute(
             "update users set deleted_at = CURRENT_TIMESTAMP where user_id = $1 and deleted_at is null",
             &[&user_id],
         )
         .await?;
-    client
-        .execute(
-            "delete from user_wards where user_id = $1",
-            &[&user_id],
-        )
-        .await?;
+    ward_assignment::end_user_assignments(user_id, &transaction).await?;
+    audit::record(context, "delete", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    session::revoke_user_sessions(user_id, client).await?;
     Ok(())
 }
 
+// Usernames are unique regardless of case.
 pub async fn user_exists(username: &str, client: &Client) -> Result<bool, Error> {
-    // Execute a query to check if the username exists in the users table
     let row = client
-        .query_one(
-            "SELECT username FROM users WHERE username = $1 and deleted_at is null",
+        .query_opt(
+            "SELECT username FROM users WHERE lower(username) = lower($1) and deleted_at is null limit 1",
             &[&username],
         )
-        .await;
+        .await?;
+    Ok(row.is_some())
+}
+
+pub async fn get_user_ward_ids(user_id: i32, client: &Client) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            "select ward_id from user_wards where user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("ward_id")).collect())
+}
 
-    // Return whether the user exists
-    Ok(row.is_ok())
+// Ids of all users, or only those with `role`.
+pub async fn get_user_ids(role: Option<&str>, client: &Client) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            "select user_id from users where deleted_at is null and ($1::text is null or role = $1)",
+            &[&role],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("user_id")).collect())
+}
+
+// The user and everyone reporting to them, directly or further down.
+pub async fn get_team_user_ids(user_id: i32, client: &Client) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            "with recursive team as (
+                select user_id from users where user_id = $1
+                union
+                select u.user_id from users u join team t on u.manager_id = t.user_id where u.deleted_at is null
+            )
+            select user_id from team",
+            &[&user_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("user_id")).collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamMember {
+    pub user_id: i32,
+    pub full_name: String,
+    pub username: String,
+    pub role: String,
+    pub manager_id: Option<i32>,
+}
+
+pub async fn get_team(user_id: i32, client: &Client) -> Result<Vec<TeamMember>, Error> {
+    let team_user_ids = get_team_user_ids(user_id, client).await?;
+    let rows = client
+        .query(
+            "select user_id, full_name, username, role, manager_id from users where user_id = any($1) and user_id <> $2 and deleted_at is null order by full_name",
+            &[&team_user_ids, &user_id],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| TeamMember {
+            user_id: row.get("user_id"),
+            full_name: row.get("full_name"),
+            username: row.get("username"),
+            role: row.get("role"),
+            manager_id: row.get("manager_id"),
+        })
+        .collect())
+}
+
+// Whether `manager_id` can be made the manager of `user_id`: it has to be
+// another existing user who does not already report to `user_id`.
+pub async fn is_valid_manager(user_id: Option<i32>, manager_id: i32, client: &Client) -> Result<bool, Error> {
+    if get_user_by_id(manager_id, client).await.is_none() {
+        return Ok(false);
+    }
+    match user_id {
+        Some(user_id) => Ok(!get_team_user_ids(user_id, client).await?.contains(&manager_id)),
+        None => Ok(true),
+    }
+}
+
+pub async fn set_manager(
+    user_id: i32,
+    manager_id: Option<i32>,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::USER, user_id, &transaction).await?;
+    transaction
+        .execute(
+            "update users set manager_id = $2 where user_id = $1",
+            &[&user_id, &manager_id],
+        )
+        .await?;
+    audit::record(context, "update", &audit::USER, user_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
 }
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{Client, Error};
+
+use crate::{
+    models::{
+        role,
+        user::{self, AddUserRequest},
+    },
+    utils::password::{generate_password, validate_password},
+};
+
+// Most rows one upload may hold; bcrypt makes each one take a while.
+pub const MAX_IMPORT_ROWS: usize = 500;
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    // Only check the rows, nothing is created.
+    #[serde(default)]
+    pub dry_run: bool,
+    // Give rows without a password a random one, returned once in the
+    // response. Without it a password is required on every row.
+    #[serde(default)]
+    pub generate_passwords: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    // Line in the file, counting the header as line 1.
+    pub row: usize,
+    pub username: String,
+    pub errors: Vec<String>,
+    pub user_id: Option<i32>,
+    pub temporary_password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResult {
+    pub dry_run: bool,
+    pub created: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+impl ImportResult {
+    pub fn has_errors(&self) -> bool {
+        self.rows.iter().any(|row| !row.errors.is_empty())
+    }
+}
+
+// A row of the file and the user it would create.
+pub struct ImportRow {
+    pub result: ImportRowResult,
+    pub user: AddUserRequest,
+}
+
+const COLUMNS: [&str; 6] = [
+    "fullname",
+    "username",
+    "password",
+    "role",
+    "ward_ids",
+    "manager_id",
+];
+const REQUIRED_COLUMNS: [&str; 3] = ["fullname", "username", "role"];
+
+// Reads the rows under a header naming the columns of `POST /api/users`
+// (`fullname, username, password, role, ward_ids, manager_id`, in any order).
+// `ward_ids` holds ids separated by `;`, `,` or spaces. Fails only on a bad
+// header; problems with a row are collected on the row.
+pub fn parse_rows(rows: &[Vec<String>]) -> Result<Vec<ImportRow>, String> {
+    let (header, rows) = rows
+        .split_first()
+        .ok_or_else(|| String::from("The file is empty!"))?;
+    let header: Vec<String> = header.iter().map(|h| h.to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    if let Some(unknown) = header
+        .iter()
+        .find(|h| !h.is_empty() && !COLUMNS.contains(&h.as_str()))
+    {
+        return Err(format!("Unknown column: {}", unknown));
+    }
+    if let Some(missing) = REQUIRED_COLUMNS.iter().find(|c| column(c).is_none()) {
+        return Err(format!("Missing column: {}", missing));
+    }
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(format!(
+            "A file may hold at most {} users!",
+            MAX_IMPORT_ROWS
+        ));
+    }
+
+    let cell = |row: &Vec<String>, name: &str| -> String {
+        column(name)
+            .and_then(|i| row.get(i))
+            .cloned()
+            .unwrap_or_default()
+    };
+    let mut parsed = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        if row.iter().all(String::is_empty) {
+            continue;
+        }
+        let mut errors = vec![];
+        let ward_ids = cell(row, "ward_ids")
+            .split(|c: char| c == ';' || c == ',' || c.is_whitespace())
+            .filter(|id| !id.is_empty())
+            .filter_map(|id| match id.parse() {
+                Ok(id) => Some(id),
+                Err(_) => {
+                    errors.push(format!("Invalid ward id: {}", id));
+                    None
+                }
+            })
+            .collect();
+        let manager_id = match cell(row, "manager_id") {
+            id if id.is_empty() => None,
+            id => match id.parse() {
+                Ok(id) => Some(id),
+                Err(_) => {
+                    errors.push(format!("Invalid manager id: {}", id));
+                    None
+                }
+            },
+        };
+        let user = AddUserRequest {
+            fullname: cell(row, "fullname"),
+            username: cell(row, "username"),
+            password: cell(row, "password"),
+            role: cell(row, "role"),
+            ward_ids,
+            manager_id,
+        };
+        parsed.push(ImportRow {
+            result: ImportRowResult {
+                row: i + 2,
+                username: user.username.clone(),
+                errors,
+                user_id: None,
+                temporary_password: None,
+            },
+            user,
+        });
+    }
+    Ok(parsed)
+}
+
+// Applies the checks of `POST /api/users` to every row, plus usernames that
+// repeat within the file. Passwords are generated here when asked for.
+pub async fn validate_rows(
+    rows: &mut [ImportRow],
+    generate_passwords: bool,
+    client: &Client,
+) -> Result<(), Error> {
+    let ward_ids: Vec<i32> = rows
+        .iter()
+        .flat_map(|r| r.user.ward_ids.iter().copied())
+        .collect();
+    let known_wards: HashSet<i32> = client
+        .query(
+            "select ward_id from wards where ward_id = any($1) and deleted_at is null",
+            &[&ward_ids],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("ward_id"))
+        .collect();
+
+    let mut usernames = HashSet::new();
+    for row in rows.iter_mut() {
+        let user = &mut row.user;
+        let errors = &mut row.result.errors;
+        if user.fullname.is_empty() {
+            errors.push(String::from("Name must not be empty!"));
+        }
+        if user.username.is_empty() {
+            errors.push(String::from("Username must not be empty!"));
+        } else if !usernames.insert(user.username.to_lowercase()) {
+            errors.push(String::from("Username appears more than once in the file!"));
+        } else if user::user_exists(&user.username, client).await? {
+            errors.push(String::from("User already exists!"));
+        }
+        if user.password.is_empty() && generate_passwords {
+            user.password = generate_password();
+            row.result.temporary_password = Some(user.password.clone());
+        } else if let Err(message) = validate_password(&user.password) {
+            errors.push(message);
+        }
+        if !role::role_exists(&user.role, client).await? {
+            errors.push(format!("Role does not exist: {}", user.role));
+        }
+        for ward_id in user.ward_ids.iter().filter(|w| !known_wards.contains(w)) {
+            errors.push(format!("Ward does not exist: {}", ward_id));
+        }
+        if let Some(manager_id) = user.manager_id {
+            if !user::is_valid_manager(None, manager_id, client).await? {
+                errors.push(format!("Manager does not exist: {}", manager_id));
+            }
+        }
+    }
+    Ok(())
+}
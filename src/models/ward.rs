@@ -1,10 +1,15 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use tokio_postgres::{types::ToSql, Client, Error};
-
-use crate::utils::{
-    common_struct::PaginationResult,
-    sql::{generate_pagination_query, PaginationOptions},
+use serde_json::Value;
+use tokio_postgres::{types::ToSql, Client, Error, Row};
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        geo::Boundary,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,32 +17,49 @@ pub struct Ward {
     pub ward_id: i32,
     pub township_id: i32,
     pub ward_name: String,
+    // GeoJSON Polygon or MultiPolygon.
+    pub boundary: Option<Value>,
     pub created_at: NaiveDateTime,
 }
 
+fn ward_from_row(row: &Row) -> Ward {
+    Ward {
+        ward_id: row.get("ward_id"),
+        township_id: row.get("township_id"),
+        ward_name: row.get("ward_name"),
+        boundary: row.get("boundary"),
+        created_at: row.get("created_at"),
+    }
+}
+
 pub async fn get_wards(
+    township_id: Option<i32>,
     search: &Option<String>,
     page: Option<usize>,
     per_page: Option<usize>,
-    role: &str,
+    newest_first: bool,
     client: &Client,
 ) -> Result<PaginationResult<Ward>, Error> {
-    let base_query =
+    let mut base_query =
         "from wards b where b.deleted_at is null".to_string();
-    let params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+    if let Some(township_id) = township_id {
+        params.push(Box::new(township_id));
+        base_query = format!("{base_query} and b.township_id = ${}", params.len());
+    }
 
-    let order_options = if role == "Distributor" {
-        "ward_name"
-    } else {
+    let order_options = if newest_first {
         "b.created_at desc"
+    } else {
+        "ward_name"
     };
 
     let result = generate_pagination_query(PaginationOptions {
-        select_columns: "b.ward_id, b.township_id, b.ward_name,b.created_at",
+        select_columns: "b.ward_id, b.township_id, b.ward_name, b.boundary, b.created_at",
         base_query: &base_query,
         search_columns: vec!["b.ward_id::varchar", "b.ward_name"],
         search: search.as_deref(),
-        order_options: Some(&order_options),
+        order_options: Some(order_options),
         page,
         per_page,
     });
@@ -50,9 +72,9 @@ pub async fn get_wards(
     let mut page_counts = 0;
     let mut current_page = 0;
     let mut limit = 0;
-    if page.is_some() && per_page.is_some() {
-        current_page = page.unwrap();
-        limit = per_page.unwrap();
+    if let (Some(p), Some(pp)) = (page, per_page) {
+        current_page = p;
+        limit = pp;
         page_counts = (total as f64 / limit as f64).ceil() as usize;
     }
 
@@ -60,12 +82,7 @@ pub async fn get_wards(
         .query(&result.query, &params_slice)
         .await?
         .iter()
-        .map(|row| Ward {
-            ward_id: row.get("ward_id"),
-            township_id: row.get("township_id"),
-            ward_name: row.get("ward_name"),
-            created_at: row.get("created_at"),
-        })
+        .map(ward_from_row)
         .collect();
 
     Ok(PaginationResult {
@@ -81,36 +98,38 @@ pub async fn get_wards(
 pub struct WardRequest {
     pub ward_name: String,
     pub township_id: i32,
+    #[serde(default)]
+    pub boundary: Option<Value>,
 }
 
 pub async fn add_ward(
     data: &WardRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
-        .execute(
-            "insert into wards (ward_name,township_id) values ($1,$2)",
-            &[&data.ward_name, &data.township_id],
+    let transaction = client.transaction().await?;
+    let ward_id: i32 = transaction
+        .query_one(
+            "insert into wards (ward_name,township_id,boundary) values ($1,$2,$3) returning ward_id",
+            &[&data.ward_name, &data.township_id, &data.boundary],
         )
-        .await?;
+        .await?
+        .get("ward_id");
+    audit::record(context, "create", &audit::WARD, ward_id, None, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn get_ward_by_id(ward_id: i32, client: &Client) -> Option<Ward> {
     let result = client
         .query_one(
-            "select ward_id,township_id, ward_name,created_at from wards  where deleted_at is null and ward_id = $1",
+            "select ward_id,township_id, ward_name,boundary,created_at from wards  where deleted_at is null and ward_id = $1",
             &[&ward_id],
         )
         .await;
 
     match result {
-        Ok(row) => Some(Ward {
-            ward_id: row.get("ward_id"),
-            township_id: row.get("township_id"),
-            ward_name: row.get("ward_name"),
-            created_at: row.get("created_at"),
-        }),
+        Ok(row) => Some(ward_from_row(&row)),
         Err(_) => None,
     }
 }
@@ -118,28 +137,68 @@ pub async fn get_ward_by_id(ward_id: i32, client: &Client) -> Option<Ward> {
 pub async fn update_ward(
     ward_id: i32,
     data: &WardRequest,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::WARD, ward_id, &transaction).await?;
+    transaction
         .execute(
-            "update wards set ward_name = $1, township_id =$2 where ward_id = $3",
-            &[&data.ward_name, &data.township_id, &ward_id],
+            "update wards set ward_name = $1, township_id =$2, boundary = $3 where ward_id = $4",
+            &[&data.ward_name, &data.township_id, &data.boundary, &ward_id],
         )
         .await?;
-
+    audit::record(context, "update", &audit::WARD, ward_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
 pub async fn delete_ward(
     ward_id: i32,
-    client: &Client,
+    context: &AuditContext,
+    client: &mut Client,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    client
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::WARD, ward_id, &transaction).await?;
+    transaction
         .execute(
             "update wards set deleted_at = CURRENT_TIMESTAMP where ward_id = $1",
             &[&ward_id],
         )
         .await?;
-
+    audit::record(context, "delete", &audit::WARD, ward_id, before, &transaction).await?;
+    transaction.commit().await?;
     Ok(())
 }
+
+// Live wards with a boundary, parsed for point lookups. Boundaries are checked
+// when saved, so one that does not parse is skipped.
+pub async fn get_ward_boundaries(client: &Client) -> Result<Vec<(Ward, Boundary)>, Error> {
+    Ok(client
+        .query(
+            "select ward_id, township_id, ward_name, boundary, created_at from wards where deleted_at is null and boundary is not null order by ward_id",
+            &[],
+        )
+        .await?
+        .iter()
+        .map(ward_from_row)
+        .filter_map(|ward| {
+            let boundary = Boundary::parse(ward.boundary.as_ref()?).ok()?;
+            Some((ward, boundary))
+        })
+        .collect())
+}
+
+// Wards whose boundary holds the point. More than one if boundaries overlap.
+pub async fn get_wards_at(
+    latitude: f64,
+    longitude: f64,
+    client: &Client,
+) -> Result<Vec<Ward>, Error> {
+    Ok(get_ward_boundaries(client)
+        .await?
+        .into_iter()
+        .filter(|(_, boundary)| boundary.contains(latitude, longitude))
+        .map(|(ward, _)| ward)
+        .collect())
+}
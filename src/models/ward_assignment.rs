@@ -0,0 +1,462 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Client, Error, Transaction};
+
+use crate::{
+    models::audit::{self, AuditContext},
+    utils::{
+        common_struct::PaginationResult,
+        sql::{generate_pagination_query, PaginationOptions},
+    },
+};
+
+// Who covers which ward and when. `effective_to` is the last day covered, or
+// open ended. A row with `delegated_from_user_id` is temporary cover: while it
+// runs, the delegate works the ward in place of that user.
+//
+// `user_wards` is a view over the rows that are in effect today, so existing
+// ward scoping keeps working off it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WardAssignment {
+    pub assignment_id: i32,
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub user_id: i32,
+    pub full_name: String,
+    pub effective_from: NaiveDate,
+    pub effective_to: Option<NaiveDate>,
+    pub delegated_from_user_id: Option<i32>,
+    pub delegated_from_name: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+// `active_on` limits the list to assignments in effect on that day; without
+// it, the full history is returned.
+#[derive(Debug, Deserialize)]
+pub struct WardAssignmentListQuery {
+    pub user_id: Option<i32>,
+    pub ward_id: Option<i32>,
+    pub active_on: Option<NaiveDate>,
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+// SQL condition for the assignment `alias` being in effect on `date`.
+fn active_on(alias: &str, date: &str) -> String {
+    format!("{alias}.effective_from <= {date} and ({alias}.effective_to is null or {alias}.effective_to >= {date})")
+}
+
+pub async fn get_ward_assignments(
+    query: &WardAssignmentListQuery,
+    client: &Client,
+) -> Result<PaginationResult<WardAssignment>, Error> {
+    let mut base_query = "from ward_assignments a
+        join wards w on w.ward_id = a.ward_id
+        join users u on u.user_id = a.user_id
+        left join users du on du.user_id = a.delegated_from_user_id
+        where 1 = 1"
+        .to_string();
+    let mut params: Vec<Box<dyn ToSql + Sync>> = vec![];
+
+    if let Some(user_id) = query.user_id {
+        params.push(Box::new(user_id));
+        base_query = format!("{base_query} and a.user_id = ${}", params.len());
+    }
+    if let Some(ward_id) = query.ward_id {
+        params.push(Box::new(ward_id));
+        base_query = format!("{base_query} and a.ward_id = ${}", params.len());
+    }
+    if let Some(date) = query.active_on {
+        params.push(Box::new(date));
+        base_query = format!(
+            "{base_query} and {}",
+            active_on("a", &format!("${}", params.len()))
+        );
+    }
+
+    let result = generate_pagination_query(PaginationOptions {
+        select_columns: "a.assignment_id, a.ward_id, w.ward_name, a.user_id, u.full_name, a.effective_from, a.effective_to, a.delegated_from_user_id, du.full_name delegated_from_name, a.created_at",
+        base_query: &base_query,
+        search_columns: vec![],
+        search: None,
+        order_options: Some("a.effective_from desc, a.assignment_id desc"),
+        page: query.page,
+        per_page: query.per_page,
+    });
+
+    let params_slice: Vec<&(dyn ToSql + Sync)> = params.iter().map(AsRef::as_ref).collect();
+
+    let row = client.query_one(&result.count_query, &params_slice).await?;
+    let total: i64 = row.get("total");
+
+    let mut page_counts = 0;
+    let mut current_page = 0;
+    let mut limit = 0;
+    if let (Some(p), Some(pp)) = (query.page, query.per_page) {
+        current_page = p;
+        limit = pp;
+        page_counts = (total as f64 / limit as f64).ceil() as usize;
+    }
+
+    let assignments = client
+        .query(&result.query, &params_slice)
+        .await?
+        .iter()
+        .map(to_ward_assignment)
+        .collect();
+
+    Ok(PaginationResult {
+        data: assignments,
+        total,
+        page: current_page,
+        per_page: limit,
+        page_counts,
+    })
+}
+
+fn to_ward_assignment(row: &tokio_postgres::Row) -> WardAssignment {
+    WardAssignment {
+        assignment_id: row.get("assignment_id"),
+        ward_id: row.get("ward_id"),
+        ward_name: row.get("ward_name"),
+        user_id: row.get("user_id"),
+        full_name: row.get("full_name"),
+        effective_from: row.get("effective_from"),
+        effective_to: row.get("effective_to"),
+        delegated_from_user_id: row.get("delegated_from_user_id"),
+        delegated_from_name: row.get("delegated_from_name"),
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn get_ward_assignment_by_id(
+    assignment_id: i32,
+    client: &Client,
+) -> Result<Option<WardAssignment>, Error> {
+    let row = client
+        .query_opt(
+            "select a.assignment_id, a.ward_id, w.ward_name, a.user_id, u.full_name, a.effective_from, a.effective_to, a.delegated_from_user_id, du.full_name delegated_from_name, a.created_at
+            from ward_assignments a
+            join wards w on w.ward_id = a.ward_id
+            join users u on u.user_id = a.user_id
+            left join users du on du.user_id = a.delegated_from_user_id
+            where a.assignment_id = $1",
+            &[&assignment_id],
+        )
+        .await?;
+    Ok(row.as_ref().map(to_ward_assignment))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WardAssignmentRequest {
+    pub user_id: i32,
+    pub ward_id: i32,
+    // Defaults to today.
+    pub effective_from: Option<NaiveDate>,
+    pub effective_to: Option<NaiveDate>,
+}
+
+// Whether `user_id` already has a regular assignment for the ward that
+// overlaps the period.
+pub async fn has_overlapping_assignment(
+    user_id: i32,
+    ward_id: i32,
+    effective_from: NaiveDate,
+    effective_to: Option<NaiveDate>,
+    client: &Client,
+) -> Result<bool, Error> {
+    let row = client
+        .query_one(
+            "select exists (select 1 from ward_assignments
+                where user_id = $1 and ward_id = $2 and delegated_from_user_id is null
+                and (effective_to is null or effective_to >= $3)
+                and ($4::date is null or effective_from <= $4)) overlapping",
+            &[&user_id, &ward_id, &effective_from, &effective_to],
+        )
+        .await?;
+    Ok(row.get("overlapping"))
+}
+
+pub async fn add_ward_assignment(
+    user_id: i32,
+    ward_id: i32,
+    effective_from: NaiveDate,
+    effective_to: Option<NaiveDate>,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<i32, Error> {
+    let transaction = client.transaction().await?;
+    let assignment_id: i32 = transaction
+        .query_one(
+            "insert into ward_assignments (user_id, ward_id, effective_from, effective_to, created_by) values ($1, $2, $3, $4, $5) returning assignment_id",
+            &[&user_id, &ward_id, &effective_from, &effective_to, &context.actor_id],
+        )
+        .await?
+        .get("assignment_id");
+    audit::record(context, "create", &audit::WARD_ASSIGNMENT, assignment_id, None, &transaction).await?;
+    transaction.commit().await?;
+    Ok(assignment_id)
+}
+
+// Ends an assignment after `last_day`. One that would then end before it
+// starts never took effect and is removed. Cover handed out by the user for
+// the ward is cut short with it.
+pub async fn end_ward_assignment(
+    assignment: &WardAssignment,
+    last_day: NaiveDate,
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<(), Error> {
+    let transaction = client.transaction().await?;
+    let before = audit::snapshot(&audit::WARD_ASSIGNMENT, assignment.assignment_id, &transaction).await?;
+    let action = if last_day < assignment.effective_from {
+        transaction
+            .execute(
+                "delete from ward_assignments where assignment_id = $1",
+                &[&assignment.assignment_id],
+            )
+            .await?;
+        "delete"
+    } else {
+        transaction
+            .execute(
+                "update ward_assignments set effective_to = $2 where assignment_id = $1",
+                &[&assignment.assignment_id, &last_day],
+            )
+            .await?;
+        "update"
+    };
+    if assignment.delegated_from_user_id.is_none() {
+        transaction
+            .execute(
+                "delete from ward_assignments where delegated_from_user_id = $1 and ward_id = $2 and effective_from > $3",
+                &[&assignment.user_id, &assignment.ward_id, &last_day],
+            )
+            .await?;
+        transaction
+            .execute(
+                "update ward_assignments set effective_to = $3
+                where delegated_from_user_id = $1 and ward_id = $2 and (effective_to is null or effective_to > $3)",
+                &[&assignment.user_id, &assignment.ward_id, &last_day],
+            )
+            .await?;
+    }
+    audit::record(context, action, &audit::WARD_ASSIGNMENT, assignment.assignment_id, before, &transaction).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DelegationRequest {
+    pub from_user_id: i32,
+    pub to_user_id: i32,
+    // Defaults to every ward `from_user_id` covers on `effective_from`.
+    pub ward_ids: Option<Vec<i32>>,
+    pub effective_from: NaiveDate,
+    pub effective_to: NaiveDate,
+}
+
+// Wards `user_id` is regularly assigned to on `date`.
+pub async fn get_assigned_ward_ids(
+    user_id: i32,
+    date: NaiveDate,
+    client: &Client,
+) -> Result<Vec<i32>, Error> {
+    let rows = client
+        .query(
+            &format!(
+                "select distinct a.ward_id from ward_assignments a where a.user_id = $1 and a.delegated_from_user_id is null and {}",
+                active_on("a", "$2")
+            ),
+            &[&user_id, &date],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get("ward_id")).collect())
+}
+
+pub async fn add_delegation(
+    data: &DelegationRequest,
+    ward_ids: &[i32],
+    context: &AuditContext,
+    client: &mut Client,
+) -> Result<Vec<i32>, Error> {
+    let transaction = client.transaction().await?;
+    let mut assignment_ids = vec![];
+    for ward_id in ward_ids {
+        let row = transaction
+            .query_one(
+                "insert into ward_assignments (user_id, ward_id, effective_from, effective_to, delegated_from_user_id, created_by) values ($1, $2, $3, $4, $5, $6) returning assignment_id",
+                &[
+                    &data.to_user_id,
+                    &ward_id,
+                    &data.effective_from,
+                    &data.effective_to,
+                    &data.from_user_id,
+                    &context.actor_id,
+                ],
+            )
+            .await?;
+        let assignment_id: i32 = row.get("assignment_id");
+        audit::record(context, "create", &audit::WARD_ASSIGNMENT, assignment_id, None, &transaction).await?;
+        assignment_ids.push(assignment_id);
+    }
+    transaction.commit().await?;
+    Ok(assignment_ids)
+}
+
+// Makes `ward_ids` the user's current wards: assignments for other wards end
+// yesterday and missing wards are assigned from today. History is kept.
+pub async fn set_current_wards(
+    user_id: i32,
+    ward_ids: &[i32],
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    transaction
+        .execute(
+            "delete from ward_assignments where user_id = $1 and delegated_from_user_id is null and not (ward_id = any($2)) and effective_from >= CURRENT_DATE",
+            &[&user_id, &ward_ids],
+        )
+        .await?;
+    transaction
+        .execute(
+            &format!(
+                "update ward_assignments a set effective_to = CURRENT_DATE - 1 where a.user_id = $1 and a.delegated_from_user_id is null and not (a.ward_id = any($2)) and {}",
+                active_on("a", "CURRENT_DATE")
+            ),
+            &[&user_id, &ward_ids],
+        )
+        .await?;
+    transaction
+        .execute(
+            &format!(
+                "insert into ward_assignments (user_id, ward_id, effective_from)
+                select $1, n.ward_id, CURRENT_DATE from unnest($2::int[]) n(ward_id)
+                where not exists (select 1 from ward_assignments a where a.user_id = $1 and a.ward_id = n.ward_id and a.delegated_from_user_id is null and {})",
+                active_on("a", "CURRENT_DATE")
+            ),
+            &[&user_id, &ward_ids],
+        )
+        .await?;
+    Ok(())
+}
+
+// Ends everything a user covers when the user is deleted. The rows are marked
+// with the deletion time, the same as the user's, so a restore can reopen them.
+pub async fn end_user_assignments(user_id: i32, transaction: &Transaction<'_>) -> Result<(), Error> {
+    transaction
+        .execute(
+            "update ward_assignments set effective_to = effective_from - 1, deleted_at = CURRENT_TIMESTAMP
+            where user_id = $1 and effective_from >= CURRENT_DATE and deleted_at is null",
+            &[&user_id],
+        )
+        .await?;
+    transaction
+        .execute(
+            "update ward_assignments set effective_to = CURRENT_DATE - 1, deleted_at = CURRENT_TIMESTAMP
+            where user_id = $1 and (effective_to is null or effective_to >= CURRENT_DATE) and deleted_at is null",
+            &[&user_id],
+        )
+        .await?;
+    Ok(())
+}
+
+// Reopens what deleting the user ended: the wards they covered then become
+// theirs again from today. Delegations are not brought back.
+pub async fn restore_user_assignments(
+    user_id: i32,
+    deleted_at: NaiveDateTime,
+    transaction: &Transaction<'_>,
+) -> Result<(), Error> {
+    let ward_ids: Vec<i32> = transaction
+        .query(
+            "select distinct a.ward_id from ward_assignments a join wards w on w.ward_id = a.ward_id
+            where a.user_id = $1 and a.deleted_at = $2 and a.delegated_from_user_id is null and w.deleted_at is null",
+            &[&user_id, &deleted_at],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("ward_id"))
+        .collect();
+    set_current_wards(user_id, &ward_ids, transaction).await?;
+    transaction
+        .execute(
+            "update ward_assignments set deleted_at = null where user_id = $1 and deleted_at = $2",
+            &[&user_id, &deleted_at],
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TerritoryReportQuery {
+    pub from_date: NaiveDate,
+    pub to_date: NaiveDate,
+    pub ward_id: Option<i32>,
+}
+
+// Orders per ward and the user who covered the ward on the day each order was
+// placed. Orders in a ward nobody covered are reported without a user.
+#[derive(Debug, Serialize)]
+pub struct TerritoryReportRow {
+    pub ward_id: i32,
+    pub ward_name: String,
+    pub user_id: Option<i32>,
+    pub full_name: Option<String>,
+    pub order_count: i64,
+    pub total_amount: f64,
+}
+
+pub async fn get_territory_report(
+    query: &TerritoryReportQuery,
+    scope_user_ids: Option<&[i32]>,
+    client: &Client,
+) -> Result<Vec<TerritoryReportRow>, Error> {
+    // For every order, the regular owner of its ward on that day, replaced by
+    // whoever covered for them. When assignments overlap the latest one wins,
+    // so each order is counted for one user only.
+    let sql = format!(
+        "with order_owners as (
+            select distinct on (o.order_id) o.order_id, o.total_amount, s.ward_id, coalesce(d.user_id, a.user_id) user_id
+            from orders o
+            join shops s on s.shop_id = o.shop_id
+            left join ward_assignments a on a.ward_id = s.ward_id and a.delegated_from_user_id is null and {}
+            left join ward_assignments d on d.ward_id = s.ward_id and d.delegated_from_user_id = a.user_id and {}
+            where o.deleted_at is null and o.order_date::date between $1 and $2 and ($3::int is null or s.ward_id = $3)
+            order by o.order_id, a.effective_from desc, a.assignment_id desc, d.effective_from desc, d.assignment_id desc
+        )
+        select w.ward_id, w.ward_name, oo.user_id, u.full_name, count(oo.order_id) order_count, coalesce(sum(oo.total_amount), 0)::text total_amount
+        from order_owners oo
+        join wards w on w.ward_id = oo.ward_id
+        left join users u on u.user_id = oo.user_id
+        where $4::int[] is null or oo.user_id = any($4)
+        group by w.ward_id, w.ward_name, oo.user_id, u.full_name
+        order by w.ward_name, u.full_name",
+        active_on("a", "o.order_date::date"),
+        active_on("d", "o.order_date::date"),
+    );
+    let rows = client
+        .query(
+            &sql,
+            &[
+                &query.from_date,
+                &query.to_date,
+                &query.ward_id,
+                &scope_user_ids.map(|ids| ids.to_vec()),
+            ],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let total_amount: &str = row.get("total_amount");
+            TerritoryReportRow {
+                ward_id: row.get("ward_id"),
+                ward_name: row.get("ward_name"),
+                user_id: row.get("user_id"),
+                full_name: row.get("full_name"),
+                order_count: row.get("order_count"),
+                total_amount: total_amount.parse().unwrap_or(0.0),
+            }
+        })
+        .collect())
+}
@@ -1,3 +1,12 @@
+pub mod auth;
 pub mod common_struct;
+pub mod geo;
 pub mod jwt;
+pub mod keyring;
+pub mod password;
+pub mod permission;
+pub mod push;
+pub mod route;
+pub mod spreadsheet;
 pub mod sql;
+pub mod totp;
@@ -0,0 +1,371 @@
+use std::{env, fmt, marker::PhantomData, ops::Deref, sync::Arc};
+
+use actix_web::{
+    dev::Payload, http::StatusCode, web, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::{
+    models::{api_key, audit::AuditContext, role, user},
+    utils::{
+        common_struct::BaseResponse,
+        jwt::verify_token_and_get_claims,
+        permission::{self, Permission, READ_ALL},
+    },
+};
+
+// The user behind a request's bearer token. Taking this as a handler argument
+// makes the handler reject requests without a valid, unrevoked token.
+//
+// Handlers guarded by `Authorized<P>` also accept an `X-Api-Key`. The caller
+// is then the key, acting with its scopes on behalf of the admin who made it.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: i32,
+    pub role: String,
+    pub session_id: Option<i32>,
+    pub must_change_password: bool,
+    pub must_enroll_mfa: bool,
+    pub api_key_id: Option<i32>,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    MissingHeader,
+    InvalidHeader,
+    InvalidToken,
+    InvalidServiceKey,
+    InvalidApiKey,
+    RateLimited,
+    PasswordChangeRequired,
+    MfaEnrollmentRequired,
+    Forbidden,
+    Internal,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            AuthError::MissingHeader => "Authorization header missing",
+            AuthError::InvalidHeader => "Invalid Authorization header format",
+            AuthError::InvalidToken => "Invalid token",
+            AuthError::InvalidServiceKey => "Invalid service key",
+            AuthError::InvalidApiKey => "Invalid API key",
+            AuthError::RateLimited => "Too many requests!",
+            AuthError::PasswordChangeRequired => "Password change required!",
+            AuthError::MfaEnrollmentRequired => "Two-factor enrollment required!",
+            AuthError::Forbidden => "Forbidden!",
+            AuthError::Internal => "Something went wrong!",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Forbidden
+            | AuthError::PasswordChangeRequired
+            | AuthError::MfaEnrollmentRequired => StatusCode::FORBIDDEN,
+            AuthError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            AuthError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(BaseResponse {
+            code: self.status_code().as_u16(),
+            message: self.to_string(),
+        })
+    }
+}
+
+fn get_bearer_token(req: &HttpRequest) -> Result<String, AuthError> {
+    let value = req
+        .headers()
+        .get("Authorization")
+        .ok_or(AuthError::MissingHeader)?;
+    let parts: Vec<&str> = value.to_str().unwrap_or("").split_whitespace().collect();
+    if parts.len() == 2 && parts[0] == "Bearer" {
+        Ok(parts[1].to_string())
+    } else {
+        Err(AuthError::InvalidHeader)
+    }
+}
+
+async fn authenticate(req: HttpRequest) -> Result<AuthUser, AuthError> {
+    let token = get_bearer_token(&req)?;
+    let data = req
+        .app_data::<web::Data<Arc<Mutex<Client>>>>()
+        .ok_or(AuthError::Internal)?;
+    let client = data.lock().await;
+    let claims = verify_token_and_get_claims(&token, &client)
+        .await
+        .ok_or(AuthError::InvalidToken)?;
+
+    Ok(AuthUser {
+        user_id: claims.user_id,
+        role: claims.role,
+        session_id: claims.sid,
+        must_change_password: claims.pwd_change,
+        must_enroll_mfa: claims.mfa_setup,
+        api_key_id: None,
+        scopes: vec![],
+    })
+}
+
+async fn authenticate_api_key(key: &str, client: &Client) -> Result<AuthUser, AuthError> {
+    let prefix = api_key::parse_api_key_prefix(key).ok_or(AuthError::InvalidApiKey)?;
+    let found = api_key::get_active_api_key(prefix, client)
+        .await
+        .map_err(|err| {
+            println!("{:?}", err);
+            AuthError::Internal
+        })?
+        .ok_or(AuthError::InvalidApiKey)?;
+    let user_id = found.created_by.ok_or(AuthError::InvalidApiKey)?;
+    if !constant_time_eq(
+        api_key::hash_api_key(key).as_bytes(),
+        found.key_hash.as_bytes(),
+    ) {
+        return Err(AuthError::InvalidApiKey);
+    }
+
+    let recent = api_key::count_recent_requests(found.api_key_id, client)
+        .await
+        .map_err(|err| {
+            println!("{:?}", err);
+            AuthError::Internal
+        })?;
+    if recent >= found.rate_limit as i64 {
+        return Err(AuthError::RateLimited);
+    }
+
+    Ok(AuthUser {
+        user_id,
+        role: String::new(),
+        session_id: None,
+        must_change_password: false,
+        must_enroll_mfa: false,
+        api_key_id: Some(found.api_key_id),
+        scopes: found.scopes,
+    })
+}
+
+async fn check_permission(
+    user: &AuthUser,
+    resource: &str,
+    action: &str,
+    client: &Client,
+) -> Result<bool, tokio_postgres::Error> {
+    if user.api_key_id.is_some() {
+        return Ok(permission::scopes_allow(&user.scopes, resource, action));
+    }
+    role::has_permission(&user.role, resource, action, client).await
+}
+
+// Like `authenticate`, but turns away users who have to change their password
+// or set up two-factor authentication before doing anything else.
+async fn authenticate_active(req: HttpRequest) -> Result<AuthUser, AuthError> {
+    let user = authenticate(req).await?;
+    if user.must_change_password {
+        return Err(AuthError::PasswordChangeRequired);
+    }
+    if user.must_enroll_mfa {
+        return Err(AuthError::MfaEnrollmentRequired);
+    }
+    Ok(user)
+}
+
+impl FromRequest for AuthUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        Box::pin(authenticate_active(req.clone()))
+    }
+}
+
+// An `AuthUser` that may still have a pending password change or two-factor
+// enrollment. Only for the handlers such a user needs: changing the password,
+// enrolling and logging out.
+pub struct PendingAuthUser(pub AuthUser);
+
+impl Deref for PendingAuthUser {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.0
+    }
+}
+
+impl FromRequest for PendingAuthUser {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(req).await.map(PendingAuthUser) })
+    }
+}
+
+impl AuthUser {
+    // Whether the user's role (or the API key's scopes) grants `P`. Lookup
+    // failures count as a denial.
+    pub async fn can<P: Permission>(&self, client: &Client) -> bool {
+        self.has_permission(P::RESOURCE, P::ACTION, client).await
+    }
+
+    pub async fn has_permission(&self, resource: &str, action: &str, client: &Client) -> bool {
+        match check_permission(self, resource, action, client).await {
+            Ok(allowed) => allowed,
+            Err(err) => {
+                println!("{:?}", err);
+                false
+            }
+        }
+    }
+
+    // Who to record in the audit log for changes made in `req`. Requests are
+    // told apart by `X-Request-Id`, or a fresh id when the client sent none.
+    pub fn audit_context(&self, req: &HttpRequest) -> AuditContext {
+        let request_id = req
+            .headers()
+            .get("X-Request-Id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().chars().take(64).collect::<String>())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        AuditContext {
+            actor_id: self.user_id,
+            api_key_id: self.api_key_id,
+            request_id,
+        }
+    }
+
+    // Whose `resource` records the caller may see: everyone's with
+    // `<resource>:read_all` (`None`), otherwise their own and their team's.
+    pub async fn visible_user_ids(&self, resource: &str, client: &Client) -> Option<Vec<i32>> {
+        if self.has_permission(resource, READ_ALL, client).await {
+            return None;
+        }
+        match user::get_team_user_ids(self.user_id, client).await {
+            Ok(user_ids) => Some(user_ids),
+            Err(err) => {
+                println!("{:?}", err);
+                Some(vec![self.user_id])
+            }
+        }
+    }
+}
+
+// An `AuthUser` that holds the permission `P`; anyone else gets a 403.
+pub struct Authorized<P: Permission> {
+    pub user: AuthUser,
+    _permission: PhantomData<P>,
+}
+
+impl<P: Permission> Deref for Authorized<P> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.user
+    }
+}
+
+impl<P: Permission + 'static> FromRequest for Authorized<P> {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let api_key = req
+                .headers()
+                .get("X-Api-Key")
+                .map(|v| v.to_str().unwrap_or("").to_string());
+            let data = req
+                .app_data::<web::Data<Arc<Mutex<Client>>>>()
+                .ok_or(AuthError::Internal)?
+                .clone();
+            let user = match api_key {
+                Some(key) => authenticate_api_key(&key, &*data.lock().await).await?,
+                None => authenticate_active(req.clone()).await?,
+            };
+            let client = data.lock().await;
+            let allowed = check_permission(&user, P::RESOURCE, P::ACTION, &client)
+                .await
+                .map_err(|err| {
+                    println!("{:?}", err);
+                    AuthError::Internal
+                })?;
+            // Every call made with an API key is recorded, including refused
+            // ones; the log doubles as the key's rate limit window.
+            if let Some(api_key_id) = user.api_key_id {
+                if let Err(err) = api_key::log_request(
+                    api_key_id,
+                    req.method().as_str(),
+                    req.path(),
+                    allowed,
+                    &client,
+                )
+                .await
+                {
+                    println!("{:?}", err);
+                }
+            }
+            if !allowed {
+                return Err(AuthError::Forbidden);
+            }
+            Ok(Authorized {
+                user,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+// A trusted internal service, such as the real-time gateway, identified by the
+// `X-Service-Key` header matching `SERVICE_API_KEY`. With no key configured
+// every request is rejected.
+pub struct ServiceAuth;
+
+fn service_key_matches(given: &str) -> bool {
+    let expected = match env::var("SERVICE_API_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => return false,
+    };
+    constant_time_eq(
+        &Sha256::digest(given.as_bytes()),
+        &Sha256::digest(expected.as_bytes()),
+    )
+}
+
+// Compares without returning early, so that the time taken does not reveal
+// how much of a secret was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl FromRequest for ServiceAuth {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let given = req
+            .headers()
+            .get("X-Service-Key")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if service_key_matches(given) {
+            ready(Ok(ServiceAuth))
+        } else {
+            ready(Err(AuthError::InvalidServiceKey))
+        }
+    }
+}
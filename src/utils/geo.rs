@@ -0,0 +1,259 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// A ring of (longitude, latitude) points, in GeoJSON order.
+type Ring = Vec<(f64, f64)>;
+
+// The area of a GeoJSON `Polygon` or `MultiPolygon`. Each polygon is its outer
+// ring followed by its holes.
+pub struct Boundary {
+    polygons: Vec<Vec<Ring>>,
+}
+
+impl Boundary {
+    // Reads a GeoJSON geometry.
+    pub fn parse(geometry: &Value) -> Result<Boundary, String> {
+        let coordinates = geometry
+            .get("coordinates")
+            .ok_or_else(|| String::from("Boundary has no coordinates!"))?;
+        let polygons = match geometry.get("type").and_then(Value::as_str) {
+            Some("Polygon") => vec![parse_polygon(coordinates)?],
+            Some("MultiPolygon") => coordinates
+                .as_array()
+                .ok_or_else(|| String::from("Invalid MultiPolygon coordinates!"))?
+                .iter()
+                .map(parse_polygon)
+                .collect::<Result<_, _>>()?,
+            _ => return Err(String::from("Boundary must be a Polygon or MultiPolygon!")),
+        };
+        if polygons.is_empty() {
+            return Err(String::from("Boundary must not be empty!"));
+        }
+        Ok(Boundary { polygons })
+    }
+
+    // Points on an edge may fall either way.
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        self.polygons.iter().any(|rings| {
+            let (outer, holes) = rings.split_first().unwrap();
+            ring_contains(outer, longitude, latitude)
+                && !holes
+                    .iter()
+                    .any(|hole| ring_contains(hole, longitude, latitude))
+        })
+    }
+}
+
+fn parse_polygon(value: &Value) -> Result<Vec<Ring>, String> {
+    let rings: Vec<Ring> = value
+        .as_array()
+        .ok_or_else(|| String::from("Invalid Polygon coordinates!"))?
+        .iter()
+        .map(parse_ring)
+        .collect::<Result<_, _>>()?;
+    if rings.is_empty() {
+        return Err(String::from("Polygon must have an outer ring!"));
+    }
+    Ok(rings)
+}
+
+fn parse_ring(value: &Value) -> Result<Ring, String> {
+    let ring: Ring = value
+        .as_array()
+        .ok_or_else(|| String::from("Invalid ring!"))?
+        .iter()
+        .map(|point| match point.as_array().map(Vec::as_slice) {
+            Some([lng, lat, ..]) => match (lng.as_f64(), lat.as_f64()) {
+                (Some(lng), Some(lat))
+                    if (-180.0..=180.0).contains(&lng) && (-90.0..=90.0).contains(&lat) =>
+                {
+                    Ok((lng, lat))
+                }
+                _ => Err(format!("Invalid position: {}", point)),
+            },
+            _ => Err(format!("Invalid position: {}", point)),
+        })
+        .collect::<Result<_, _>>()?;
+    // GeoJSON rings repeat the first point at the end.
+    if ring.len() < 4 || ring.first() != ring.last() {
+        return Err(String::from(
+            "A ring needs at least 4 positions and must end where it starts!",
+        ));
+    }
+    Ok(ring)
+}
+
+// Even-odd ray casting; the planar approximation is fine at ward scale.
+fn ring_contains(ring: &Ring, x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let ((x1, y1), (x2, y2)) = (edge[0], edge[1]);
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// Mean earth radius, as used for great-circle distances.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// A (min_latitude, max_latitude, min_longitude, max_longitude) box holding
+// every point within `radius_m` of the centre, for an indexed prefilter.
+pub fn bounding_box(latitude: f64, longitude: f64, radius_m: f64) -> (f64, f64, f64, f64) {
+    let dlat = (radius_m / EARTH_RADIUS_M).to_degrees();
+    let min_lat = (latitude - dlat).max(-90.0);
+    let max_lat = (latitude + dlat).min(90.0);
+    // Longitude degrees shrink towards the poles; near one, take them all.
+    let cos = min_lat.to_radians().cos().min(max_lat.to_radians().cos());
+    let dlng = if cos > 0.01 { dlat / cos } else { 180.0 };
+    (
+        min_lat,
+        max_lat,
+        (longitude - dlng).max(-180.0),
+        (longitude + dlng).min(180.0),
+    )
+}
+
+// A GeoJSON FeatureCollection, as loaded by QGIS or Leaflet.
+#[derive(Serialize)]
+pub struct FeatureCollection<P> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pub features: Vec<Feature<P>>,
+}
+
+impl<P> FeatureCollection<P> {
+    pub fn new(features: Vec<Feature<P>>) -> Self {
+        FeatureCollection {
+            kind: "FeatureCollection",
+            features,
+        }
+    }
+}
+
+// A feature; the geometry is null where the location is unknown.
+#[derive(Serialize)]
+pub struct Feature<P> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    pub geometry: Option<Value>,
+    pub properties: P,
+}
+
+impl<P> Feature<P> {
+    pub fn new(geometry: Option<Value>, properties: P) -> Self {
+        Feature {
+            kind: "Feature",
+            geometry,
+            properties,
+        }
+    }
+}
+
+pub fn point(latitude: Option<f64>, longitude: Option<f64>) -> Option<Value> {
+    Some(json!({ "type": "Point", "coordinates": [longitude?, latitude?] }))
+}
+
+// Great-circle distance in metres between two (latitude, longitude) points.
+pub fn distance_m(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (from.0.to_radians(), to.0.to_radians());
+    let dlat = lat2 - lat1;
+    let dlng = (to.1 - from.1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Coordinates are (longitude, latitude), as in GeoJSON.
+    fn square(min: f64, max: f64) -> Value {
+        json!([[min, min], [max, min], [max, max], [min, max], [min, min]])
+    }
+
+    #[test]
+    fn contains_points_inside_a_square() {
+        let boundary = Boundary::parse(&json!({
+            "type": "Polygon",
+            "coordinates": [square(0.0, 10.0)],
+        }))
+        .unwrap();
+        assert!(boundary.contains(5.0, 5.0));
+        assert!(boundary.contains(0.5, 9.5));
+        assert!(!boundary.contains(5.0, 11.0));
+        assert!(!boundary.contains(-1.0, 5.0));
+    }
+
+    #[test]
+    fn leaves_out_holes() {
+        let boundary = Boundary::parse(&json!({
+            "type": "Polygon",
+            "coordinates": [square(0.0, 10.0), square(4.0, 6.0)],
+        }))
+        .unwrap();
+        assert!(boundary.contains(2.0, 2.0));
+        assert!(!boundary.contains(5.0, 5.0));
+        assert!(boundary.contains(5.0, 7.0));
+    }
+
+    #[test]
+    fn contains_points_in_any_part_of_a_multipolygon() {
+        let boundary = Boundary::parse(&json!({
+            "type": "MultiPolygon",
+            "coordinates": [[square(0.0, 1.0)], [square(5.0, 6.0)]],
+        }))
+        .unwrap();
+        assert!(boundary.contains(0.5, 0.5));
+        assert!(boundary.contains(5.5, 5.5));
+        assert!(!boundary.contains(3.0, 3.0));
+    }
+
+    #[test]
+    fn contains_points_inside_a_concave_ring() {
+        // An L shape; its inner corner is outside.
+        let boundary = Boundary::parse(&json!({
+            "type": "Polygon",
+            "coordinates": [[[0, 0], [4, 0], [4, 1], [1, 1], [1, 4], [0, 4], [0, 0]]],
+        }))
+        .unwrap();
+        assert!(boundary.contains(3.0, 0.5));
+        assert!(boundary.contains(0.5, 3.0));
+        assert!(!boundary.contains(3.0, 3.0));
+    }
+
+    #[test]
+    fn rejects_invalid_rings() {
+        let parse = |coordinates: Value| {
+            Boundary::parse(&json!({ "type": "Polygon", "coordinates": coordinates }))
+        };
+        // Too short.
+        assert!(parse(json!([[[0, 0], [1, 0], [0, 0]]])).is_err());
+        // Not closed.
+        assert!(parse(json!([[[0, 0], [1, 0], [1, 1], [0, 1]]])).is_err());
+        // Out of range.
+        assert!(parse(json!([[[0, 0], [200, 0], [1, 1], [0, 0]]])).is_err());
+        assert!(parse(json!([])).is_err());
+        assert!(Boundary::parse(&json!({ "type": "Point", "coordinates": [0, 0] })).is_err());
+    }
+
+    #[test]
+    fn bounding_box_holds_the_radius() {
+        let (min_lat, max_lat, min_lng, max_lng) = bounding_box(16.8, 96.15, 1000.0);
+        for point in [
+            (min_lat, 96.15),
+            (max_lat, 96.15),
+            (16.8, min_lng),
+            (16.8, max_lng),
+        ] {
+            assert!(distance_m((16.8, 96.15), point) >= 999.0);
+        }
+        assert!(max_lat - min_lat < 0.02);
+
+        // Near a pole every longitude is taken.
+        let (_, max_lat, min_lng, max_lng) = bounding_box(89.999, 0.0, 1000.0);
+        assert_eq!(max_lat, 90.0);
+        assert_eq!((min_lng, max_lng), (-180.0, 180.0));
+    }
+}
@@ -1,56 +1,235 @@
 use std::env;
 
 use jsonwebtoken::{
-    decode, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+    decode, decode_header, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
+    Validation,
 };
 use serde::{Deserialize, Serialize};
+use tokio_postgres::Client;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{
+    models::session,
+    utils::keyring::{keyring, SigningKey},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
+    // The user id. Legacy tokens carry "user_id,role,username" here instead.
     pub sub: String,
     pub exp: usize,
+    #[serde(default)]
+    pub iat: usize,
+    #[serde(default)]
+    pub jti: String,
+    #[serde(default)]
+    pub iss: String,
+    #[serde(default)]
+    pub aud: String,
+    #[serde(default)]
+    pub user_id: i32,
+    #[serde(default)]
+    pub role: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub ward_ids: Vec<i32>,
+    // Session id and generation, checked against `user_sessions` so that
+    // logout, revocation and refresh token rotation take effect immediately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gen: Option<i32>,
+    // Set while the user still has to replace a password an admin gave them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pwd_change: bool,
+    // Set while a user whose role requires two-factor authentication has not
+    // enrolled yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mfa_setup: bool,
+}
+
+impl Claims {
+    pub fn new(
+        user_id: i32,
+        role: &str,
+        username: &str,
+        ward_ids: Vec<i32>,
+        iat: usize,
+        exp: usize,
+    ) -> Self {
+        Claims {
+            sub: user_id.to_string(),
+            exp,
+            iat,
+            jti: uuid::Uuid::new_v4().to_string(),
+            iss: issuer(),
+            aud: audience(),
+            user_id,
+            role: role.to_string(),
+            username: username.to_string(),
+            ward_ids,
+            sid: None,
+            gen: None,
+            pwd_change: false,
+            mfa_setup: false,
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.sub.contains(',')
+    }
+
+    // Fills the typed fields from a legacy "user_id,role,username" `sub`.
+    fn parse_legacy_sub(mut self) -> Option<Self> {
+        let parsed_values: Vec<&str> = self.sub.split(',').collect();
+        if parsed_values.len() != 3 {
+            return None;
+        }
+        self.user_id = parsed_values[0].parse().ok()?;
+        self.role = parsed_values[1].to_string();
+        self.username = parsed_values[2].to_string();
+        Some(self)
+    }
+}
+
+pub fn issuer() -> String {
+    env::var("JWT_ISSUER").unwrap_or(String::from("distribution"))
+}
+
+pub fn audience() -> String {
+    env::var("JWT_AUDIENCE").unwrap_or(String::from("distribution-api"))
+}
+
+// Whether tokens with the old comma-joined `sub` are still accepted. Turn this
+// off once the deployed mobile apps have all moved to the new claims.
+fn accept_legacy_tokens() -> bool {
+    env::var("JWT_ACCEPT_LEGACY")
+        .map(|v| v != "false")
+        .unwrap_or(true)
+}
+
+// Lifetime of an access token in seconds (default 15 minutes).
+pub fn access_token_ttl() -> usize {
+    env::var("ACCESS_TOKEN_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(900)
+}
+
+// Lifetime of a refresh token (and its session) in seconds (default 30 days).
+pub fn refresh_token_ttl() -> i64 {
+    env::var("REFRESH_TOKEN_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600 * 24 * 30)
 }
 
+// Signs with the active key of the keyring, or with the shared HS256 secret
+// when no keys are configured.
 pub fn sign_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    encode(
-        &Header::default(),
-        claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-}
-
-// pub fn verify_token(token: &str) -> bool {
-//     let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-//     let validation = Validation::default();
-//     match decode::<Claims>(
-//         &token,
-//         &DecodingKey::from_secret(secret.as_ref()),
-//         &validation,
-//     ) {
-//         Ok(_) => true,   // Token is valid
-//         Err(_) => false, // Token is invalid
-//     }
-// }
-
-pub fn verify_token_and_get_sub(token: &str) -> Option<String> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-
-    let validation = Validation::new(Algorithm::HS256); // Assuming you're using HS256
-    match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &validation,
-    ) {
-        Ok(data) => Some(data.claims.sub),
+    let keyring = keyring();
+    match keyring.active_key() {
+        Some(SigningKey {
+            kid,
+            encoding: Some(encoding),
+            ..
+        }) => {
+            let mut header = Header::new(Algorithm::EdDSA);
+            header.kid = Some(kid.clone());
+            encode(&header, claims, encoding)
+        }
+        _ => {
+            let secret = keyring.hs_secret.as_ref().expect("JWT_SECRET must be set");
+            encode(
+                &Header::default(),
+                claims,
+                &EncodingKey::from_secret(secret.as_ref()),
+            )
+        }
+    }
+}
+
+// Checks the signature, expiry, issuer and audience only. Use
+// `verify_token_and_get_claims` for anything that grants access, since it also
+// checks the session.
+pub fn verify_token(token: &str) -> Option<Claims> {
+    let keyring = keyring();
+    let header = match decode_header(token) {
+        Ok(header) => header,
+        Err(err) => {
+            println!("Token is invalid: {:?}", err);
+            return None;
+        }
+    };
+    // Tokens from the keyring name their key. Anything else must be an HS256
+    // token from before the keyring, which is only accepted while
+    // `JWT_SECRET` is still set.
+    let (decoding_key, algorithm) = match &header.kid {
+        Some(kid) => match keyring.find(kid) {
+            Some(key) => (key.decoding.clone(), Algorithm::EdDSA),
+            None => {
+                println!("Token signed with unknown key {}", kid);
+                return None;
+            }
+        },
+        None => match &keyring.hs_secret {
+            Some(secret) => (DecodingKey::from_secret(secret.as_ref()), Algorithm::HS256),
+            None => {
+                println!("HS256 token rejected");
+                return None;
+            }
+        },
+    };
+
+    let mut validation = Validation::new(algorithm);
+    // Legacy tokens have no `aud`, so it is checked by hand below.
+    validation.validate_aud = false;
+    let claims = match decode::<Claims>(token, &decoding_key, &validation) {
+        Ok(data) => data.claims,
         Err(err) => {
             match *err.kind() {
                 ErrorKind::InvalidToken => println!("Token is invalid"), // Example logging for invalid token
                 ErrorKind::ExpiredSignature => println!("Token is expired"), // Example logging for expired token
                 _ => println!("Some other error: {:?}", err),                // Other errors
             }
-            None
+            return None;
         }
+    };
+
+    if claims.is_legacy() {
+        if !accept_legacy_tokens() {
+            println!("Legacy token rejected");
+            return None;
+        }
+        return claims.parse_legacy_sub();
+    }
+
+    if claims.iss != issuer() || claims.aud != audience() {
+        println!("Token has wrong issuer or audience");
+        return None;
+    }
+    if claims.sub != claims.user_id.to_string() {
+        println!("Token sub does not match user id");
+        return None;
+    }
+    Some(claims)
+}
+
+pub async fn verify_token_and_get_claims(token: &str, client: &Client) -> Option<Claims> {
+    let claims = verify_token(token)?;
+
+    let active = match (claims.sid, claims.gen) {
+        (Some(sid), Some(gen)) => {
+            session::is_session_active(sid, claims.user_id, gen, client).await
+        }
+        // Tokens issued before sessions existed carry no session id. They are
+        // still honoured until they expire, unless the user was deleted or
+        // their sessions were revoked after the token was issued.
+        _ => session::is_user_active(claims.user_id, claims.iat, client).await,
+    };
+    if !active {
+        println!("Token is revoked");
+        return None;
     }
-}
\ No newline at end of file
+    Some(claims)
+}
@@ -0,0 +1,144 @@
+use std::{env, fs, sync::OnceLock};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    },
+    DecodingKey, EncodingKey,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+// Ed25519 keys used to sign access tokens, loaded once from `JWT_KEYS_DIR`.
+//
+// Every `<kid>.pem` in the directory is a PKCS#8 private key and every
+// `<kid>.pub.pem` a public key that is only used to verify. Tokens are signed
+// with `JWT_ACTIVE_KID`, or the greatest kid with a private key. To rotate,
+// add the new key and make it active; once tokens signed with the old one have
+// expired, remove it (or keep only its public half for a while).
+pub struct SigningKey {
+    pub kid: String,
+    pub encoding: Option<EncodingKey>,
+    pub decoding: DecodingKey,
+    // Base64url encoded public key, as published in the JWKS.
+    pub x: String,
+}
+
+pub struct Keyring {
+    pub keys: Vec<SigningKey>,
+    pub active_kid: Option<String>,
+    // Shared HS256 secret from `JWT_SECRET`. Used to sign when no keys are
+    // configured, and to verify tokens that were issued before keys were.
+    pub hs_secret: Option<String>,
+}
+
+static KEYRING: OnceLock<Keyring> = OnceLock::new();
+
+pub fn keyring() -> &'static Keyring {
+    KEYRING.get_or_init(load_keyring)
+}
+
+fn load_keyring() -> Keyring {
+    let hs_secret = env::var("JWT_SECRET").ok().filter(|s| !s.is_empty());
+    let dir = match env::var("JWT_KEYS_DIR") {
+        Ok(dir) if !dir.is_empty() => dir,
+        _ => {
+            return Keyring {
+                keys: vec![],
+                active_kid: None,
+                hs_secret: Some(hs_secret.expect("JWT_SECRET or JWT_KEYS_DIR must be set")),
+            }
+        }
+    };
+
+    let mut keys: Vec<SigningKey> = vec![];
+    let entries = fs::read_dir(&dir).expect("JWT_KEYS_DIR must be a readable directory");
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let contents = fs::read(entry.path()).expect("Failed to read JWT key file");
+        if let Some(kid) = file_name.strip_suffix(".pub.pem") {
+            keys.push(load_public_key(kid, &contents));
+        } else if let Some(kid) = file_name.strip_suffix(".pem") {
+            keys.push(load_private_key(kid, &contents));
+        }
+    }
+    keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+
+    let active_kid = env::var("JWT_ACTIVE_KID").ok().or_else(|| {
+        keys.iter()
+            .rev()
+            .find(|k| k.encoding.is_some())
+            .map(|k| k.kid.clone())
+    });
+    if let Some(kid) = &active_kid {
+        if !keys.iter().any(|k| &k.kid == kid && k.encoding.is_some()) {
+            panic!("No private key for JWT_ACTIVE_KID {}", kid);
+        }
+    }
+
+    Keyring {
+        keys,
+        active_kid,
+        hs_secret,
+    }
+}
+
+fn load_private_key(kid: &str, contents: &[u8]) -> SigningKey {
+    let der = pem::parse(contents).expect("Invalid private key PEM");
+    let pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents())
+        .expect("Private key must be a PKCS#8 Ed25519 key");
+    let public_key = pair.public_key().as_ref();
+    SigningKey {
+        kid: kid.to_string(),
+        encoding: Some(EncodingKey::from_ed_der(der.contents())),
+        decoding: DecodingKey::from_ed_der(public_key),
+        x: URL_SAFE_NO_PAD.encode(public_key),
+    }
+}
+
+fn load_public_key(kid: &str, contents: &[u8]) -> SigningKey {
+    let der = pem::parse(contents).expect("Invalid public key PEM");
+    // An Ed25519 SubjectPublicKeyInfo ends with the 32 byte raw key.
+    let der = der.contents();
+    let public_key = &der[der.len().saturating_sub(32)..];
+    SigningKey {
+        kid: kid.to_string(),
+        encoding: None,
+        decoding: DecodingKey::from_ed_der(public_key),
+        x: URL_SAFE_NO_PAD.encode(public_key),
+    }
+}
+
+impl Keyring {
+    pub fn active_key(&self) -> Option<&SigningKey> {
+        let kid = self.active_kid.as_ref()?;
+        self.keys.iter().find(|k| &k.kid == kid)
+    }
+
+    pub fn find(&self, kid: &str) -> Option<&SigningKey> {
+        self.keys.iter().find(|k| k.kid == kid)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .keys
+                .iter()
+                .map(|k| Jwk {
+                    common: CommonParameters {
+                        public_key_use: Some(PublicKeyUse::Signature),
+                        key_algorithm: Some(KeyAlgorithm::EdDSA),
+                        key_id: Some(k.kid.clone()),
+                        ..Default::default()
+                    },
+                    algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                        key_type: OctetKeyPairType::OctetKeyPair,
+                        curve: EllipticCurve::Ed25519,
+                        x: k.x.clone(),
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
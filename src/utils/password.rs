@@ -0,0 +1,84 @@
+use std::env;
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+// Password rules, configured through the environment:
+// PASSWORD_MIN_LENGTH (default 8) and PASSWORD_REQUIRE_UPPERCASE,
+// PASSWORD_REQUIRE_LOWERCASE, PASSWORD_REQUIRE_DIGIT, PASSWORD_REQUIRE_SYMBOL
+// (each "true" or "false", digits are required by default).
+fn min_length() -> usize {
+    env::var("PASSWORD_MIN_LENGTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8)
+}
+
+fn required(name: &str, default: bool) -> bool {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+// Returns the message to show the user if `password` breaks the policy.
+pub fn validate_password(password: &str) -> Result<(), String> {
+    let min_length = min_length();
+    if password.chars().count() < min_length {
+        return Err(format!(
+            "Password must be at least {} characters long!",
+            min_length
+        ));
+    }
+    if required("PASSWORD_REQUIRE_UPPERCASE", false) && !password.chars().any(char::is_uppercase) {
+        return Err(String::from("Password must contain an uppercase letter!"));
+    }
+    if required("PASSWORD_REQUIRE_LOWERCASE", false) && !password.chars().any(char::is_lowercase) {
+        return Err(String::from("Password must contain a lowercase letter!"));
+    }
+    if required("PASSWORD_REQUIRE_DIGIT", true) && !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(String::from("Password must contain a digit!"));
+    }
+    if required("PASSWORD_REQUIRE_SYMBOL", false)
+        && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace())
+    {
+        return Err(String::from("Password must contain a symbol!"));
+    }
+    Ok(())
+}
+
+const LOWERCASE: &[u8] = b"abcdefghijkmnpqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"23456789";
+const SYMBOLS: &[u8] = b"!#$%*?@";
+
+// A uniformly random index below `n`.
+fn random_index(rng: &SystemRandom, n: usize) -> usize {
+    let limit = u32::MAX - u32::MAX % n as u32;
+    loop {
+        let mut bytes = [0u8; 4];
+        rng.fill(&mut bytes).expect("Failed to generate random bytes");
+        let value = u32::from_le_bytes(bytes);
+        if value < limit {
+            return (value % n as u32) as usize;
+        }
+    }
+}
+
+// A random password that passes `validate_password` whatever the rules are,
+// for accounts an admin creates without picking one. Look-alike characters
+// are left out since it is read off a screen.
+pub fn generate_password() -> String {
+    let rng = SystemRandom::new();
+    let alphabet = [LOWERCASE, UPPERCASE, DIGITS].concat();
+    let mut chars: Vec<u8> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS]
+        .iter()
+        .map(|set| set[random_index(&rng, set.len())])
+        .collect();
+    while chars.len() < min_length().max(12) {
+        chars.push(alphabet[random_index(&rng, alphabet.len())]);
+    }
+    for i in (1..chars.len()).rev() {
+        chars.swap(i, random_index(&rng, i + 1));
+    }
+    String::from_utf8(chars).unwrap()
+}
@@ -0,0 +1,117 @@
+// Compile-time names for the rows in the `permissions` table. A handler that
+// takes `Authorized<P>` only runs if the caller's role has been granted the
+// permission `P` in `role_permissions`.
+pub trait Permission {
+    const RESOURCE: &'static str;
+    const ACTION: &'static str;
+}
+
+macro_rules! permission {
+    ($name:ident, $resource:expr, $action:expr) => {
+        pub struct $name;
+
+        impl Permission for $name {
+            const RESOURCE: &'static str = $resource;
+            const ACTION: &'static str = $action;
+        }
+    };
+}
+
+macro_rules! crud_permissions {
+    ($resource:expr, $read:ident, $create:ident, $update:ident, $delete:ident) => {
+        permission!($read, $resource, "read");
+        permission!($create, $resource, "create");
+        permission!($update, $resource, "update");
+        permission!($delete, $resource, "delete");
+    };
+}
+
+crud_permissions!("users", UserRead, UserCreate, UserUpdate, UserDelete);
+crud_permissions!("roles", RoleRead, RoleCreate, RoleUpdate, RoleDelete);
+crud_permissions!("brands", BrandRead, BrandCreate, BrandUpdate, BrandDelete);
+crud_permissions!("categories", CategoryRead, CategoryCreate, CategoryUpdate, CategoryDelete);
+crud_permissions!("products", ProductRead, ProductCreate, ProductUpdate, ProductDelete);
+crud_permissions!("prices", PriceRead, PriceCreate, PriceUpdate, PriceDelete);
+crud_permissions!("discounts", DiscountRead, DiscountCreate, DiscountUpdate, DiscountDelete);
+crud_permissions!("shops", ShopRead, ShopCreate, ShopUpdate, ShopDelete);
+crud_permissions!("regions", RegionRead, RegionCreate, RegionUpdate, RegionDelete);
+crud_permissions!("townships", TownshipRead, TownshipCreate, TownshipUpdate, TownshipDelete);
+crud_permissions!("wards", WardRead, WardCreate, WardUpdate, WardDelete);
+
+permission!(OrderRead, "orders", "read");
+permission!(OrderCreate, "orders", "create");
+permission!(OrderUpdate, "orders", "update");
+permission!(OrderApprove, "orders", "approve");
+
+permission!(PaymentRead, "payments", "read");
+permission!(PaymentCreate, "payments", "create");
+permission!(PaymentDelete, "payments", "delete");
+
+permission!(ReceivableRead, "receivables", "read");
+permission!(ReceivableUpdate, "receivables", "update");
+
+permission!(NotificationCreate, "notifications", "create");
+
+permission!(TerritoryRead, "territories", "read");
+permission!(TerritoryUpdate, "territories", "update");
+
+permission!(ProposalRead, "shop_proposals", "read");
+permission!(ProposalCreate, "shop_proposals", "create");
+permission!(ProposalReview, "shop_proposals", "review");
+
+permission!(VisitRead, "visits", "read");
+permission!(VisitCreate, "visits", "create");
+
+permission!(HolidayRead, "holidays", "read");
+permission!(HolidayCreate, "holidays", "create");
+permission!(HolidayDelete, "holidays", "delete");
+
+permission!(ApiKeyRead, "api_keys", "read");
+permission!(ApiKeyCreate, "api_keys", "create");
+permission!(ApiKeyDelete, "api_keys", "delete");
+
+permission!(AuditRead, "audit", "read");
+
+permission!(TrashRead, "trash", "read");
+permission!(TrashRestore, "trash", "restore");
+permission!(TrashPurge, "trash", "purge");
+
+// Permissions that are checked inside a handler rather than by the extractor,
+// because they widen what a request returns instead of gating it.
+pub const ORDERS: &str = "orders";
+pub const SHOPS: &str = "shops";
+pub const SHOP_PROPOSALS: &str = "shop_proposals";
+pub const VISITS: &str = "visits";
+pub const READ_ALL: &str = "read_all";
+
+// What each API key scope allows, as (resource, action) pairs. API keys are
+// checked against this list instead of `role_permissions`.
+pub const API_KEY_SCOPES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "catalog:read",
+        &[
+            ("brands", "read"),
+            ("categories", "read"),
+            ("products", "read"),
+            ("prices", "read"),
+            ("discounts", "read"),
+            ("shops", "read"),
+            ("shops", READ_ALL),
+            ("regions", "read"),
+            ("townships", "read"),
+            ("wards", "read"),
+        ],
+    ),
+    ("orders:read", &[("orders", "read"), ("orders", READ_ALL)]),
+    ("stock:write", &[("prices", "read"), ("prices", "update")]),
+];
+
+pub fn is_known_scope(scope: &str) -> bool {
+    API_KEY_SCOPES.iter().any(|(name, _)| *name == scope)
+}
+
+pub fn scopes_allow(scopes: &[String], resource: &str, action: &str) -> bool {
+    API_KEY_SCOPES.iter().any(|(name, grants)| {
+        scopes.iter().any(|s| s == name) && grants.contains(&(resource, action))
+    })
+}
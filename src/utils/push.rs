@@ -0,0 +1,298 @@
+use std::{env, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::models::{device, discount};
+
+// Push notifications through FCM's HTTP API. `FIREBASE_FCM_URL` is the send
+// endpoint and `FIREBASE_FCM_AUTH` the value of the Authorization header
+// (`key=<server key>`). Pointing the URL at a local stub is enough to test
+// without Firebase; with no URL set, pushes are only logged.
+#[derive(Debug, Clone, Serialize)]
+pub struct PushMessage {
+    pub title: String,
+    pub body: String,
+    // Extra key/value pairs for the app, e.g. which order to open.
+    pub data: Value,
+}
+
+#[derive(Serialize)]
+struct FcmNotification<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct FcmRequest<'a> {
+    registration_ids: &'a [String],
+    notification: FcmNotification<'a>,
+    data: &'a Value,
+}
+
+#[derive(Deserialize)]
+struct FcmResult {
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FcmResponse {
+    #[serde(default)]
+    results: Vec<FcmResult>,
+}
+
+// FCM accepts at most this many registration ids per request.
+const MAX_TOKENS_PER_REQUEST: usize = 1000;
+
+// Sends `message` to every token and returns the tokens FCM says are gone, so
+// the caller can forget them.
+pub async fn send(tokens: &[String], message: &PushMessage) -> Result<Vec<String>, reqwest::Error> {
+    let url = match env::var("FIREBASE_FCM_URL") {
+        Ok(url) if !url.is_empty() => url,
+        _ => {
+            println!("FIREBASE_FCM_URL not set, skipping push: {}", message.title);
+            return Ok(vec![]);
+        }
+    };
+    let auth = env::var("FIREBASE_FCM_AUTH").unwrap_or_default();
+    send_to(&url, &auth, tokens, message).await
+}
+
+async fn send_to(
+    url: &str,
+    auth: &str,
+    tokens: &[String],
+    message: &PushMessage,
+) -> Result<Vec<String>, reqwest::Error> {
+    let http = reqwest::Client::new();
+
+    let mut stale = vec![];
+    for chunk in tokens.chunks(MAX_TOKENS_PER_REQUEST) {
+        let response: FcmResponse = http
+            .post(url)
+            .header("Authorization", auth)
+            .json(&FcmRequest {
+                registration_ids: chunk,
+                notification: FcmNotification {
+                    title: &message.title,
+                    body: &message.body,
+                },
+                data: &message.data,
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // Results come back in the same order as the registration ids.
+        for (token, result) in chunk.iter().zip(response.results) {
+            if matches!(
+                result.error.as_deref(),
+                Some("NotRegistered") | Some("InvalidRegistration")
+            ) {
+                stale.push(token.clone());
+            }
+        }
+    }
+    Ok(stale)
+}
+
+// Pushes `message` to all devices of the given users in the background, so
+// the request that caused it neither waits for FCM nor fails with it.
+pub fn notify_users(data: Arc<Mutex<Client>>, user_ids: Vec<i32>, message: PushMessage) {
+    if user_ids.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let tokens = {
+            let client = data.lock().await;
+            match device::get_tokens_for_users(&user_ids, &client).await {
+                Ok(tokens) => tokens,
+                Err(err) => {
+                    println!("Error reading device tokens: {:?}", err);
+                    return;
+                }
+            }
+        };
+        if tokens.is_empty() {
+            return;
+        }
+        match send(&tokens, &message).await {
+            Ok(stale) if !stale.is_empty() => {
+                let client = data.lock().await;
+                if let Err(err) = device::delete_tokens(&stale, &client).await {
+                    println!("Error deleting stale device tokens: {:?}", err);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => eprintln!("Push sending error: {}", err),
+        }
+    });
+}
+
+// Checks every `DISCOUNT_NOTIFY_INTERVAL` seconds (default 300) for discounts
+// that have started and tells the distributors whose shops they apply to.
+pub async fn run_discount_notifier(data: Arc<Mutex<Client>>) {
+    let seconds = env::var("DISCOUNT_NOTIFY_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(300);
+    let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+    loop {
+        interval.tick().await;
+        let client = data.lock().await;
+        let discounts = match discount::get_started_discounts(&client).await {
+            Ok(discounts) => discounts,
+            Err(err) => {
+                println!("Error reading started discounts: {:?}", err);
+                continue;
+            }
+        };
+        for d in discounts {
+            if let Err(err) = discount::mark_discount_notified(d.discount_id, &client).await {
+                println!("Error marking discount notified: {:?}", err);
+                continue;
+            }
+            notify_users(
+                data.clone(),
+                d.user_ids,
+                PushMessage {
+                    title: String::from("New discount"),
+                    body: format!("{} has started.", d.discount_name),
+                    data: serde_json::json!({
+                        "type": "discount",
+                        "discount_id": d.discount_id,
+                    }),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::Mutex as StdMutex};
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    // Requests the stub received, with their Authorization header.
+    type Received = Arc<StdMutex<Vec<(String, Value)>>>;
+
+    // A local stand-in for FCM. Tokens starting with `gone` or `bad` come back
+    // as `NotRegistered` and `InvalidRegistration`.
+    fn start_stub() -> (String, Received) {
+        let received: Received = Arc::default();
+        let state = received.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/fcm/send", listener.local_addr().unwrap());
+        let server = HttpServer::new(move || {
+            let state = state.clone();
+            App::new().route(
+                "/fcm/send",
+                web::post().to(move |req: HttpRequest, body: web::Json<Value>| {
+                    let state = state.clone();
+                    async move {
+                        let auth = req
+                            .headers()
+                            .get("Authorization")
+                            .and_then(|v| v.to_str().ok())
+                            .unwrap_or_default()
+                            .to_string();
+                        let results: Vec<Value> = body["registration_ids"]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|token| match token.as_str().unwrap() {
+                                t if t.starts_with("gone") => json!({ "error": "NotRegistered" }),
+                                t if t.starts_with("bad") => {
+                                    json!({ "error": "InvalidRegistration" })
+                                }
+                                _ => json!({ "message_id": "1" }),
+                            })
+                            .collect();
+                        state.lock().unwrap().push((auth, body.into_inner()));
+                        HttpResponse::Ok().json(json!({ "results": results }))
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+        (url, received)
+    }
+
+    fn message() -> PushMessage {
+        PushMessage {
+            title: String::from("Order updated"),
+            body: String::from("Order #7 is now Delivered."),
+            data: json!({ "type": "order_status", "order_id": 7 }),
+        }
+    }
+
+    fn tokens(prefix: &str, count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("{prefix}-{i}")).collect()
+    }
+
+    #[actix_web::test]
+    async fn sends_fcm_payload_through_configured_url() {
+        let (url, received) = start_stub();
+        env::set_var("FIREBASE_FCM_URL", &url);
+        env::set_var("FIREBASE_FCM_AUTH", "key=test");
+
+        let stale = send(&tokens("ok", 2), &message()).await.unwrap();
+
+        assert!(stale.is_empty());
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (auth, body) = &received[0];
+        assert_eq!(auth, "key=test");
+        assert_eq!(
+            body,
+            &json!({
+                "registration_ids": ["ok-0", "ok-1"],
+                "notification": {
+                    "title": "Order updated",
+                    "body": "Order #7 is now Delivered.",
+                },
+                "data": { "type": "order_status", "order_id": 7 },
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn splits_tokens_into_batches() {
+        let (url, received) = start_stub();
+        let all = tokens("ok", MAX_TOKENS_PER_REQUEST * 2 + 1);
+
+        send_to(&url, "", &all, &message()).await.unwrap();
+
+        let sizes: Vec<usize> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, body)| body["registration_ids"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(sizes, vec![MAX_TOKENS_PER_REQUEST, MAX_TOKENS_PER_REQUEST, 1]);
+    }
+
+    #[actix_web::test]
+    async fn reports_unregistered_and_invalid_tokens_as_stale() {
+        let (url, _) = start_stub();
+        let mut all = tokens("ok", MAX_TOKENS_PER_REQUEST);
+        all.push(String::from("gone-late"));
+        all.insert(1, String::from("gone-early"));
+        all.insert(3, String::from("bad-token"));
+
+        let stale = send_to(&url, "", &all, &message()).await.unwrap();
+
+        assert_eq!(stale, vec!["gone-early", "bad-token", "gone-late"]);
+    }
+}
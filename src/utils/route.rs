@@ -0,0 +1,154 @@
+use crate::utils::geo::distance_m;
+
+// Rounds of 2-opt improvement before the route is taken as it is.
+const MAX_TWO_OPT_ROUNDS: usize = 50;
+
+// A visiting order for `stops`, (latitude, longitude) points, as indexes into
+// it. The route starts at `start` when given, otherwise at whichever stop makes
+// it shortest, and does not return. It is built nearest neighbour first, then
+// shortened by 2-opt, reversing stretches of it while that makes it shorter.
+pub fn plan_route(start: Option<(f64, f64)>, stops: &[(f64, f64)]) -> Vec<usize> {
+    if stops.is_empty() {
+        return vec![];
+    }
+    // Points of the path; with a start location it is point 0 and the stops
+    // are shifted by one.
+    let points: Vec<(f64, f64)> = start.into_iter().chain(stops.iter().copied()).collect();
+    let offset = points.len() - stops.len();
+    let n = points.len();
+    let distances: Vec<Vec<f64>> = points
+        .iter()
+        .map(|&a| points.iter().map(|&b| distance_m(a, b)).collect())
+        .collect();
+
+    let mut path = vec![0];
+    let mut visited = vec![false; n];
+    visited[0] = true;
+    while path.len() < n {
+        let last = *path.last().unwrap();
+        let next = (0..n)
+            .filter(|&i| !visited[i])
+            .min_by(|&a, &b| distances[last][a].total_cmp(&distances[last][b]))
+            .unwrap();
+        visited[next] = true;
+        path.push(next);
+    }
+
+    // A start location stays put, otherwise the first stop may move too. With
+    // an open path, reversing a stretch that runs to either end only changes
+    // the edge on its other side.
+    let first = offset;
+    for _ in 0..MAX_TWO_OPT_ROUNDS {
+        let mut improved = false;
+        for i in first..n.saturating_sub(1) {
+            for k in i + 1..n {
+                let (b, c) = (path[i], path[k]);
+                let mut delta = 0.0;
+                if i > 0 {
+                    let a = path[i - 1];
+                    delta += distances[a][c] - distances[a][b];
+                }
+                if k + 1 < n {
+                    let d = path[k + 1];
+                    delta += distances[b][d] - distances[c][d];
+                }
+                if delta < -1e-6 {
+                    path[i..=k].reverse();
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    path.into_iter()
+        .filter(|&p| p >= offset)
+        .map(|p| p - offset)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Points a few hundred metres apart near the equator, where distances
+    // are close to those on a flat grid.
+    fn point(x: i32, y: i32) -> (f64, f64) {
+        (x as f64 * 0.001, y as f64 * 0.001)
+    }
+
+    fn length(start: Option<(f64, f64)>, stops: &[(f64, f64)], order: &[usize]) -> f64 {
+        let path: Vec<(f64, f64)> = start
+            .into_iter()
+            .chain(order.iter().map(|&i| stops[i]))
+            .collect();
+        path.windows(2).map(|w| distance_m(w[0], w[1])).sum()
+    }
+
+    // The shortest length over every order of the stops.
+    fn shortest(start: Option<(f64, f64)>, stops: &[(f64, f64)]) -> f64 {
+        fn search(
+            start: Option<(f64, f64)>,
+            stops: &[(f64, f64)],
+            order: &mut Vec<usize>,
+            best: &mut f64,
+        ) {
+            if order.len() == stops.len() {
+                *best = best.min(length(start, stops, order));
+                return;
+            }
+            for i in 0..stops.len() {
+                if !order.contains(&i) {
+                    order.push(i);
+                    search(start, stops, order, best);
+                    order.pop();
+                }
+            }
+        }
+        let mut best = f64::INFINITY;
+        search(start, stops, &mut vec![], &mut best);
+        best
+    }
+
+    #[test]
+    fn plans_nothing_without_stops() {
+        assert!(plan_route(Some(point(0, 0)), &[]).is_empty());
+        assert!(plan_route(None, &[]).is_empty());
+    }
+
+    #[test]
+    fn visits_nearest_stop_next() {
+        let stops = [point(0, 10), point(0, 2), point(0, 5)];
+        assert_eq!(plan_route(Some(point(0, 0)), &stops), vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn moves_first_stop_without_start() {
+        // Starting at the first stop goes back and forth along the line.
+        let stops = [point(0, 4), point(0, 0), point(0, 10)];
+        assert_eq!(plan_route(None, &stops), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn untangles_nearest_neighbour_route() {
+        // Nearest neighbour leaves (8, 0) for later and has to come back
+        // for it.
+        let start = Some(point(0, 0));
+        let stops = [
+            point(0, 4),
+            point(0, 1),
+            point(1, 9),
+            point(8, 0),
+            point(3, 6),
+        ];
+        let order = plan_route(start, &stops);
+
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+        assert!(length(start, &stops, &[1, 0, 4, 2, 3]) > length(start, &stops, &order) + 100.0);
+        assert!((length(start, &stops, &order) - shortest(start, &stops)).abs() < 1e-6);
+    }
+}
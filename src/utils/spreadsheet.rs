@@ -0,0 +1,47 @@
+use std::io::Cursor;
+
+use calamine::{open_workbook_from_rs, Reader, Xlsx};
+
+// Reads an uploaded CSV or XLSX file, told apart by its name, into rows of
+// trimmed cell text. Only the first sheet of a workbook is read.
+pub fn read_rows(filename: &str, bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "csv" => read_csv(bytes),
+        "xlsx" => read_xlsx(bytes),
+        _ => Err(String::from("File must be a .csv or .xlsx file!")),
+    }
+}
+
+fn read_csv(bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(bytes);
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV file: {}", e))?;
+        rows.push(record.iter().map(|cell| cell.trim().to_string()).collect());
+    }
+    Ok(rows)
+}
+
+fn read_xlsx(bytes: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(bytes))
+        .map_err(|e| format!("Invalid XLSX file: {}", e))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| String::from("The workbook has no sheets!"))?
+        .map_err(|e| format!("Invalid XLSX file: {}", e))?;
+    Ok(range
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.to_string().trim().to_string())
+                .collect()
+        })
+        .collect())
+}
@@ -0,0 +1,205 @@
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+
+// Time-based one-time passwords (RFC 6238) as used by authenticator apps:
+// HMAC-SHA1, 6 digits, 30 second steps.
+const STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("Failed to generate random bytes");
+    bytes
+}
+
+// A new base32 encoded 160 bit secret.
+pub fn generate_secret() -> String {
+    base32_encode(&random_bytes::<20>())
+}
+
+// A recovery code like `K7QF-2M9X-TB4C`.
+pub fn generate_recovery_code() -> String {
+    let code = base32_encode(&random_bytes::<8>());
+    format!("{}-{}-{}", &code[0..4], &code[4..8], &code[8..12])
+}
+
+pub fn provisioning_uri(secret: &str, username: &str) -> String {
+    let issuer = env::var("MFA_ISSUER").unwrap_or(String::from("Distribution"));
+    format!(
+        "otpauth://totp/{issuer}:{username}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        issuer = url_encode(&issuer),
+        username = url_encode(username),
+    )
+}
+
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn code_at(key: &hmac::Key, step: u64) -> u32 {
+    let tag = hmac::sign(key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+pub fn current_step() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+        / STEP_SECONDS
+}
+
+// Returns the time step `code` belongs to, allowing one step of clock drift
+// either way. The caller has to make sure the same step is not used twice.
+pub fn verify_code(secret: &str, code: &str) -> Option<u64> {
+    verify_code_at(secret, code, current_step())
+}
+
+fn verify_code_at(secret: &str, code: &str, now: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = hmac::Key::new(
+        hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        &base32_decode(secret)?,
+    );
+    [now - 1, now, now + 1]
+        .into_iter()
+        .find(|step| code_at(&key, *step) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648 section 10. Padding is left off when encoding.
+    const BASE32_VECTORS: &[(&str, &str)] = &[
+        ("", ""),
+        ("f", "MY"),
+        ("fo", "MZXQ"),
+        ("foo", "MZXW6"),
+        ("foob", "MZXW6YQ"),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI"),
+    ];
+
+    // RFC 6238 appendix B for SHA1, cut down to our 6 digits.
+    const SECRET: &[u8] = b"12345678901234567890";
+    const TOTP_VECTORS: &[(u64, &str)] = &[
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ];
+
+    #[test]
+    fn encodes_base32() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(base32_encode(plain.as_bytes()), *encoded);
+        }
+    }
+
+    #[test]
+    fn decodes_base32_with_or_without_padding() {
+        for (plain, encoded) in BASE32_VECTORS {
+            assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+        }
+        assert_eq!(base32_decode("MZXW6YQ=").unwrap(), b"foob");
+        assert_eq!(base32_decode("mzxw6ytboi").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW1"), None);
+    }
+
+    #[test]
+    fn generates_rfc_6238_codes() {
+        let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, SECRET);
+        for (time, code) in TOTP_VECTORS {
+            assert_eq!(format!("{:06}", code_at(&key, time / STEP_SECONDS)), *code);
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_drift_either_way() {
+        let secret = base32_encode(SECRET);
+        let step = 1111111109 / STEP_SECONDS;
+        assert_eq!(verify_code_at(&secret, "081804", step), Some(step));
+        assert_eq!(verify_code_at(&secret, "081804", step - 1), Some(step));
+        assert_eq!(verify_code_at(&secret, "081804", step + 1), Some(step));
+        assert_eq!(verify_code_at(&secret, "081804", step - 2), None);
+        assert_eq!(verify_code_at(&secret, "081804", step + 2), None);
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        let secret = base32_encode(SECRET);
+        let step = 1111111109 / STEP_SECONDS;
+        assert_eq!(verify_code_at(&secret, " 081804 ", step), Some(step));
+        assert_eq!(verify_code_at(&secret, "81804", step), None);
+        assert_eq!(verify_code_at(&secret, "08180a", step), None);
+    }
+}